@@ -0,0 +1,264 @@
+//! Synchronous master for code that doesn't run inside Tokio.
+//!
+//! ```no_run
+//! use modbus::client::blocking::BlockingClient;
+//!
+//! let mut client = BlockingClient::connect_tcp("127.0.0.1:502").unwrap();
+//! let registers = client.read_holding_registers(0x11, 0x6B, 3).unwrap();
+//! println!("{:?}", registers);
+//! ```
+use crate::frame::prelude::*;
+use crate::transport::error::ClientError;
+use crate::transport::tcp::client::TcpClient;
+use std::io;
+use std::net::ToSocketAddrs;
+use std::time::Duration;
+use tokio::runtime::{Builder, Runtime};
+
+/// Blocking wrapper over the async master. Owns a current-thread runtime, so
+/// it must not be used from async code
+pub struct BlockingClient {
+    runtime: Runtime,
+    client: TcpClient,
+}
+
+impl BlockingClient {
+    pub fn connect_tcp(addr: impl ToSocketAddrs) -> io::Result<BlockingClient> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        let addr: Vec<_> = addr.to_socket_addrs()?.collect();
+        let client = runtime.block_on(TcpClient::connect(addr.as_slice()))?;
+        Ok(BlockingClient { runtime, client })
+    }
+
+    /// time to wait for the response. 1s by default
+    pub fn with_timeout(mut self, timeout: Duration) -> BlockingClient {
+        self.client = self.client.with_timeout(timeout);
+        self
+    }
+
+    /// Send any request and wait for the response
+    pub fn request(&mut self, slave: u8, pdu: RequestPdu) -> Result<ResponsePdu, ClientError> {
+        self.runtime.block_on(self.client.request(slave, pdu))
+    }
+
+    /// 0x1
+    pub fn read_coils(
+        &mut self,
+        slave: u8,
+        address: u16,
+        count: u16,
+    ) -> Result<Vec<bool>, ClientError> {
+        let pdu = RequestPdu::try_read_coils(address, count)?;
+        self.read_coils_inner(slave, pdu, count)
+    }
+
+    /// 0x2
+    pub fn read_discrete_inputs(
+        &mut self,
+        slave: u8,
+        address: u16,
+        count: u16,
+    ) -> Result<Vec<bool>, ClientError> {
+        let pdu = RequestPdu::try_read_discrete_inputs(address, count)?;
+        self.read_coils_inner(slave, pdu, count)
+    }
+
+    /// 0x3
+    pub fn read_holding_registers(
+        &mut self,
+        slave: u8,
+        address: u16,
+        count: u16,
+    ) -> Result<Vec<u16>, ClientError> {
+        let pdu = RequestPdu::try_read_holding_registers(address, count)?;
+        self.read_registers_inner(slave, pdu, count)
+    }
+
+    /// 0x4
+    pub fn read_input_registers(
+        &mut self,
+        slave: u8,
+        address: u16,
+        count: u16,
+    ) -> Result<Vec<u16>, ClientError> {
+        let pdu = RequestPdu::try_read_input_registers(address, count)?;
+        self.read_registers_inner(slave, pdu, count)
+    }
+
+    /// 0x5
+    pub fn write_single_coil(
+        &mut self,
+        slave: u8,
+        address: u16,
+        value: bool,
+    ) -> Result<(), ClientError> {
+        match self.request(slave, RequestPdu::write_single_coil(address, value))? {
+            ResponsePdu::WriteSingleCoil { .. } => Ok(()),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// 0x6
+    pub fn write_single_register(
+        &mut self,
+        slave: u8,
+        address: u16,
+        value: u16,
+    ) -> Result<(), ClientError> {
+        match self.request(slave, RequestPdu::write_single_register(address, value))? {
+            ResponsePdu::WriteSingleRegister { .. } => Ok(()),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// 0xF
+    pub fn write_multiple_coils(
+        &mut self,
+        slave: u8,
+        address: u16,
+        values: &[bool],
+    ) -> Result<(), ClientError> {
+        let pdu = RequestPdu::try_write_multiple_coils(address, values)?;
+        match self.request(slave, pdu)? {
+            ResponsePdu::WriteMultipleCoils { .. } => Ok(()),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// 0x10
+    pub fn write_multiple_registers(
+        &mut self,
+        slave: u8,
+        address: u16,
+        values: &[u16],
+    ) -> Result<(), ClientError> {
+        let pdu = RequestPdu::try_write_multiple_registers(address, values)?;
+        match self.request(slave, pdu)? {
+            ResponsePdu::WriteMultipleRegisters { .. } => Ok(()),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    fn read_coils_inner(
+        &mut self,
+        slave: u8,
+        pdu: RequestPdu,
+        count: u16,
+    ) -> Result<Vec<bool>, ClientError> {
+        let func = pdu.func();
+        let response = self.request(slave, pdu)?;
+        if read_func(&response) != func {
+            return Err(ClientError::UnexpectedResponse);
+        }
+        match response.into_read_result(count) {
+            Some(ReadResult::Coils(coils)) => Ok(coils),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    fn read_registers_inner(
+        &mut self,
+        slave: u8,
+        pdu: RequestPdu,
+        count: u16,
+    ) -> Result<Vec<u16>, ClientError> {
+        let func = pdu.func();
+        let response = self.request(slave, pdu)?;
+        if read_func(&response) != func {
+            return Err(ClientError::UnexpectedResponse);
+        }
+        match response.into_read_result(count) {
+            Some(ReadResult::Registers(registers)) => Ok(registers),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+}
+
+// function of fc1-fc4 responses
+fn read_func(pdu: &ResponsePdu) -> Option<u8> {
+    match pdu {
+        ResponsePdu::ReadCoils { .. } => Some(0x1),
+        ResponsePdu::ReadDiscreteInputs { .. } => Some(0x2),
+        ResponsePdu::ReadHoldingRegisters { .. } => Some(0x3),
+        ResponsePdu::ReadInputRegisters { .. } => Some(0x4),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::codec::error::Error as CodecError;
+    use crate::transport::{prelude::*, tcp::server::TcpServer};
+
+    fn answer(pdu: &RequestPdu) -> ResponsePdu {
+        match pdu {
+            RequestPdu::ReadCoils { .. } => ResponsePdu::read_coils([true, false, true].as_slice()),
+            RequestPdu::ReadHoldingRegisters { .. } => {
+                ResponsePdu::read_holding_registers([0x1234u16, 0x5678].as_slice())
+            }
+            RequestPdu::WriteMultipleRegisters { address, nobjs, .. } => {
+                ResponsePdu::write_multiple_registers(*address, *nobjs)
+            }
+            // answer of another function
+            RequestPdu::ReadInputRegisters { .. } => {
+                ResponsePdu::read_holding_registers([0x1u16].as_slice())
+            }
+            pdu => ResponsePdu::exception(pdu.func().unwrap(), ExceptionCode::IllegalFunction),
+        }
+    }
+
+    #[test]
+    fn blocking_requests() {
+        let server = Builder::new_multi_thread().enable_all().build().unwrap();
+        server.block_on(async {
+            let settings = Settings {
+                address: TransportAddress::Tcp("127.0.0.1:15237".to_owned()),
+                ..Default::default()
+            };
+            let mut handler = TcpServer::build(settings).await.unwrap();
+            tokio::spawn(async move {
+                while let Some(request) = handler.request_rx.recv().await {
+                    let pdu = answer(&request.pdu);
+                    let _ = Response::make(request, pdu).send();
+                }
+            });
+        });
+
+        let mut client = BlockingClient::connect_tcp("127.0.0.1:15237").unwrap();
+        assert_eq!(
+            client.read_holding_registers(0x11, 0, 2).unwrap(),
+            vec![0x1234, 0x5678]
+        );
+        assert_eq!(
+            client.read_coils(0x11, 0, 3).unwrap(),
+            vec![true, false, true]
+        );
+        client
+            .write_multiple_registers(0x11, 0, &[0x1, 0x2])
+            .unwrap();
+        assert!(matches!(
+            client.read_input_registers(0x11, 0, 1),
+            Err(ClientError::UnexpectedResponse)
+        ));
+        assert!(matches!(
+            client.write_single_coil(0x11, 0, true),
+            Err(ClientError::Exception(ExceptionCode::IllegalFunction))
+        ));
+
+        // out of range counts don't reach the wire
+        let invalid = |res| matches!(res, Err(ClientError::Codec(CodecError::InvalidData)));
+        assert!(invalid(client.read_coils(0x11, 0, 0).map(|_| ())));
+        assert!(invalid(
+            client.read_discrete_inputs(0x11, 0, 2001).map(|_| ())
+        ));
+        assert!(invalid(
+            client.read_holding_registers(0x11, 0, 0).map(|_| ())
+        ));
+        assert!(invalid(
+            client.read_input_registers(0x11, 0, 126).map(|_| ())
+        ));
+        assert!(invalid(client.write_multiple_coils(0x11, 0, &[])));
+        assert!(invalid(client.write_multiple_registers(0x11, 0, &[0; 126])));
+    }
+}
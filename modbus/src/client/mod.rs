@@ -0,0 +1,41 @@
+pub mod blocking;
+pub mod poller;
+
+use crate::frame::prelude::*;
+use crate::transport::error::ClientError;
+use crate::transport::rtu::master::RtuMasterChannel;
+use crate::transport::tcp::client::TcpClient;
+use std::future::Future;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// Anything that sends a request to a slave and waits for its response
+pub trait Master {
+    fn request(
+        &mut self,
+        slave: u8,
+        pdu: RequestPdu,
+    ) -> impl Future<Output = Result<ResponsePdu, ClientError>> + Send;
+}
+
+impl Master for TcpClient {
+    fn request(
+        &mut self,
+        slave: u8,
+        pdu: RequestPdu,
+    ) -> impl Future<Output = Result<ResponsePdu, ClientError>> + Send {
+        TcpClient::request(self, slave, pdu)
+    }
+}
+
+impl<S> Master for RtuMasterChannel<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send,
+{
+    fn request(
+        &mut self,
+        slave: u8,
+        pdu: RequestPdu,
+    ) -> impl Future<Output = Result<ResponsePdu, ClientError>> + Send {
+        RtuMasterChannel::request(self, slave, pdu)
+    }
+}
@@ -0,0 +1,161 @@
+//! Periodic polling on top of a master.
+//!
+//! ```no_run
+//! use futures::StreamExt;
+//! use modbus::client::poller::{PollItem, Poller};
+//! use modbus::frame::prelude::*;
+//! use modbus::transport::tcp::client::TcpClient;
+//! use std::time::Duration;
+//!
+//! # async fn poll() -> std::io::Result<()> {
+//! let client = TcpClient::connect("127.0.0.1:502").await?;
+//! let items = vec![PollItem {
+//!     slave: 0x11,
+//!     pdu: RequestPdu::read_holding_registers(0x6B, 3),
+//!     interval: Duration::from_secs(1),
+//! }];
+//! let mut stream = Box::pin(Poller::new(client, items).to_stream());
+//! while let Some((item, result)) = stream.next().await {
+//!     println!("{} {:?}", item.slave, result);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use crate::client::Master;
+use crate::frame::prelude::*;
+use crate::transport::error::ClientError;
+use futures::Stream;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// The interval of an item that keeps failing grows up to 2^MAX_BACKOFF times
+const MAX_BACKOFF: u32 = 3;
+
+/// Request that is sent every `interval`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PollItem {
+    pub slave: u8,
+    pub pdu: RequestPdu,
+    pub interval: Duration,
+}
+
+struct Schedule {
+    item: PollItem,
+    next: Instant,
+    errors: u32,
+}
+
+impl Schedule {
+    fn delay(&self) -> Duration {
+        self.item.interval * 2u32.pow(self.errors.min(MAX_BACKOFF))
+    }
+}
+
+/// Sends the items one at a time, each at its own interval. All items are
+/// sent right after the start
+pub struct Poller<M> {
+    client: M,
+    items: Vec<Schedule>,
+}
+
+impl<M: Master> Poller<M> {
+    pub fn new(client: M, items: Vec<PollItem>) -> Poller<M> {
+        let now = Instant::now();
+        let items = items
+            .into_iter()
+            .map(|item| Schedule {
+                item,
+                next: now,
+                errors: 0,
+            })
+            .collect();
+        Poller { client, items }
+    }
+
+    /// Wait for the closest item and send it
+    pub async fn poll_next(&mut self) -> Option<(PollItem, Result<ResponsePdu, ClientError>)> {
+        let schedule = self.items.iter_mut().min_by_key(|schedule| schedule.next)?;
+        tokio::time::sleep_until(schedule.next).await;
+
+        let item = schedule.item.clone();
+        let result = self.client.request(item.slave, item.pdu.clone()).await;
+        if result.is_ok() {
+            schedule.errors = 0;
+            schedule.next += schedule.item.interval;
+        } else {
+            schedule.errors += 1;
+            schedule.next = Instant::now() + schedule.delay();
+        }
+        Some((item, result))
+    }
+
+    pub fn to_stream(self) -> impl Stream<Item = (PollItem, Result<ResponsePdu, ClientError>)> {
+        futures::stream::unfold(self, |mut poller| async move {
+            let next = poller.poll_next().await?;
+            Some((next, poller))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::StreamExt;
+
+    // answers at once and fails requests to slave 0xFF
+    struct Mock;
+
+    impl Master for Mock {
+        async fn request(&mut self, slave: u8, _: RequestPdu) -> Result<ResponsePdu, ClientError> {
+            match slave {
+                0xFF => Err(ClientError::Timeout),
+                _ => Ok(ResponsePdu::read_holding_registers([0x1u16].as_slice())),
+            }
+        }
+    }
+
+    fn item(slave: u8, interval: u64) -> PollItem {
+        PollItem {
+            slave,
+            pdu: RequestPdu::read_holding_registers(0, 1),
+            interval: Duration::from_millis(interval),
+        }
+    }
+
+    // slave and time of the first `count` polls
+    async fn cadence(items: Vec<PollItem>, count: usize) -> Vec<(u8, u128)> {
+        let start = Instant::now();
+        Poller::new(Mock, items)
+            .to_stream()
+            .take(count)
+            .map(|(item, _)| (item.slave, start.elapsed().as_millis()))
+            .collect()
+            .await
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn intervals() {
+        let polls = cadence(vec![item(1, 10), item(2, 25)], 9).await;
+        assert_eq!(
+            polls,
+            vec![
+                (1, 0),
+                (2, 0),
+                (1, 10),
+                (1, 20),
+                (2, 25),
+                (1, 30),
+                (1, 40),
+                (1, 50),
+                (2, 50),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn backoff() {
+        let polls = cadence(vec![item(0xFF, 10)], 6).await;
+        let times: Vec<_> = polls.into_iter().map(|(_, time)| time).collect();
+        assert_eq!(times, vec![0, 20, 60, 140, 220, 300]);
+    }
+}
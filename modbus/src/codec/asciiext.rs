@@ -0,0 +1,160 @@
+use crate::codec::checksum::lrc;
+use crate::codec::context::{ReadCtx, WriteCtx};
+use crate::codec::error::Error;
+use crate::codec::pduext::{read_pdu, write_pdu, PduConfig};
+use crate::frame::prelude::*;
+use bytes::BytesMut;
+
+// ':' + hex of slave, PDU (253) and LRC + CRLF
+const MAX_FRAME_SIZE: usize = 1 + 2 * 255 + 2;
+
+/// Two's complement of the sum of bytes. Sum of a frame with its LRC is 0
+pub fn calc_lrc(bytes: &[u8]) -> u8 {
+    lrc(bytes)
+}
+
+fn hex_digit(c: u8) -> Option<u8> {
+    (c as char).to_digit(16).map(|value| value as u8)
+}
+
+pub(crate) fn hex_byte(hi: u8, lo: u8) -> Option<u8> {
+    Some((hex_digit(hi)? << 4) | hex_digit(lo)?)
+}
+
+fn decode_hex(text: &[u8]) -> Result<Vec<u8>, Error> {
+    if !text.len().is_multiple_of(2) {
+        return Err(Error::InvalidData);
+    }
+    text.chunks(2)
+        .map(|pair| hex_byte(pair[0], pair[1]).ok_or(Error::InvalidData))
+        .collect()
+}
+
+// Binary frame (slave + PDU + LRC) and the size of its text if the buffer
+// holds a complete line
+fn read_line(buffer: &[u8]) -> Result<Option<(Vec<u8>, usize)>, Error> {
+    let Some(first) = buffer.first() else {
+        return Ok(None);
+    };
+    if *first != b':' {
+        return Err(Error::InvalidData);
+    }
+    let Some(end) = buffer.windows(2).position(|pair| pair == b"\r\n") else {
+        return if buffer.len() >= MAX_FRAME_SIZE {
+            Err(Error::InvalidData)
+        } else {
+            Ok(None)
+        };
+    };
+    let bytes = decode_hex(&buffer[1..end])?;
+    if bytes.len() < 3 {
+        return Err(Error::InvalidData);
+    }
+    if calc_lrc(&bytes) != 0 {
+        return Err(Error::InvalidCrc);
+    }
+    Ok(Some((bytes, end + 2)))
+}
+
+pub(crate) fn read_ascii_frame(
+    ctx: &mut ReadCtx,
+    config: &PduConfig,
+) -> Result<Option<RequestFrame>, Error> {
+    let Some((bytes, size)) = read_line(ctx.buffer)? else {
+        return Ok(None);
+    };
+    // the line is complete, so the PDU must fill it exactly
+    let mut pdu_ctx = ReadCtx::new(&bytes[1..bytes.len() - 1]);
+    let pdu = read_pdu(&mut pdu_ctx, config)?.ok_or(Error::InvalidData)?;
+    if pdu_ctx.remaining() != 0 {
+        return Err(Error::InvalidData);
+    }
+    ctx.cursor.set_position(size as u64);
+    Ok(Some(RequestFrame::from_parts(0, bytes[0], pdu)))
+}
+
+pub(crate) fn write_ascii_frame(
+    dst: &mut BytesMut,
+    frame: &ResponseFrame,
+    config: &PduConfig,
+) -> Result<(), Error> {
+    let mut bytes = vec![0u8; 1 + frame.pdu.len()];
+    let mut ctx = WriteCtx::new(&mut bytes);
+    ctx.write_u8(frame.slave).unwrap();
+    write_pdu(&mut ctx, &frame.pdu, config)?;
+    bytes.push(calc_lrc(&bytes));
+
+    dst.clear();
+    dst.extend_from_slice(b":");
+    for byte in bytes {
+        dst.extend_from_slice(format!("{:02X}", byte).as_bytes());
+    }
+    dst.extend_from_slice(b"\r\n");
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lrc_values() {
+        assert_eq!(calc_lrc(&[0x11, 0x03, 0x00, 0x6B, 0x00, 0x03]), 0x7E);
+        assert_eq!(calc_lrc(&[0x11, 0x03, 0x00, 0x6B, 0x00, 0x03, 0x7E]), 0);
+        assert_eq!(calc_lrc(&[]), 0);
+    }
+
+    #[test]
+    fn read_frame() {
+        let input = b":1103006B00037E\r\n:11";
+        let mut ctx = ReadCtx::new(input);
+        let frame = read_ascii_frame(&mut ctx, &PduConfig::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(frame.slave, 0x11);
+        assert_eq!(frame.pdu, RequestPdu::read_holding_registers(0x6B, 3));
+        assert_eq!(ctx.processed(), 17);
+
+        // lowercase hex is accepted too
+        let input = b":1103006b00037e\r\n";
+        let frame = read_ascii_frame(&mut ReadCtx::new(input), &PduConfig::default());
+        assert!(matches!(frame, Ok(Some(_))));
+    }
+
+    #[test]
+    fn read_frame_incomplete() {
+        for input in [&b""[..], b":", b":1103006B00037E", b":1103006B00037E\r"] {
+            let frame = read_ascii_frame(&mut ReadCtx::new(input), &PduConfig::default());
+            assert_eq!(frame, Ok(None));
+        }
+    }
+
+    #[test]
+    fn read_frame_invalid() {
+        let cases: [(&[u8], Error); 5] = [
+            (b":1103006B00037F\r\n", Error::InvalidCrc),
+            (b":1103006B00037\r\n", Error::InvalidData),
+            (b":1103006B0003XE\r\n", Error::InvalidData),
+            (b"1103006B00037E\r\n", Error::InvalidData),
+            // fc3 PDU is shorter than the line
+            (b":1103006B0003007E\r\n", Error::InvalidData),
+        ];
+        for (input, err) in cases {
+            let frame = read_ascii_frame(&mut ReadCtx::new(input), &PduConfig::default());
+            assert_eq!(frame, Err(err));
+        }
+    }
+
+    #[test]
+    fn write_frame() {
+        let pdu = ResponsePdu::read_holding_registers([0x022Bu16, 0x0000, 0x0064].as_slice());
+        let mut dst = BytesMut::new();
+        write_ascii_frame(
+            &mut dst,
+            &ResponseFrame::new(0x11, pdu),
+            &PduConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(&dst[..], b":110306022B0000006455\r\n");
+    }
+}
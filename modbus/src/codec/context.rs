@@ -1,4 +1,4 @@
-use byteorder::{BigEndian, NativeEndian, ReadBytesExt, WriteBytesExt};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use bytes::Buf;
 use std::io::Cursor;
 
@@ -8,7 +8,7 @@ pub(crate) struct ReadCtx<'a> {
 }
 
 impl<'a> ReadCtx<'a> {
-    pub fn new(buffer: &'a [u8]) -> ReadCtx {
+    pub fn new(buffer: &'a [u8]) -> ReadCtx<'a> {
         ReadCtx {
             buffer,
             cursor: Cursor::new(buffer),
@@ -19,10 +19,6 @@ impl<'a> ReadCtx<'a> {
         self.cursor.read_u8().ok()
     }
 
-    pub fn read_u16(&mut self) -> Option<u16> {
-        self.cursor.read_u16::<NativeEndian>().ok()
-    }
-
     pub fn read_u16_be(&mut self) -> Option<u16> {
         self.cursor.read_u16::<BigEndian>().ok()
     }
@@ -49,7 +45,7 @@ pub(crate) struct WriteCtx<'a> {
 }
 
 impl<'a> WriteCtx<'a> {
-    pub fn new(buffer: &'a mut [u8]) -> WriteCtx {
+    pub fn new(buffer: &'a mut [u8]) -> WriteCtx<'a> {
         WriteCtx {
             //       buffer,
             cursor: Cursor::new(buffer),
@@ -60,10 +56,6 @@ impl<'a> WriteCtx<'a> {
         self.cursor.write_u8(value).ok()
     }
 
-    pub fn write_u16(&mut self, value: u16) -> Option<()> {
-        self.cursor.write_u16::<NativeEndian>(value).ok()
-    }
-
     pub fn write_u16_be(&mut self, value: u16) -> Option<()> {
         self.cursor.write_u16::<BigEndian>(value).ok()
     }
@@ -78,7 +70,7 @@ impl<'a> WriteCtx<'a> {
     }
 
     pub fn write_data_u16_be(&mut self, values: &[u8]) -> Option<()> {
-        assert!(values.len() % 2 == 0);
+        assert!(values.len().is_multiple_of(2));
         let len = values.len() / 2;
         for idx in 0..len {
             let b1 = values[idx * 2];
@@ -135,7 +127,7 @@ mod test {
     fn read_ctx_und() {
         let buffer = [0x01];
         let mut ctx = ReadCtx::new(&buffer);
-        let res = ctx.read_u16();
+        let res = ctx.read_u16_be();
         assert!(res.is_none());
     }
 
@@ -1,7 +1,7 @@
 use std::convert::From;
 use std::io;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Error {
     InvalidData,
     InvalidVersion,
@@ -33,7 +33,7 @@ mod test {
         let err = io::Error::new(io::ErrorKind::UnexpectedEof, "");
         assert_eq!(Error::from(err), Error::BufferToSmall);
 
-        let err = io::Error::new(io::ErrorKind::Other, "");
+        let err = io::Error::other("");
         assert_eq!(Error::from(err), Error::Other);
     }
 }
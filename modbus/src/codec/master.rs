@@ -0,0 +1,188 @@
+use crate::codec::context::{ReadCtx, WriteCtx};
+use crate::codec::error::Error;
+use crate::codec::mbap::{read_mbap, write_mbap_header};
+use crate::codec::pduext::{read_response_pdu, write_request_pdu, PduConfig};
+use crate::codec::rtuext::calc_crc_be;
+use crate::codec::slave::{read_crc, write_crc, CodecMode};
+use crate::codec::wait;
+
+use crate::frame::prelude::*;
+use bytes::{Buf, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+fn read_rtu_frame(ctx: &mut ReadCtx, config: &PduConfig) -> Result<Option<ResponseFrame>, Error> {
+    let slave = wait!(ctx.read_u8());
+    if let [_, 0x2b, ..] = ctx.buffer {
+        return read_rtu_mei(ctx, slave, config);
+    }
+    let pdu = wait!(read_response_pdu(ctx, config)?);
+    let _ = wait!(read_crc(ctx)?);
+    Ok(Some(ResponseFrame::from_parts(0, slave, pdu)))
+}
+
+// 0x2b payload takes the rest of the PDU, so the frame is the whole input if
+// it ends with a valid CRC
+fn read_rtu_mei(
+    ctx: &mut ReadCtx,
+    slave: u8,
+    config: &PduConfig,
+) -> Result<Option<ResponseFrame>, Error> {
+    let buffer = ctx.buffer;
+    if buffer.len() < 5 || calc_crc_be(buffer) != 0 {
+        return Ok(None);
+    }
+    let mut pdu_ctx = ReadCtx::new(&buffer[1..buffer.len() - 2]);
+    let pdu = read_response_pdu(&mut pdu_ctx, config)?.ok_or(Error::InvalidData)?;
+    ctx.cursor.set_position(buffer.len() as u64);
+    Ok(Some(ResponseFrame::from_parts(0, slave, pdu)))
+}
+
+fn read_net_frame(ctx: &mut ReadCtx, config: &PduConfig) -> Result<Option<ResponseFrame>, Error> {
+    let header = wait!(read_mbap(ctx)?);
+
+    // same as for requests, MBAP length bounds the PDU
+    let start = ctx.processed();
+    let end = start + header.len as usize - 1;
+    let complete = ctx.buffer.len() >= end;
+    let mut pdu_ctx = ReadCtx::new(&ctx.buffer[start..end.min(ctx.buffer.len())]);
+    let pdu = match read_response_pdu(&mut pdu_ctx, config)? {
+        Some(ResponsePdu::Raw { .. } | ResponsePdu::EncapsulatedInterfaceTransport { .. })
+            if !complete =>
+        {
+            return Ok(None)
+        }
+        Some(pdu) => pdu,
+        None if complete => return Err(Error::InvalidData),
+        None => return Ok(None),
+    };
+    ctx.cursor.advance(pdu_ctx.processed());
+
+    Ok(Some(ResponseFrame::from_parts(
+        header.id,
+        header.slave,
+        pdu,
+    )))
+}
+
+/// Request encoder / response decoder for the client side
+#[derive(Clone)]
+pub struct MasterCodec {
+    mode: CodecMode,
+    config: PduConfig,
+}
+
+impl MasterCodec {
+    pub fn new_rtu() -> MasterCodec {
+        MasterCodec {
+            mode: CodecMode::Rtu,
+            config: PduConfig::default(),
+        }
+    }
+
+    pub fn new_tcp() -> MasterCodec {
+        MasterCodec {
+            mode: CodecMode::Net,
+            config: PduConfig::default(),
+        }
+    }
+}
+
+impl Decoder for MasterCodec {
+    type Item = ResponseFrame;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let mut ctx = ReadCtx::new(src);
+        let res = match self.mode {
+            CodecMode::Rtu => read_rtu_frame(&mut ctx, &self.config),
+            CodecMode::Net => read_net_frame(&mut ctx, &self.config),
+            CodecMode::Ascii => unreachable!("no ASCII master"),
+        };
+        let processed = ctx.processed();
+        match res {
+            Ok(Some(_)) => src.advance(processed),
+            Err(_) => src.clear(),
+            Ok(None) => {}
+        }
+        res
+    }
+}
+
+impl Encoder<RequestFrame> for MasterCodec {
+    type Error = Error;
+    fn encode(&mut self, frame: RequestFrame, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let len = match self.mode {
+            CodecMode::Rtu => frame.pdu.len() + 3,
+            CodecMode::Net => frame.pdu.len() + 7,
+            CodecMode::Ascii => unreachable!("no ASCII master"),
+        };
+        dst.resize(len, 0);
+        let mut ctx = WriteCtx::new(dst.as_mut());
+        if self.mode == CodecMode::Net {
+            write_mbap_header(&mut ctx, frame.id, frame.pdu.len())?;
+        }
+        ctx.write_u8(frame.slave).unwrap();
+        write_request_pdu(&mut ctx, &frame.pdu, &self.config)?;
+        if self.mode == CodecMode::Rtu {
+            write_crc(&mut ctx)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encode_tcp() {
+        let frame =
+            RequestFrame::from_parts(0x1, 0x11, RequestPdu::read_holding_registers(0x6B, 3));
+        let mut buffer = BytesMut::new();
+        MasterCodec::new_tcp().encode(frame, &mut buffer).unwrap();
+        assert_eq!(
+            buffer[..],
+            [0x0, 0x1, 0x0, 0x0, 0x0, 0x6, 0x11, 0x03, 0x00, 0x6B, 0x00, 0x03]
+        );
+    }
+
+    #[test]
+    fn encode_rtu() {
+        let frame = RequestFrame::new(0x11, RequestPdu::read_coils(0x13, 0x25));
+        let mut buffer = BytesMut::new();
+        MasterCodec::new_rtu().encode(frame, &mut buffer).unwrap();
+        assert_eq!(buffer[..], [0x11, 0x01, 0x00, 0x13, 0x00, 0x25, 0x0E, 0x84]);
+    }
+
+    #[test]
+    fn decode_tcp() {
+        let input = [
+            0x0, 0x7, 0x0, 0x0, 0x0, 0x5, 0x11, 0x03, 0x02, 0x12, 0x34, 0x0, 0x8,
+        ];
+        let mut buffer = BytesMut::from(&input[..]);
+        let mut codec = MasterCodec::new_tcp();
+        let frame = codec.decode(&mut buffer).unwrap().unwrap();
+        assert_eq!(frame.id, 7);
+        assert_eq!(frame.slave, 0x11);
+        assert_eq!(
+            frame.pdu,
+            ResponsePdu::read_holding_registers([0x1234u16].as_slice())
+        );
+        // the next frame is incomplete
+        assert_eq!(buffer.len(), 2);
+        assert!(codec.decode(&mut buffer).unwrap().is_none());
+    }
+
+    #[test]
+    fn decode_rtu_exception() {
+        let mut buffer = BytesMut::new();
+        buffer.extend_from_slice(&[0x11, 0x83, 0x02]);
+        let crc = crate::codec::rtuext::calc_crc_be(&buffer[..]);
+        buffer.extend_from_slice(&crc.to_be_bytes());
+        let frame = MasterCodec::new_rtu().decode(&mut buffer).unwrap().unwrap();
+        assert_eq!(
+            frame.pdu,
+            ResponsePdu::exception(0x3, ExceptionCode::IllegalDataAddress)
+        );
+    }
+}
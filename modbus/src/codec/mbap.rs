@@ -5,6 +5,9 @@ use crate::data::MAX_DATA_SIZE;
 
 use crate::frame::prelude::*;
 
+/// transaction id, protocol id, length and unit id
+pub const MBAP_HEADER_LEN: usize = 7;
+
 pub(crate) struct Mbap {
     pub id: u16,
     pub proto: u16,
@@ -29,9 +32,18 @@ pub(crate) fn read_mbap(ctx: &mut ReadCtx) -> Result<Option<Mbap>, Error> {
 }
 
 pub(crate) fn write_mbap(ctx: &mut WriteCtx, frame: &ResponseFrame) -> Result<(), Error> {
-    ctx.write_u16_be(frame.id).unwrap();
-    ctx.write_u16_be(0).unwrap();
-    ctx.write_u16_be(frame.pdu.len() as u16 + 1).unwrap();
+    write_mbap_header(ctx, frame.id, frame.pdu.len())
+}
+
+/// id, protocol and length. The slave id is written with the PDU
+pub(crate) fn write_mbap_header(ctx: &mut WriteCtx, id: u16, pdu_len: usize) -> Result<(), Error> {
+    if pdu_len + 1 > MAX_DATA_SIZE {
+        return Err(Error::InvalidData);
+    }
+    ctx.write_u16_be(id).ok_or(Error::BufferToSmall)?;
+    ctx.write_u16_be(0).ok_or(Error::BufferToSmall)?;
+    ctx.write_u16_be(pdu_len as u16 + 1)
+        .ok_or(Error::BufferToSmall)?;
     Ok(())
 }
 
@@ -1,10 +1,18 @@
+pub mod asciiext;
+pub mod checksum;
 pub mod context;
 pub mod error;
+pub mod master;
 pub mod mbap;
 pub mod pduext;
 pub mod rtuext;
 pub mod slave;
 
+#[cfg(test)]
+mod roundtrip;
+#[cfg(any(test, feature = "testing"))]
+pub mod vectors;
+
 #[macro_export]
 macro_rules! wait {
     ($op:expr) => {
@@ -17,3 +25,5 @@ macro_rules! wait {
 }
 
 pub(crate) use wait;
+
+pub use slave::decode_request;
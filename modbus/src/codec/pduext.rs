@@ -2,43 +2,102 @@ use crate::codec::context::{ReadCtx, WriteCtx};
 use crate::codec::error::Error;
 use crate::codec::wait;
 use crate::data::{
-    bytes::BytesCursor, checks, coils::CoilsCursor, helpers, registers::RegistersCursorBe,
-    storage::DataStorage, MAX_DATA_SIZE,
+    bytes::BytesCursor,
+    checks,
+    coils::{BitOrder, CoilsSlice},
+    helpers,
+    registers::RegistersCursorBe,
+    storage::DataStorage,
+    MAX_DATA_SIZE, MAX_FIFO_COUNT, MAX_NCOILS, MAX_NREGS, MAX_RW_WRITE_NREGS,
 };
 
 use crate::frame::prelude::*;
 use bytes::Buf;
-use std::io::Cursor;
 
 const COIL_ON: u16 = 0xFF00;
 const COIL_OFF: u16 = 0x0000;
 
-pub(crate) fn read_pdu(ctx: &mut ReadCtx) -> Result<Option<RequestPdu>, Error> {
+/// Max. number of objects accepted in requests. Defaults to the spec values
+/// and can only tighten them: a response can't carry more (one byte count)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// fc1/fc2/fc15
+    pub max_coils: u16,
+    /// fc3/fc4/fc16/fc23
+    pub max_registers: u16,
+    /// payload of fc15/fc16/fc23
+    pub max_write_bytes: u8,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_coils: MAX_NCOILS as u16,
+            max_registers: MAX_NREGS as u16,
+            max_write_bytes: MAX_WRITE_BYTES,
+        }
+    }
+}
+
+impl Limits {
+    /// the limits bounded by the spec values
+    pub(crate) fn clamp_to_spec(self) -> Limits {
+        let spec = Limits::default();
+        Limits {
+            max_coils: self.max_coils.min(spec.max_coils),
+            max_registers: self.max_registers.min(spec.max_registers),
+            max_write_bytes: self.max_write_bytes.min(spec.max_write_bytes),
+        }
+    }
+}
+
+// 0x7B0 coils or 0x7B registers
+const MAX_WRITE_BYTES: u8 = 246;
+
+/// PDU decoding options
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PduConfig {
+    /// accept only 0xE (Read Device Identification) in 0x2b requests.
+    /// 0xD is decoded as Raw to be answered with IllegalDataValue
+    pub strict_mei: bool,
+    /// order of coils in fc1/fc2 responses and fc15 requests
+    pub coil_order: BitOrder,
+    /// decode fc15/fc16 with zero count as Raw instead of failing
+    pub zero_count_writes: bool,
+    /// max. number of objects in requests
+    pub limits: Limits,
+}
+
+pub(crate) fn read_pdu(ctx: &mut ReadCtx, config: &PduConfig) -> Result<Option<RequestPdu>, Error> {
     let func = wait!(ctx.read_u8()); // else { return Ok(None) };
     match func {
         0x1 => {
             let address = wait!(ctx.read_u16_be());
             let nobjs = wait!(ctx.read_u16_be());
-            check_coils_count(nobjs)?;
-            Ok(Some(RequestPdu::read_coils(address, nobjs)))
+            check_count(nobjs, config.limits.max_coils)?;
+            check_address_range(address, nobjs)?;
+            Ok(Some(RequestPdu::ReadCoils { address, nobjs }))
         }
         0x2 => {
             let address = wait!(ctx.read_u16_be());
             let nobjs = wait!(ctx.read_u16_be());
-            check_coils_count(nobjs)?;
-            Ok(Some(RequestPdu::read_discrete_inputs(address, nobjs)))
+            check_count(nobjs, config.limits.max_coils)?;
+            check_address_range(address, nobjs)?;
+            Ok(Some(RequestPdu::ReadDiscreteInputs { address, nobjs }))
         }
         0x3 => {
             let address = wait!(ctx.read_u16_be());
             let nobjs = wait!(ctx.read_u16_be());
-            check_registers_count(nobjs)?;
-            Ok(Some(RequestPdu::read_holding_registers(address, nobjs)))
+            check_count(nobjs, config.limits.max_registers)?;
+            check_address_range(address, nobjs)?;
+            Ok(Some(RequestPdu::ReadHoldingRegisters { address, nobjs }))
         }
         0x4 => {
             let address = wait!(ctx.read_u16_be());
             let nobjs = wait!(ctx.read_u16_be());
-            check_registers_count(nobjs)?;
-            Ok(Some(RequestPdu::read_input_registers(address, nobjs)))
+            check_count(nobjs, config.limits.max_registers)?;
+            check_address_range(address, nobjs)?;
+            Ok(Some(RequestPdu::ReadInputRegisters { address, nobjs }))
         }
         0x5 => {
             let address = wait!(ctx.read_u16_be());
@@ -51,22 +110,45 @@ pub(crate) fn read_pdu(ctx: &mut ReadCtx) -> Result<Option<RequestPdu>, Error> {
             let value = wait!(ctx.read_u16_be());
             Ok(Some(RequestPdu::write_single_register(address, value)))
         }
+        0x7 => Ok(Some(RequestPdu::read_exception_status())),
+        0x8 => {
+            let sub_function = wait!(ctx.read_u16_be());
+            let data = wait!(ctx.read_u16_be());
+            Ok(Some(RequestPdu::diagnostic(sub_function, data)))
+        }
         0xF => {
             let address = wait!(ctx.read_u16_be());
             let nobjs = wait!(ctx.read_u16_be());
             let nbytes = wait!(ctx.read_u8());
+            if nobjs == 0 && config.zero_count_writes {
+                return zero_count_write(func, address, nbytes);
+            }
             check_coils_count(nobjs)?;
+            check_count(nobjs, config.limits.max_coils)?;
+            check_address_range(address, nobjs)?;
+            check_write_bytes(nbytes, &config.limits)?;
             check_matching(helpers::get_coils_len(nobjs), nbytes as usize)?;
             wait!(ctx.is_enough(nbytes as usize));
-            let pdu =
-                RequestPdu::write_multiple_coils(address, CoilsCursor::new(&mut ctx.cursor, nobjs));
+            let mut bytes = [0u8; MAX_DATA_SIZE];
+            let bytes = &mut bytes[..nbytes as usize];
+            ctx.cursor.copy_to_slice(bytes);
+            bytes
+                .iter_mut()
+                .for_each(|byte| *byte = config.coil_order.convert(*byte));
+            let pdu = RequestPdu::write_multiple_coils(address, CoilsSlice::new(bytes, nobjs));
             Ok(Some(pdu))
         }
         0x10 => {
             let address = wait!(ctx.read_u16_be());
             let nobjs = wait!(ctx.read_u16_be());
             let nbytes = wait!(ctx.read_u8());
-            check_registers_count(nobjs)?;
+            if nobjs == 0 && config.zero_count_writes {
+                return zero_count_write(func, address, nbytes);
+            }
+            check_registers_count(nobjs, MAX_NREGS as u16)?;
+            check_count(nobjs, config.limits.max_registers)?;
+            check_address_range(address, nobjs)?;
+            check_write_bytes(nbytes, &config.limits)?;
             check_matching(helpers::get_registers_len(nobjs), nbytes as usize)?;
             wait!(ctx.is_enough(nbytes as usize));
             let pdu = RequestPdu::write_multiple_registers(
@@ -75,16 +157,66 @@ pub(crate) fn read_pdu(ctx: &mut ReadCtx) -> Result<Option<RequestPdu>, Error> {
             );
             Ok(Some(pdu))
         }
+        0x11 => Ok(Some(RequestPdu::report_slave_id())),
+        0x16 => {
+            let address = wait!(ctx.read_u16_be());
+            let and_mask = wait!(ctx.read_u16_be());
+            let or_mask = wait!(ctx.read_u16_be());
+            Ok(Some(RequestPdu::mask_write_register(
+                address, and_mask, or_mask,
+            )))
+        }
+        0x17 => {
+            let read_address = wait!(ctx.read_u16_be());
+            let read_nobjs = wait!(ctx.read_u16_be());
+            let write_address = wait!(ctx.read_u16_be());
+            let write_nobjs = wait!(ctx.read_u16_be());
+            let nbytes = wait!(ctx.read_u8());
+            check_registers_count(read_nobjs, MAX_NREGS as u16)?;
+            check_count(read_nobjs, config.limits.max_registers)?;
+            check_registers_count(write_nobjs, MAX_RW_WRITE_NREGS as u16)?;
+            check_write_bytes(nbytes, &config.limits)?;
+            check_matching(helpers::get_registers_len(write_nobjs), nbytes as usize)?;
+            wait!(ctx.is_enough(nbytes as usize));
+            let pdu = RequestPdu::read_write_multiple_registers(
+                read_address,
+                read_nobjs,
+                write_address,
+                RegistersCursorBe::new(&mut ctx.cursor, write_nobjs),
+            );
+            Ok(Some(pdu))
+        }
+        0x18 => {
+            let address = wait!(ctx.read_u16_be());
+            Ok(Some(RequestPdu::read_fifo_queue(address)))
+        }
         0x2b => {
             let mei_type = wait!(ctx.read_u8());
             check_mei_type(mei_type)?;
             wait!(ctx.is_enough(1));
             let pdu = match mei_type {
-                0xE => RequestPdu::encapsulated_interface_transport(
-                    mei_type,
-                    BytesCursor::new(&mut ctx.cursor, 1),
-                ),
+                // read device id code and object id
+                0xE => {
+                    wait!(ctx.is_enough(2));
+                    RequestPdu::encapsulated_interface_transport(
+                        mei_type,
+                        BytesCursor::new(&mut ctx.cursor, 2),
+                    )
+                }
+                0xD if config.strict_mei => {
+                    // MEI type and the payload
+                    if ctx.remaining() >= MAX_DATA_SIZE {
+                        return Err(Error::InvalidData);
+                    }
+                    let mut data = DataStorage::raw_empty(ctx.remaining() + 1);
+                    data.get_mut()[0] = mei_type;
+                    ctx.cursor.copy_to_slice(&mut data.get_mut()[1..]);
+                    RequestPdu::raw(func, data)
+                }
                 0xD => {
+                    if ctx.remaining() > MAX_DATA_SIZE {
+                        return Err(Error::InvalidData);
+                    }
                     let remain = ctx.remaining() as u16;
                     RequestPdu::encapsulated_interface_transport(
                         mei_type,
@@ -104,20 +236,254 @@ pub(crate) fn read_pdu(ctx: &mut ReadCtx) -> Result<Option<RequestPdu>, Error> {
     }
 }
 
-pub(crate) fn write_pdu(ctx: &mut WriteCtx, src: &ResponsePdu) -> Result<Option<()>, Error> {
+fn write_coils(ctx: &mut WriteCtx, data: &DataStorage, order: BitOrder) {
+    for byte in data.iter_bytes() {
+        ctx.write_u8(order.convert(byte)).unwrap();
+    }
+}
+
+/// function codes with a request decoder. Others are decoded as Raw
+pub(crate) fn is_known_function(func: u8) -> bool {
+    matches!(func, 0x1..=0x8 | 0xF | 0x10 | 0x11 | 0x16..=0x18 | 0x2b)
+}
+
+pub(crate) fn write_request_pdu(
+    ctx: &mut WriteCtx,
+    src: &RequestPdu,
+    config: &PduConfig,
+) -> Result<Option<()>, Error> {
+    let func = src.func().unwrap_or(0);
+    ctx.is_enough(src.len()).unwrap();
+    ctx.write_u8(func).unwrap();
+    match src {
+        RequestPdu::ReadCoils { address, nobjs }
+        | RequestPdu::ReadDiscreteInputs { address, nobjs }
+        | RequestPdu::ReadHoldingRegisters { address, nobjs }
+        | RequestPdu::ReadInputRegisters { address, nobjs } => {
+            ctx.write_u16_be(*address).unwrap();
+            ctx.write_u16_be(*nobjs).unwrap();
+        }
+        RequestPdu::WriteSingleCoil { address, value } => {
+            ctx.write_u16_be(*address).unwrap();
+            ctx.write_u16_be(coil_to_raw(*value)).unwrap();
+        }
+        RequestPdu::WriteSingleRegister { address, value } => {
+            ctx.write_u16_be(*address).unwrap();
+            ctx.write_u16_be(*value).unwrap();
+        }
+        RequestPdu::ReadExceptionStatus | RequestPdu::ReportSlaveId => {}
+        RequestPdu::MaskWriteRegister {
+            address,
+            and_mask,
+            or_mask,
+        } => {
+            ctx.write_u16_be(*address).unwrap();
+            ctx.write_u16_be(*and_mask).unwrap();
+            ctx.write_u16_be(*or_mask).unwrap();
+        }
+        RequestPdu::ReadWriteMultipleRegisters {
+            read_address,
+            read_nobjs,
+            write_address,
+            write_nobjs,
+            data,
+        } => {
+            ctx.write_u16_be(*read_address).unwrap();
+            ctx.write_u16_be(*read_nobjs).unwrap();
+            ctx.write_u16_be(*write_address).unwrap();
+            ctx.write_u16_be(*write_nobjs).unwrap();
+            ctx.write_u8(data.len() as u8).unwrap();
+            ctx.write_data_u16_be(data.get()).unwrap();
+        }
+        RequestPdu::Diagnostic { sub_function, data } => {
+            ctx.write_u16_be(*sub_function).unwrap();
+            ctx.write_u16_be(*data).unwrap();
+        }
+        RequestPdu::WriteMultipleCoils {
+            address,
+            nobjs,
+            data,
+        } => {
+            ctx.write_u16_be(*address).unwrap();
+            ctx.write_u16_be(*nobjs).unwrap();
+            ctx.write_u8(data.len() as u8).unwrap();
+            write_coils(ctx, data, config.coil_order);
+        }
+        RequestPdu::WriteMultipleRegisters {
+            address,
+            nobjs,
+            data,
+        } => {
+            ctx.write_u16_be(*address).unwrap();
+            ctx.write_u16_be(*nobjs).unwrap();
+            ctx.write_u8(data.len() as u8).unwrap();
+            ctx.write_data_u16_be(data.get()).unwrap();
+        }
+        RequestPdu::ReadFifoQueue { address } => {
+            ctx.write_u16_be(*address).unwrap();
+        }
+        RequestPdu::EncapsulatedInterfaceTransport { mei_type, data } => {
+            ctx.write_u8(*mei_type).unwrap();
+            ctx.write_bytes(data.get()).unwrap();
+        }
+        RequestPdu::Raw { data, .. } => {
+            ctx.write_bytes(data.get()).unwrap();
+        }
+    }
+    Ok(Some(()))
+}
+
+/// Decode a response PDU. Ok(None) if the buffer holds only a part of it
+pub fn decode_response_pdu(bytes: &[u8]) -> Result<Option<ResponsePdu>, Error> {
+    read_response_pdu(&mut ReadCtx::new(bytes), &PduConfig::default())
+}
+
+pub(crate) fn read_response_pdu(
+    ctx: &mut ReadCtx,
+    config: &PduConfig,
+) -> Result<Option<ResponsePdu>, Error> {
+    let func = wait!(ctx.read_u8());
+    match func {
+        0x1 | 0x2 => {
+            let nbytes = wait!(ctx.read_u8());
+            check_coils_bytes(nbytes)?;
+            wait!(ctx.is_enough(nbytes as usize));
+            let mut data = DataStorage::raw_empty(nbytes as usize);
+            ctx.cursor.copy_to_slice(data.get_mut());
+            data.get_mut()
+                .iter_mut()
+                .for_each(|byte| *byte = config.coil_order.convert(*byte));
+            // the count isn't on the wire. Padding bits are taken as coils
+            let nobjs = nbytes as u16 * 8;
+            let pdu = if func == 0x1 {
+                ResponsePdu::ReadCoils { nobjs, data }
+            } else {
+                ResponsePdu::ReadDiscreteInputs { nobjs, data }
+            };
+            Ok(Some(pdu))
+        }
+        0x3 | 0x4 | 0x17 => {
+            let nbytes = wait!(ctx.read_u8());
+            check_registers_bytes(nbytes)?;
+            wait!(ctx.is_enough(nbytes as usize));
+            let registers = RegistersCursorBe::new(&mut ctx.cursor, nbytes as u16 / 2);
+            let pdu = match func {
+                0x3 => ResponsePdu::read_holding_registers(registers),
+                0x4 => ResponsePdu::read_input_registers(registers),
+                _ => ResponsePdu::read_write_multiple_registers(registers),
+            };
+            Ok(Some(pdu))
+        }
+        0x5 => {
+            let address = wait!(ctx.read_u16_be());
+            let value = wait!(ctx.read_u16_be());
+            let value = raw_to_coil(value)?;
+            Ok(Some(ResponsePdu::write_single_coil(address, value)))
+        }
+        0x6 => {
+            let address = wait!(ctx.read_u16_be());
+            let value = wait!(ctx.read_u16_be());
+            Ok(Some(ResponsePdu::write_single_register(address, value)))
+        }
+        0x7 => {
+            let data = wait!(ctx.read_u8());
+            Ok(Some(ResponsePdu::read_exception_status(data)))
+        }
+        0x8 => {
+            let sub_function = wait!(ctx.read_u16_be());
+            let data = wait!(ctx.read_u16_be());
+            Ok(Some(ResponsePdu::diagnostic(sub_function, data)))
+        }
+        0xF => {
+            let address = wait!(ctx.read_u16_be());
+            let nobjs = wait!(ctx.read_u16_be());
+            check_coils_count(nobjs)?;
+            Ok(Some(ResponsePdu::write_multiple_coils(address, nobjs)))
+        }
+        0x10 => {
+            let address = wait!(ctx.read_u16_be());
+            let nobjs = wait!(ctx.read_u16_be());
+            check_registers_count(nobjs, MAX_NREGS as u16)?;
+            Ok(Some(ResponsePdu::write_multiple_registers(address, nobjs)))
+        }
+        0x11 => {
+            let nbytes = wait!(ctx.read_u8());
+            if nbytes == 0 {
+                return Err(Error::InvalidData);
+            }
+            wait!(ctx.is_enough(nbytes as usize));
+            let mut data = DataStorage::raw_empty(nbytes as usize);
+            ctx.cursor.copy_to_slice(data.get_mut());
+            Ok(Some(ResponsePdu::ReportSlaveId { data }))
+        }
+        0x16 => {
+            let address = wait!(ctx.read_u16_be());
+            let and_mask = wait!(ctx.read_u16_be());
+            let or_mask = wait!(ctx.read_u16_be());
+            Ok(Some(ResponsePdu::mask_write_register(
+                address, and_mask, or_mask,
+            )))
+        }
+        0x18 => {
+            let nbytes = wait!(ctx.read_u16_be());
+            let count = wait!(ctx.read_u16_be());
+            if count as usize > MAX_FIFO_COUNT {
+                return Err(Error::InvalidData);
+            }
+            check_matching(2 + count as usize * 2, nbytes as usize)?;
+            wait!(ctx.is_enough(count as usize * 2));
+            let data = if count == 0 {
+                DataStorage::raw_empty(0)
+            } else {
+                DataStorage::registers(RegistersCursorBe::new(&mut ctx.cursor, count))
+            };
+            Ok(Some(ResponsePdu::ReadFifoQueue { count, data }))
+        }
+        0x2b => {
+            let mei_type = wait!(ctx.read_u8());
+            let remain = std::cmp::min(ctx.remaining(), MAX_DATA_SIZE);
+            let mut data = DataStorage::raw_empty(remain);
+            ctx.cursor.copy_to_slice(data.get_mut());
+            Ok(Some(ResponsePdu::EncapsulatedInterfaceTransport {
+                mei_type,
+                data,
+            }))
+        }
+        _ if func & 0x80 != 0 => {
+            let code = wait!(ctx.read_u8());
+            let code = ExceptionCode::from_u8(code).ok_or(Error::InvalidData)?;
+            Ok(Some(ResponsePdu::Exception {
+                function: func,
+                code,
+            }))
+        }
+        _ => {
+            let min = std::cmp::min(ctx.remaining(), MAX_DATA_SIZE);
+            let mut data = DataStorage::raw_empty(min);
+            ctx.cursor.copy_to_slice(data.get_mut());
+            Ok(Some(ResponsePdu::raw(func, data)))
+        }
+    }
+}
+
+pub(crate) fn write_pdu(
+    ctx: &mut WriteCtx,
+    src: &ResponsePdu,
+    config: &PduConfig,
+) -> Result<Option<()>, Error> {
     match src {
         ResponsePdu::ReadCoils { data, .. } => {
             ctx.is_enough(data.len() + 2).unwrap();
             ctx.write_u8(0x1).unwrap();
             ctx.write_u8(data.len() as u8).unwrap();
-            ctx.write_bytes(data.get()).unwrap();
+            write_coils(ctx, data, config.coil_order);
             Ok(Some(()))
         }
         ResponsePdu::ReadDiscreteInputs { data, .. } => {
             ctx.is_enough(data.len() + 2).unwrap();
             ctx.write_u8(0x2).unwrap();
             ctx.write_u8(data.len() as u8).unwrap();
-            ctx.write_bytes(data.get()).unwrap();
+            write_coils(ctx, data, config.coil_order);
             Ok(Some(()))
         }
         ResponsePdu::ReadHoldingRegisters { data, .. } => {
@@ -149,6 +515,53 @@ pub(crate) fn write_pdu(ctx: &mut WriteCtx, src: &ResponsePdu) -> Result<Option<
             Ok(Some(()))
         }
 
+        ResponsePdu::ReadExceptionStatus { data } => {
+            ctx.is_enough(2).unwrap();
+            ctx.write_u8(0x7).unwrap();
+            ctx.write_u8(*data).unwrap();
+            Ok(Some(()))
+        }
+        ResponsePdu::ReadFifoQueue { count, data } => {
+            ctx.is_enough(data.len() + 5).unwrap();
+            ctx.write_u8(0x18).unwrap();
+            ctx.write_u16_be(data.len() as u16 + 2).unwrap();
+            ctx.write_u16_be(*count).unwrap();
+            ctx.write_data_u16_be(data.get()).unwrap();
+            Ok(Some(()))
+        }
+        ResponsePdu::ReadWriteMultipleRegisters { data, .. } => {
+            ctx.is_enough(data.len() + 2).unwrap();
+            ctx.write_u8(0x17).unwrap();
+            ctx.write_u8(data.len() as u8).unwrap();
+            ctx.write_data_u16_be(data.get()).unwrap();
+            Ok(Some(()))
+        }
+        ResponsePdu::MaskWriteRegister {
+            address,
+            and_mask,
+            or_mask,
+        } => {
+            ctx.is_enough(7).unwrap();
+            ctx.write_u8(0x16).unwrap();
+            ctx.write_u16_be(*address).unwrap();
+            ctx.write_u16_be(*and_mask).unwrap();
+            ctx.write_u16_be(*or_mask).unwrap();
+            Ok(Some(()))
+        }
+        ResponsePdu::ReportSlaveId { data } => {
+            ctx.is_enough(data.len() + 2).unwrap();
+            ctx.write_u8(0x11).unwrap();
+            ctx.write_u8(data.len() as u8).unwrap();
+            ctx.write_bytes(data.get()).unwrap();
+            Ok(Some(()))
+        }
+        ResponsePdu::Diagnostic { sub_function, data } => {
+            ctx.is_enough(5).unwrap();
+            ctx.write_u8(0x8).unwrap();
+            ctx.write_u16_be(*sub_function).unwrap();
+            ctx.write_u16_be(*data).unwrap();
+            Ok(Some(()))
+        }
         ResponsePdu::WriteMultipleCoils { address, nobjs } => {
             ctx.is_enough(5).unwrap();
             ctx.write_u8(0xF).unwrap();
@@ -168,20 +581,35 @@ pub(crate) fn write_pdu(ctx: &mut WriteCtx, src: &ResponsePdu) -> Result<Option<
         ResponsePdu::Exception { function, code } => {
             ctx.is_enough(2).unwrap();
             ctx.write_u8(*function | 0x80).unwrap();
-            ctx.write_u8(*code as u8).unwrap();
+            ctx.write_u8(u8::from(*code)).unwrap();
             Ok(Some(()))
         }
+        // 0xE and 0xD are both written with the whole payload, the decoder
+        // takes everything up to the end of the PDU
         ResponsePdu::EncapsulatedInterfaceTransport { mei_type, data } => {
-            ctx.is_enough(2).unwrap();
+            ctx.is_enough(data.len() + 2).unwrap();
             ctx.write_u8(0x2b).unwrap();
             ctx.write_u8(*mei_type).unwrap();
-            ctx.write_bytes(data.get());
+            ctx.write_bytes(data.get()).unwrap();
+            Ok(Some(()))
+        }
+        ResponsePdu::Raw { function, data } => {
+            ctx.is_enough(data.len() + 1).unwrap();
+            ctx.write_u8(*function).unwrap();
+            ctx.write_bytes(data.get()).unwrap();
             Ok(Some(()))
         }
-        _ => unreachable!(),
     }
 }
 
+// Keep the whole PDU, so the transport can answer it with an exception
+fn zero_count_write(func: u8, address: u16, nbytes: u8) -> Result<Option<RequestPdu>, Error> {
+    check_matching(0, nbytes as usize)?;
+    let [hi, lo] = address.to_be_bytes();
+    let data = DataStorage::raw(&[hi, lo, 0, 0, 0]);
+    Ok(Some(RequestPdu::raw(func, data)))
+}
+
 fn check_coils_count(nobjs: u16) -> Result<(), Error> {
     if checks::check_coils_count(nobjs) {
         Ok(())
@@ -190,33 +618,64 @@ fn check_coils_count(nobjs: u16) -> Result<(), Error> {
     }
 }
 
-fn check_registers_count(nobjs: u16) -> Result<(), Error> {
-    if checks::check_registers_count(nobjs) {
+fn check_registers_count(nobjs: u16, max: u16) -> Result<(), Error> {
+    if checks::check_registers_count(nobjs) && nobjs <= max {
         Ok(())
     } else {
         Err(Error::InvalidData)
     }
 }
 
-fn check_matching(requested: usize, actual: usize) -> Result<(), Error> {
-    if requested == actual {
+// configurable limits of requests
+fn check_count(nobjs: u16, max: u16) -> Result<(), Error> {
+    if nobjs > 0 && nobjs <= max {
+        Ok(())
+    } else {
+        Err(Error::InvalidData)
+    }
+}
+
+// the last object must be within the 16-bit address space
+fn check_address_range(address: u16, nobjs: u16) -> Result<(), Error> {
+    if address as u32 + nobjs as u32 <= 0x10000 {
+        Ok(())
+    } else {
+        Err(Error::InvalidData)
+    }
+}
+
+fn check_write_bytes(nbytes: u8, limits: &Limits) -> Result<(), Error> {
+    if nbytes <= limits.max_write_bytes {
         Ok(())
     } else {
         Err(Error::InvalidData)
     }
 }
 
-fn check_capacity(requested: usize, dst: &mut Cursor<&mut [u8]>) -> Result<(), Error> {
-    if requested > dst.remaining() {
-        Err(Error::BufferToSmall)
+fn check_coils_bytes(nbytes: u8) -> Result<(), Error> {
+    check_coils_count(nbytes as u16 * 8)
+}
+
+// whole number of registers only
+fn check_registers_bytes(nbytes: u8) -> Result<(), Error> {
+    if nbytes.is_multiple_of(2) {
+        check_registers_count(nbytes as u16 / 2, MAX_NREGS as u16)
     } else {
+        Err(Error::InvalidData)
+    }
+}
+
+fn check_matching(requested: usize, actual: usize) -> Result<(), Error> {
+    if requested == actual {
         Ok(())
+    } else {
+        Err(Error::InvalidData)
     }
 }
 
 fn check_mei_type(mei_type: u8) -> Result<(), Error> {
     match mei_type {
-        0xE | 0xD => Ok(()),
+        0xD | 0xE => Ok(()),
         _ => Err(Error::InvalidData),
     }
 }
@@ -240,13 +699,17 @@ fn coil_to_raw(value: bool) -> u16 {
 
 #[cfg(test)]
 mod test {
-    use super::{read_pdu, write_pdu, Error, ReadCtx, RequestPdu, ResponsePdu, WriteCtx};
+    use super::{
+        read_pdu, write_pdu, BitOrder, Error, PduConfig, ReadCtx, RequestPdu, ResponsePdu, WriteCtx,
+    };
     use crate::data::prelude::*;
     use crate::frame::exception::Code;
     #[test]
     fn read_pdu_fc1() {
         let buffer = [0x01, 0x00, 0x13, 0x00, 0x25];
-        let pdu = read_pdu(&mut ReadCtx::new(&buffer)).unwrap().unwrap();
+        let pdu = read_pdu(&mut ReadCtx::new(&buffer), &PduConfig::default())
+            .unwrap()
+            .unwrap();
         match pdu {
             RequestPdu::ReadCoils { address, nobjs } => {
                 assert_eq!(address, 0x13);
@@ -259,7 +722,9 @@ mod test {
     #[test]
     fn read_pdu_fc2() {
         let buffer = [0x02, 0x00, 0xC4, 0x00, 0x16];
-        let pdu = read_pdu(&mut ReadCtx::new(&buffer)).unwrap().unwrap();
+        let pdu = read_pdu(&mut ReadCtx::new(&buffer), &PduConfig::default())
+            .unwrap()
+            .unwrap();
         match pdu {
             RequestPdu::ReadDiscreteInputs { address, nobjs } => {
                 assert_eq!(address, 0xC4);
@@ -272,7 +737,9 @@ mod test {
     #[test]
     fn read_pdu_fc3() {
         let buffer = [0x03, 0x00, 0x6B, 0x00, 0x03];
-        let pdu = read_pdu(&mut ReadCtx::new(&buffer)).unwrap().unwrap();
+        let pdu = read_pdu(&mut ReadCtx::new(&buffer), &PduConfig::default())
+            .unwrap()
+            .unwrap();
         match pdu {
             RequestPdu::ReadHoldingRegisters { address, nobjs } => {
                 assert_eq!(address, 0x6B);
@@ -285,7 +752,9 @@ mod test {
     #[test]
     fn read_pdu_fc4() {
         let buffer = [0x04, 0x00, 0x08, 0x00, 0x01];
-        let pdu = read_pdu(&mut ReadCtx::new(&buffer)).unwrap().unwrap();
+        let pdu = read_pdu(&mut ReadCtx::new(&buffer), &PduConfig::default())
+            .unwrap()
+            .unwrap();
         match pdu {
             RequestPdu::ReadInputRegisters { address, nobjs } => {
                 assert_eq!(address, 0x8);
@@ -298,11 +767,13 @@ mod test {
     #[test]
     fn read_pdu_fc5() {
         let buffer = [0x05, 0x00, 0xAC, 0xFF, 0x00];
-        let pdu = read_pdu(&mut ReadCtx::new(&buffer)).unwrap().unwrap();
+        let pdu = read_pdu(&mut ReadCtx::new(&buffer), &PduConfig::default())
+            .unwrap()
+            .unwrap();
         match pdu {
             RequestPdu::WriteSingleCoil { address, value } => {
                 assert_eq!(address, 0xAC);
-                assert_eq!(value, true);
+                assert!(value);
             }
             _ => unreachable!(),
         }
@@ -311,7 +782,9 @@ mod test {
     #[test]
     fn read_pdu_fc6() {
         let buffer = [0x06, 0x00, 0x01, 0x00, 0x03];
-        let pdu = read_pdu(&mut ReadCtx::new(&buffer)).unwrap().unwrap();
+        let pdu = read_pdu(&mut ReadCtx::new(&buffer), &PduConfig::default())
+            .unwrap()
+            .unwrap();
         match pdu {
             RequestPdu::WriteSingleRegister { address, value } => {
                 assert_eq!(address, 0x1);
@@ -321,10 +794,144 @@ mod test {
         }
     }
 
+    #[test]
+    fn read_pdu_fc7() {
+        let buffer = [0x07];
+        let pdu = read_pdu(&mut ReadCtx::new(&buffer), &PduConfig::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(pdu, RequestPdu::ReadExceptionStatus);
+        assert_eq!(pdu.len(), 1);
+    }
+
+    #[test]
+    fn write_pdu_fc7() {
+        let mut buffer = [0u8; 2];
+        let pdu = ResponsePdu::read_exception_status(0x6D);
+        write_pdu(&mut WriteCtx::new(&mut buffer), &pdu, &PduConfig::default()).unwrap();
+        assert_eq!(buffer, [0x07, 0x6D]);
+        assert_eq!(pdu.len(), 2);
+    }
+
+    #[test]
+    fn read_pdu_fc8() {
+        let buffer = [0x08, 0x00, 0x00, 0xA5, 0x37];
+        let pdu = read_pdu(&mut ReadCtx::new(&buffer), &PduConfig::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(pdu, RequestPdu::diagnostic(0x0, 0xA537));
+        assert_eq!(
+            read_pdu(&mut ReadCtx::new(&buffer[..4]), &PduConfig::default()),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn read_pdu_fc17() {
+        let pdu = read_pdu(&mut ReadCtx::new(&[0x11]), &PduConfig::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(pdu, RequestPdu::report_slave_id());
+    }
+
+    #[test]
+    fn write_pdu_fc17() {
+        let pdu = ResponsePdu::report_slave_id(&[0x42, 0xFF, b'i', b'd']);
+        let mut buffer = [0u8; 6];
+        write_pdu(&mut WriteCtx::new(&mut buffer), &pdu, &PduConfig::default()).unwrap();
+        assert_eq!(buffer, [0x11, 0x04, 0x42, 0xFF, b'i', b'd']);
+    }
+
+    #[test]
+    fn read_pdu_fc22() {
+        let buffer = [0x16, 0x00, 0x04, 0x00, 0xF2, 0x00, 0x25];
+        let pdu = read_pdu(&mut ReadCtx::new(&buffer), &PduConfig::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(pdu, RequestPdu::mask_write_register(0x4, 0xF2, 0x25));
+        assert_eq!(pdu.len(), buffer.len());
+    }
+
+    #[test]
+    fn read_pdu_fc23() {
+        let buffer = [
+            0x17, 0x00, 0x03, 0x00, 0x06, 0x00, 0x0E, 0x00, 0x03, 0x06, 0x00, 0xFF, 0x00, 0xFF,
+            0x00, 0xFF,
+        ];
+        let pdu = read_pdu(&mut ReadCtx::new(&buffer), &PduConfig::default())
+            .unwrap()
+            .unwrap();
+        match &pdu {
+            RequestPdu::ReadWriteMultipleRegisters {
+                read_address,
+                read_nobjs,
+                write_address,
+                write_nobjs,
+                data,
+            } => {
+                assert_eq!(*read_address, 0x3);
+                assert_eq!(*read_nobjs, 6);
+                assert_eq!(*write_address, 0xE);
+                assert_eq!(*write_nobjs, 3);
+                assert_eq!(data.get_u16(2), Some(0xFF));
+            }
+            _ => unreachable!(),
+        }
+        assert_eq!(pdu.len(), buffer.len());
+
+        // partial write payload
+        for len in 1..buffer.len() {
+            let pdu = read_pdu(&mut ReadCtx::new(&buffer[..len]), &PduConfig::default());
+            assert_eq!(pdu, Ok(None));
+        }
+    }
+
+    #[test]
+    fn read_pdu_fc23_invalid() {
+        // write byte count doesn't match the count
+        let buffer = [0x17, 0x00, 0x03, 0x00, 0x06, 0x00, 0x0E, 0x00, 0x03, 0x04];
+        let pdu = read_pdu(&mut ReadCtx::new(&buffer), &PduConfig::default());
+        assert_eq!(pdu, Err(Error::InvalidData));
+        // too many registers to read
+        let buffer = [0x17, 0x00, 0x03, 0x00, 0x7E, 0x00, 0x0E, 0x00, 0x01, 0x02];
+        let pdu = read_pdu(&mut ReadCtx::new(&buffer), &PduConfig::default());
+        assert_eq!(pdu, Err(Error::InvalidData));
+    }
+
+    #[test]
+    fn write_pdu_fc23() {
+        let pdu = ResponsePdu::read_write_multiple_registers([0xFEu16, 0xACDC].as_slice());
+        let mut buffer = [0u8; 6];
+        write_pdu(&mut WriteCtx::new(&mut buffer), &pdu, &PduConfig::default()).unwrap();
+        assert_eq!(buffer, [0x17, 0x04, 0x00, 0xFE, 0xAC, 0xDC]);
+    }
+
+    #[test]
+    fn read_pdu_fc24() {
+        let buffer = [0x18, 0x04, 0xDE];
+        let pdu = read_pdu(&mut ReadCtx::new(&buffer), &PduConfig::default());
+        assert_eq!(pdu, Ok(Some(RequestPdu::read_fifo_queue(0x04DE))));
+        let pdu = read_pdu(&mut ReadCtx::new(&buffer[..2]), &PduConfig::default());
+        assert_eq!(pdu, Ok(None));
+    }
+
+    #[test]
+    fn write_pdu_fc24() {
+        let pdu = ResponsePdu::read_fifo_queue([0x1B8u16, 0x1284].as_slice());
+        let mut buffer = [0u8; 9];
+        write_pdu(&mut WriteCtx::new(&mut buffer), &pdu, &PduConfig::default()).unwrap();
+        assert_eq!(
+            buffer,
+            [0x18, 0x00, 0x06, 0x00, 0x02, 0x01, 0xB8, 0x12, 0x84]
+        );
+    }
+
     #[test]
     fn read_pdu_fc15() {
         let buffer = [0x0F, 0x00, 0x13, 0x00, 0x0A, 0x02, 0xCD, 0x01];
-        let pdu = read_pdu(&mut ReadCtx::new(&buffer)).unwrap().unwrap();
+        let pdu = read_pdu(&mut ReadCtx::new(&buffer), &PduConfig::default())
+            .unwrap()
+            .unwrap();
         match pdu {
             RequestPdu::WriteMultipleCoils {
                 address,
@@ -342,7 +949,9 @@ mod test {
     #[test]
     fn read_pdu_fc16() {
         let buffer = [0x10, 0x00, 0x01, 0x00, 0x02, 0x04, 0x00, 0x0A, 0x01, 0x02];
-        let pdu = read_pdu(&mut ReadCtx::new(&buffer)).unwrap().unwrap();
+        let pdu = read_pdu(&mut ReadCtx::new(&buffer), &PduConfig::default())
+            .unwrap()
+            .unwrap();
         match pdu {
             RequestPdu::WriteMultipleRegisters {
                 address,
@@ -359,15 +968,80 @@ mod test {
 
     #[test]
     fn read_pdu_0x2b() {
-        let buffer = [0x2B, 0x0E, 0x1];
-        let pdu = read_pdu(&mut ReadCtx::new(&buffer)).unwrap().unwrap();
-        match pdu {
+        let buffer = [0x2B, 0x0E, 0x1, 0x2];
+        let pdu = read_pdu(&mut ReadCtx::new(&buffer), &PduConfig::default())
+            .unwrap()
+            .unwrap();
+        match &pdu {
             RequestPdu::EncapsulatedInterfaceTransport { mei_type, data } => {
-                assert_eq!(mei_type, 0xE);
+                assert_eq!(*mei_type, 0xE);
                 assert_eq!(data.get_u8(0).unwrap(), 0x1);
             }
             _ => unreachable!(),
         };
+        assert_eq!(pdu.device_id_request(), Some((0x1, 0x2)));
+
+        // object id is missing
+        let res = read_pdu(&mut ReadCtx::new(&buffer[..3]), &PduConfig::default());
+        assert_eq!(res, Ok(None));
+    }
+
+    #[test]
+    fn coils_msb_first() {
+        let msb = PduConfig {
+            coil_order: BitOrder::MsbFirst,
+            ..Default::default()
+        };
+        let coils = [true, true, false, false, false, false, false, false, true];
+        let pdu = ResponsePdu::read_coils(coils.as_slice());
+
+        let mut buffer = [0u8; 4];
+        write_pdu(&mut WriteCtx::new(&mut buffer), &pdu, &PduConfig::default()).unwrap();
+        assert_eq!(buffer, [0x1, 0x2, 0x03, 0x01]);
+
+        let mut buffer = [0u8; 4];
+        write_pdu(&mut WriteCtx::new(&mut buffer), &pdu, &msb).unwrap();
+        assert_eq!(buffer, [0x1, 0x2, 0xC0, 0x80]);
+
+        let buffer = [0x0F, 0x00, 0x01, 0x00, 0x09, 0x02, 0xC0, 0x80];
+        let pdu = read_pdu(&mut ReadCtx::new(&buffer), &msb).unwrap().unwrap();
+        assert_eq!(pdu, RequestPdu::write_multiple_coils(1, coils.as_slice()));
+        let pdu = read_pdu(&mut ReadCtx::new(&buffer), &PduConfig::default())
+            .unwrap()
+            .unwrap();
+        assert_ne!(pdu, RequestPdu::write_multiple_coils(1, coils.as_slice()));
+    }
+
+    #[test]
+    fn read_pdu_0x2b_strict() {
+        let buffer = [0x2B, 0x0D, 0x1, 0x2, 0x3];
+        let pdu = read_pdu(&mut ReadCtx::new(&buffer), &PduConfig::default())
+            .unwrap()
+            .unwrap();
+        match pdu {
+            RequestPdu::EncapsulatedInterfaceTransport { mei_type, data } => {
+                assert_eq!(mei_type, 0xD);
+                assert_eq!(data.get(), &[0x1, 0x2, 0x3]);
+            }
+            _ => unreachable!(),
+        };
+
+        let strict = PduConfig {
+            strict_mei: true,
+            ..Default::default()
+        };
+        let res = read_pdu(&mut ReadCtx::new(&buffer), &strict);
+        assert_eq!(
+            res,
+            Ok(Some(RequestPdu::raw(
+                0x2B,
+                Data::raw(&[0x0D, 0x1, 0x2, 0x3])
+            )))
+        );
+
+        let buffer = [0x2B, 0x0E, 0x1, 0x0];
+        let res = read_pdu(&mut ReadCtx::new(&buffer), &strict);
+        assert!(res.unwrap().is_some());
     }
 
     #[test]
@@ -416,7 +1090,7 @@ mod test {
 
         for rec in check {
             let mut ctx = ReadCtx::new(rec.as_ref());
-            let res = read_pdu(&mut ctx);
+            let res = read_pdu(&mut ctx, &PduConfig::default());
             assert!(res.unwrap().is_none());
         }
     }
@@ -439,7 +1113,7 @@ mod test {
 
         for rec in check {
             let mut ctx = ReadCtx::new(rec.as_ref());
-            let res = read_pdu(&mut ctx);
+            let res = read_pdu(&mut ctx, &PduConfig::default());
             match res {
                 Err(Error::InvalidData) => {}
                 _ => unreachable!(),
@@ -455,7 +1129,7 @@ mod test {
             data: Data::raw(&[0xCDu8, 0x6B, 0xB2, 0x0E, 0x1B]),
         };
         let mut buffer = [0u8; 7];
-        write_pdu(&mut WriteCtx::new(&mut buffer), &pdu)
+        write_pdu(&mut WriteCtx::new(&mut buffer), &pdu, &PduConfig::default())
             .unwrap()
             .unwrap();
         assert_eq!(buffer, control);
@@ -469,7 +1143,7 @@ mod test {
             data: Data::raw(&[0xAC, 0xDB, 0x35]),
         };
         let mut buffer = [0u8; 5];
-        write_pdu(&mut WriteCtx::new(&mut buffer), &pdu)
+        write_pdu(&mut WriteCtx::new(&mut buffer), &pdu, &PduConfig::default())
             .unwrap()
             .unwrap();
         assert_eq!(buffer, control);
@@ -483,7 +1157,7 @@ mod test {
             data: Data::registers([0xAE41u16, 0x5652, 0x4340].as_ref()),
         };
         let mut buffer = [0u8; 8];
-        write_pdu(&mut WriteCtx::new(&mut buffer), &pdu)
+        write_pdu(&mut WriteCtx::new(&mut buffer), &pdu, &PduConfig::default())
             .unwrap()
             .unwrap();
         assert_eq!(buffer, control);
@@ -497,7 +1171,7 @@ mod test {
             data: Data::registers([0xAu16].as_ref()),
         };
         let mut buffer = [0u8; 4];
-        write_pdu(&mut WriteCtx::new(&mut buffer), &pdu)
+        write_pdu(&mut WriteCtx::new(&mut buffer), &pdu, &PduConfig::default())
             .unwrap()
             .unwrap();
         assert_eq!(buffer, control);
@@ -511,7 +1185,7 @@ mod test {
             value: true,
         };
         let mut buffer = [0u8; 5];
-        write_pdu(&mut WriteCtx::new(&mut buffer), &pdu)
+        write_pdu(&mut WriteCtx::new(&mut buffer), &pdu, &PduConfig::default())
             .unwrap()
             .unwrap();
         assert_eq!(buffer, control);
@@ -525,7 +1199,7 @@ mod test {
             value: 3,
         };
         let mut buffer = [0u8; 5];
-        write_pdu(&mut WriteCtx::new(&mut buffer), &pdu)
+        write_pdu(&mut WriteCtx::new(&mut buffer), &pdu, &PduConfig::default())
             .unwrap()
             .unwrap();
         assert_eq!(buffer, control);
@@ -539,7 +1213,7 @@ mod test {
             nobjs: 0xA,
         };
         let mut buffer = [0u8; 5];
-        write_pdu(&mut WriteCtx::new(&mut buffer), &pdu)
+        write_pdu(&mut WriteCtx::new(&mut buffer), &pdu, &PduConfig::default())
             .unwrap()
             .unwrap();
         assert_eq!(buffer, control);
@@ -553,7 +1227,7 @@ mod test {
             nobjs: 0x2,
         };
         let mut buffer = [0u8; 5];
-        write_pdu(&mut WriteCtx::new(&mut buffer), &pdu)
+        write_pdu(&mut WriteCtx::new(&mut buffer), &pdu, &PduConfig::default())
             .unwrap()
             .unwrap();
         assert_eq!(buffer, control);
@@ -567,7 +1241,7 @@ mod test {
             code: Code::IllegalDataAddress,
         };
         let mut buffer = [0u8; 2];
-        write_pdu(&mut WriteCtx::new(&mut buffer), &pdu)
+        write_pdu(&mut WriteCtx::new(&mut buffer), &pdu, &PduConfig::default())
             .unwrap()
             .unwrap();
         assert_eq!(buffer, control);
@@ -584,9 +1258,31 @@ mod test {
         };
 
         let mut buffer = [0u8; 13];
-        write_pdu(&mut WriteCtx::new(&mut buffer), &pdu)
+        write_pdu(&mut WriteCtx::new(&mut buffer), &pdu, &PduConfig::default())
             .unwrap()
             .unwrap();
         assert_eq!(buffer, control);
     }
+
+    #[test]
+    fn read_pdu_address_overflow() {
+        let decode = |buffer: &[u8]| read_pdu(&mut ReadCtx::new(buffer), &PduConfig::default());
+        for func in [0x1, 0x2, 0x3, 0x4] {
+            assert!(decode(&[func, 0xFF, 0xF6, 0x00, 0x0A]).unwrap().is_some());
+            assert_eq!(
+                decode(&[func, 0xFF, 0xF7, 0x00, 0x0A]),
+                Err(Error::InvalidData)
+            );
+        }
+
+        let coils = [0x0F, 0xFF, 0xF6, 0x00, 0x0A, 0x02, 0xFF, 0x03];
+        assert!(decode(&coils).unwrap().is_some());
+        let coils = [0x0F, 0xFF, 0xF7, 0x00, 0x0A, 0x02, 0xFF, 0x03];
+        assert_eq!(decode(&coils), Err(Error::InvalidData));
+
+        let registers = [0x10, 0xFF, 0xFF, 0x00, 0x01, 0x02, 0x00, 0x01];
+        assert!(decode(&registers).unwrap().is_some());
+        let registers = [0x10, 0xFF, 0xFF, 0x00, 0x02, 0x04, 0x00, 0x01, 0x00, 0x02];
+        assert_eq!(decode(&registers), Err(Error::InvalidData));
+    }
 }
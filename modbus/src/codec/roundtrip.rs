@@ -0,0 +1,268 @@
+//! Table driven checks of the wire format for every supported function.
+//! Each PDU is checked with both RTU and Net framings. Requests are checked
+//! with the test vectors.
+use crate::codec::context::WriteCtx;
+use crate::codec::error::Error;
+use crate::codec::master::MasterCodec;
+use crate::codec::pduext::{decode_response_pdu, write_pdu, PduConfig};
+use crate::codec::slave::SlaveCodec;
+use crate::codec::vectors::{net_bytes, rtu_bytes, ID, SLAVE};
+use crate::data::helpers;
+use crate::data::prelude::*;
+use crate::frame::prelude::*;
+use bytes::BytesMut;
+use tokio_util::codec::{Decoder, Encoder};
+
+fn responses() -> Vec<(ResponsePdu, Vec<u8>)> {
+    let fc1 = helpers::bits_from_bytes(&[0xCD, 0x6B, 0xB2, 0x0E, 0x1B], 37);
+    let fc2 = helpers::bits_from_bytes(&[0xAC, 0xDB, 0x35], 22);
+    vec![
+        (
+            ResponsePdu::read_coils(fc1.as_slice()),
+            vec![0x01, 0x05, 0xCD, 0x6B, 0xB2, 0x0E, 0x1B],
+        ),
+        (
+            ResponsePdu::read_discrete_inputs(fc2.as_slice()),
+            vec![0x02, 0x03, 0xAC, 0xDB, 0x35],
+        ),
+        (
+            ResponsePdu::read_holding_registers([0x022Bu16, 0x0, 0x64].as_slice()),
+            vec![0x03, 0x06, 0x02, 0x2B, 0x00, 0x00, 0x00, 0x64],
+        ),
+        (
+            ResponsePdu::read_input_registers([0x000Au16].as_slice()),
+            vec![0x04, 0x02, 0x00, 0x0A],
+        ),
+        (
+            ResponsePdu::write_single_coil(0xAC, true),
+            vec![0x05, 0x00, 0xAC, 0xFF, 0x00],
+        ),
+        (
+            ResponsePdu::write_single_register(0x1, 0x3),
+            vec![0x06, 0x00, 0x01, 0x00, 0x03],
+        ),
+        (ResponsePdu::read_exception_status(0x6D), vec![0x07, 0x6D]),
+        (
+            ResponsePdu::diagnostic(0x0, 0xA537),
+            vec![0x08, 0x00, 0x00, 0xA5, 0x37],
+        ),
+        (
+            ResponsePdu::write_multiple_coils(0x13, 0xA),
+            vec![0x0F, 0x00, 0x13, 0x00, 0x0A],
+        ),
+        (
+            ResponsePdu::write_multiple_registers(0x1, 0x2),
+            vec![0x10, 0x00, 0x01, 0x00, 0x02],
+        ),
+        (
+            ResponsePdu::report_slave_id(&[0x42, 0xFF, b'i', b'd']),
+            vec![0x11, 0x04, 0x42, 0xFF, b'i', b'd'],
+        ),
+        (
+            ResponsePdu::mask_write_register(0x4, 0xF2, 0x25),
+            vec![0x16, 0x00, 0x04, 0x00, 0xF2, 0x00, 0x25],
+        ),
+        (
+            ResponsePdu::read_write_multiple_registers(
+                [0xFEu16, 0xACDC, 0x9FE, 0x0, 0x0, 0x0].as_slice(),
+            ),
+            vec![
+                0x17, 0x0C, 0x00, 0xFE, 0xAC, 0xDC, 0x09, 0xFE, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            ],
+        ),
+        (
+            ResponsePdu::read_fifo_queue([0x1B8u16, 0x1284].as_slice()),
+            vec![0x18, 0x00, 0x06, 0x00, 0x02, 0x01, 0xB8, 0x12, 0x84],
+        ),
+        (
+            ResponsePdu::read_fifo_queue(&[]),
+            vec![0x18, 0x00, 0x02, 0x00, 0x00],
+        ),
+        (
+            ResponsePdu::encapsulated_interface_transport(0xE, &[0x01, 0x01, 0x00, 0x00]),
+            vec![0x2B, 0x0E, 0x01, 0x01, 0x00, 0x00],
+        ),
+        (
+            ResponsePdu::read_device_identification(&[
+                (0x0, b"Company".as_slice()),
+                (0x1, b"PC-1".as_slice()),
+                (0x2, b"V2.1".as_slice()),
+            ]),
+            vec![
+                0x2B, 0x0E, 0x01, 0x01, 0x00, 0x00, 0x03, 0x00, 0x07, b'C', b'o', b'm', b'p', b'a',
+                b'n', b'y', 0x01, 0x04, b'P', b'C', b'-', b'1', 0x02, 0x04, b'V', b'2', b'.', b'1',
+            ],
+        ),
+        (
+            DeviceId::new(0x4)
+                .with_conformity(0x81)
+                .with_more_follows(0x2)
+                .with_object(0x1, b"PC-1")
+                .build(),
+            vec![
+                0x2B, 0x0E, 0x04, 0x81, 0xFF, 0x02, 0x01, 0x01, 0x04, b'P', b'C', b'-', b'1',
+            ],
+        ),
+        (
+            ResponsePdu::encapsulated_interface_transport(0xD, &[0x01, 0x20, 0x00, 0x0B, 0x7F]),
+            vec![0x2B, 0x0D, 0x01, 0x20, 0x00, 0x0B, 0x7F],
+        ),
+        (
+            ResponsePdu::exception(0x3, ExceptionCode::IllegalDataAddress),
+            vec![0x83, 0x02],
+        ),
+        (
+            ResponsePdu::Exception {
+                function: 0x83,
+                code: ExceptionCode::IllegalDataAddress,
+            },
+            vec![0x83, 0x02],
+        ),
+        (
+            ResponsePdu::exception(0x3, ExceptionCode::Unknown(0x0C)),
+            vec![0x83, 0x0C],
+        ),
+        (
+            ResponsePdu::raw(0x41, Data::raw(&[0x01, 0x02, 0x03])),
+            vec![0x41, 0x01, 0x02, 0x03],
+        ),
+    ]
+}
+
+#[test]
+fn responses_rtu() {
+    for (pdu, bytes) in responses() {
+        let mut output = BytesMut::new();
+        let frame = ResponseFrame::new(SLAVE, pdu);
+        SlaveCodec::new_rtu().encode(frame, &mut output).unwrap();
+        assert_eq!(&output[..], &rtu_bytes(&bytes)[..]);
+    }
+}
+
+#[test]
+fn responses_net() {
+    for (pdu, bytes) in responses() {
+        let mut output = BytesMut::new();
+        let frame = ResponseFrame::from_parts(ID, SLAVE, pdu);
+        SlaveCodec::new_tcp().encode(frame, &mut output).unwrap();
+        assert_eq!(&output[..], &net_bytes(&bytes)[..]);
+    }
+}
+
+fn encode_pdu(pdu: &ResponsePdu) -> Vec<u8> {
+    let mut output = vec![0u8; pdu.len()];
+    write_pdu(&mut WriteCtx::new(&mut output), pdu, &PduConfig::default()).unwrap();
+    output
+}
+
+#[test]
+fn responses_decode() {
+    for (pdu, bytes) in responses() {
+        let decoded = decode_response_pdu(&bytes).unwrap().unwrap();
+        assert_eq!(encode_pdu(&decoded), bytes);
+        match pdu {
+            // the coils count isn't on the wire
+            ResponsePdu::ReadCoils { .. } | ResponsePdu::ReadDiscreteInputs { .. } => {}
+            pdu => assert_eq!(decoded, pdu),
+        }
+
+        // partial PDU. Raw and 0x2b are bounded by the buffer only
+        let bounded = matches!(
+            decoded,
+            ResponsePdu::Raw { .. } | ResponsePdu::EncapsulatedInterfaceTransport { .. }
+        );
+        for len in 0..bytes.len() {
+            if !bounded {
+                assert_eq!(decode_response_pdu(&bytes[..len]), Ok(None));
+            }
+        }
+    }
+}
+
+#[test]
+fn responses_decode_invalid() {
+    // odd byte count of registers
+    let bytes = [0x03, 0x05, 0x00, 0x01, 0x00, 0x02, 0x00];
+    assert_eq!(decode_response_pdu(&bytes), Err(Error::InvalidData));
+    // no data
+    assert_eq!(decode_response_pdu(&[0x03, 0x00]), Err(Error::InvalidData));
+    assert_eq!(decode_response_pdu(&[0x01, 0x00]), Err(Error::InvalidData));
+    // FIFO count above 31 and byte count not matching the FIFO count
+    let bytes = [0x18, 0x00, 0x42, 0x00, 0x20];
+    assert_eq!(decode_response_pdu(&bytes), Err(Error::InvalidData));
+    let bytes = [0x18, 0x00, 0x04, 0x00, 0x02, 0x01, 0xB8, 0x12, 0x84];
+    assert_eq!(decode_response_pdu(&bytes), Err(Error::InvalidData));
+    // zero is not an exception code
+    assert_eq!(decode_response_pdu(&[0x83, 0x00]), Err(Error::InvalidData));
+}
+
+#[test]
+fn raw_net() {
+    // unknown function is bounded by the MBAP length, so the next frame is intact
+    let raw = [0x41, 0x01, 0x02, 0x03];
+    let mut input = BytesMut::from(&net_bytes(&raw)[..]);
+    input.extend_from_slice(&net_bytes(&[0x03, 0x00, 0x6B, 0x00, 0x03]));
+
+    let mut codec = SlaveCodec::new_tcp();
+    let frame = codec.decode(&mut input).unwrap().unwrap();
+    assert_eq!(
+        frame.pdu,
+        RequestPdu::raw(0x41, Data::raw(&[0x01, 0x02, 0x03]))
+    );
+    let frame = codec.decode(&mut input).unwrap().unwrap();
+    assert_eq!(frame.pdu, RequestPdu::read_holding_registers(0x6B, 3));
+    assert!(input.is_empty());
+
+    // wait for the whole PDU
+    let bytes = net_bytes(&raw);
+    let mut input = BytesMut::from(&bytes[..bytes.len() - 1]);
+    assert_eq!(codec.decode(&mut input), Ok(None));
+    input.extend_from_slice(&bytes[bytes.len() - 1..]);
+    let frame = codec.decode(&mut input).unwrap().unwrap();
+
+    // echo it back
+    let pdu = match frame.pdu {
+        RequestPdu::Raw { function, data } => ResponsePdu::raw(function, data),
+        _ => unreachable!(),
+    };
+    let mut output = BytesMut::new();
+    let frame = ResponseFrame::from_parts(ID, SLAVE, pdu);
+    SlaveCodec::new_tcp().encode(frame, &mut output).unwrap();
+    assert_eq!(&output[..], &net_bytes(&raw)[..]);
+}
+
+#[test]
+fn canopen_frames() {
+    // CANopen payload takes the rest of the PDU, CRC must not be part of it
+    let payload = [0x01, 0x20, 0x00, 0x0B, 0x00, 0x7F, 0x12];
+    let request = RequestPdu::encapsulated_interface_transport(0xD, payload.as_slice());
+    let response = ResponsePdu::encapsulated_interface_transport(0xD, payload.as_slice());
+    let codecs = [
+        (MasterCodec::new_rtu(), SlaveCodec::new_rtu(), 0),
+        (MasterCodec::new_tcp(), SlaveCodec::new_tcp(), ID),
+    ];
+    for (mut master, mut slave, id) in codecs {
+        let mut wire = BytesMut::new();
+        let frame = RequestFrame::from_parts(id, SLAVE, request.clone());
+        master.encode(frame, &mut wire).unwrap();
+        let len = wire.len();
+        // nothing until the last byte
+        let mut input = BytesMut::from(&wire[..len - 1]);
+        assert_eq!(slave.decode(&mut input), Ok(None));
+        input.extend_from_slice(&wire[len - 1..]);
+        let decoded = slave.decode(&mut input).unwrap().unwrap();
+        assert_eq!(decoded.pdu, request);
+        assert!(input.is_empty());
+
+        let mut wire = BytesMut::new();
+        let frame = ResponseFrame::from_parts(id, SLAVE, response.clone());
+        slave.encode(frame, &mut wire).unwrap();
+        let len = wire.len();
+        let mut input = BytesMut::from(&wire[..len - 1]);
+        assert_eq!(master.decode(&mut input), Ok(None));
+        input.extend_from_slice(&wire[len - 1..]);
+        let decoded = master.decode(&mut input).unwrap().unwrap();
+        assert_eq!(decoded.pdu, response);
+        assert!(input.is_empty());
+    }
+}
@@ -1,9 +1,13 @@
+use crate::codec::asciiext::{hex_byte, read_ascii_frame, write_ascii_frame};
 use crate::codec::context::{ReadCtx, WriteCtx};
 use crate::codec::error::Error;
-use crate::codec::mbap::{read_mbap, write_mbap};
-use crate::codec::pduext::{read_pdu, write_pdu};
+use crate::codec::mbap::{read_mbap, write_mbap, Mbap};
+pub use crate::codec::pduext::Limits;
+use crate::codec::pduext::{is_known_function, read_pdu, write_pdu, PduConfig};
 use crate::codec::rtuext::calc_crc_be;
 use crate::codec::wait;
+use crate::data::coils::BitOrder;
+use crate::data::{prelude::Data, MAX_DATA_SIZE, MAX_PDU_SIZE};
 
 use crate::frame::prelude::*;
 use bytes::{Buf, BytesMut};
@@ -22,7 +26,7 @@ fn resize_buffer(dst: &mut BytesMut, size: usize) {
     dst.resize(size, 0);
 }
 
-fn read_crc(ctx: &mut ReadCtx) -> Result<Option<u16>, Error> {
+pub(crate) fn read_crc(ctx: &mut ReadCtx) -> Result<Option<u16>, Error> {
     let crc = wait!(ctx.read_u16_be());
     let end = ctx.processed();
     let calc = calc_crc_be(&ctx.buffer[..end]);
@@ -33,30 +37,142 @@ fn read_crc(ctx: &mut ReadCtx) -> Result<Option<u16>, Error> {
     }
 }
 
-fn write_crc(ctx: &mut WriteCtx) -> Result<Option<u16>, Error> {
+pub(crate) fn write_crc(ctx: &mut WriteCtx) -> Result<Option<u16>, Error> {
     let data = &ctx.buffer()[..ctx.processed()];
     let crc = calc_crc_be(data);
     ctx.write_u16_be(crc).unwrap();
     Ok(Some(crc))
 }
 
-fn read_rtu_frame(ctx: &mut ReadCtx) -> Result<Option<RequestFrame>, Error> {
+fn read_rtu_frame(ctx: &mut ReadCtx, config: &PduConfig) -> Result<Option<RequestFrame>, Error> {
     let slave = wait!(read_u8(ctx)?); // else { return Ok(None) };
-    let pdu = wait!(read_pdu(ctx)?);
+    if let Some(func) = ctx.buffer.get(1).filter(|func| !is_known_function(**func)) {
+        return read_rtu_raw(ctx, slave, *func);
+    }
+    if let [_, 0x2b, 0xD, ..] = ctx.buffer {
+        return read_rtu_canopen(ctx, slave, config);
+    }
+    let pdu = wait!(read_pdu(ctx, config)?);
     let _ = wait!(read_crc(ctx)?);
     Ok(Some(RequestFrame::from_parts(0, slave, pdu)))
 }
 
-fn write_rtu_frame(ctx: &mut WriteCtx, frame: &ResponseFrame) -> Result<(), Error> {
+// 0x2b/0xD (CANopen) payload takes the rest of the PDU. As for Raw, the frame
+// is the whole input if it ends with a valid CRC
+fn read_rtu_canopen(
+    ctx: &mut ReadCtx,
+    slave: u8,
+    config: &PduConfig,
+) -> Result<Option<RequestFrame>, Error> {
+    let buffer = ctx.buffer;
+    if buffer.len() < 5 || calc_crc_be(buffer) != 0 {
+        return Ok(None);
+    }
+    // slave, function, MEI type and CRC aren't payload
+    if buffer.len() - 5 > MAX_DATA_SIZE {
+        return Err(Error::InvalidData);
+    }
+    let mut pdu_ctx = ReadCtx::new(&buffer[1..buffer.len() - 2]);
+    let pdu = read_pdu(&mut pdu_ctx, config)?.ok_or(Error::InvalidData)?;
+    ctx.cursor.set_position(buffer.len() as u64);
+    Ok(Some(RequestFrame::from_parts(0, slave, pdu)))
+}
+
+// Unknown functions don't describe their size. The frame is the whole input
+// if it ends with a valid CRC, otherwise wait for more bytes
+fn read_rtu_raw(ctx: &mut ReadCtx, slave: u8, func: u8) -> Result<Option<RequestFrame>, Error> {
+    let buffer = ctx.buffer;
+    if buffer.len() < 4 || calc_crc_be(buffer) != 0 {
+        return Ok(None);
+    }
+    let data = &buffer[2..buffer.len() - 2];
+    if data.len() > MAX_DATA_SIZE {
+        return Err(Error::InvalidData);
+    }
+    ctx.cursor.set_position(buffer.len() as u64);
+    Ok(Some(RequestFrame::from_parts(
+        0,
+        slave,
+        RequestPdu::raw(func, Data::raw(data)),
+    )))
+}
+
+// Skip up to `zeros` stray 0x00 bytes before the slave id. The frame without
+// them is accepted only if it's complete and its CRC is valid
+fn read_rtu_frame_lenient(
+    ctx: &mut ReadCtx,
+    config: &PduConfig,
+    zeros: usize,
+) -> Result<Option<RequestFrame>, Error> {
+    let strict = read_rtu_frame(ctx, config);
+    if matches!(strict, Ok(Some(_))) {
+        return strict;
+    }
+    let buffer = ctx.buffer;
+    let mut res = strict;
+    for skip in 1..=zeros {
+        if buffer.get(skip - 1) != Some(&0) {
+            break;
+        }
+        let mut frame_ctx = ReadCtx::new(&buffer[skip..]);
+        match read_rtu_frame(&mut frame_ctx, config) {
+            Ok(Some(frame)) => {
+                ctx.cursor
+                    .set_position((skip + frame_ctx.processed()) as u64);
+                return Ok(Some(frame));
+            }
+            // the shifted frame may still arrive, don't drop the input
+            Ok(None) if res.is_err() => res = Ok(None),
+            _ => {}
+        }
+    }
+    res
+}
+
+fn write_rtu_frame(
+    ctx: &mut WriteCtx,
+    frame: &ResponseFrame,
+    config: &PduConfig,
+) -> Result<(), Error> {
     write_u8(ctx, frame.slave).unwrap();
-    write_pdu(ctx, &frame.pdu).unwrap();
+    write_pdu(ctx, &frame.pdu, config).unwrap();
     write_crc(ctx).unwrap();
     Ok(())
 }
 
-fn read_net_frame(ctx: &mut ReadCtx) -> Result<Option<RequestFrame>, Error> {
+// Byte count of fc15/fc16 requests must agree with the MBAP length. Otherwise
+// the frame is crafted/broken and waiting for the rest of it makes no sense
+fn check_write_len(ctx: &ReadCtx, header: &Mbap) -> Result<(), Error> {
+    let pdu = &ctx.buffer[ctx.processed()..];
+    match pdu {
+        [0xF | 0x10, _, _, _, _, nbytes, ..] if header.len != 7 + *nbytes as u16 => {
+            Err(Error::InvalidData)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn read_net_frame(ctx: &mut ReadCtx, config: &PduConfig) -> Result<Option<RequestFrame>, Error> {
     let header = wait!(read_mbap(ctx)?);
-    let pdu = wait!(read_pdu(ctx)?);
+    check_write_len(ctx, &header)?;
+
+    // MBAP length bounds the PDU. It makes possible to frame PDUs of unknown
+    // functions (Raw) that don't describe their own size
+    let start = ctx.processed();
+    let end = start + header.len as usize - 1;
+    let complete = ctx.buffer.len() >= end;
+    let mut pdu_ctx = ReadCtx::new(&ctx.buffer[start..end.min(ctx.buffer.len())]);
+    let pdu = match read_pdu(&mut pdu_ctx, config)? {
+        Some(RequestPdu::Raw { .. }) if !complete => return Ok(None),
+        Some(RequestPdu::EncapsulatedInterfaceTransport { mei_type: 0xD, .. }) if !complete => {
+            return Ok(None)
+        }
+        Some(pdu) => pdu,
+        None if complete => return Err(Error::InvalidData),
+        None => return Ok(None),
+    };
+    ctx.cursor.advance(pdu_ctx.processed());
+
     Ok(Some(RequestFrame {
         id: header.id,
         slave: header.slave,
@@ -64,10 +180,14 @@ fn read_net_frame(ctx: &mut ReadCtx) -> Result<Option<RequestFrame>, Error> {
     }))
 }
 
-fn write_net_frame(ctx: &mut WriteCtx, frame: &ResponseFrame) -> Result<(), Error> {
-    write_mbap(ctx, frame).unwrap();
+fn write_net_frame(
+    ctx: &mut WriteCtx,
+    frame: &ResponseFrame,
+    config: &PduConfig,
+) -> Result<(), Error> {
+    write_mbap(ctx, frame)?;
     write_u8(ctx, frame.slave).unwrap();
-    write_pdu(ctx, &frame.pdu).unwrap();
+    write_pdu(ctx, &frame.pdu, config).unwrap();
     Ok(())
 }
 
@@ -76,20 +196,21 @@ fn frame_ok<T, E>(frame: &Result<Option<T>, E>) -> bool {
 }
 
 fn frame_err<T, E>(frame: &Result<Option<T>, E>) -> bool {
-    matches!(frame, Err(_))
+    frame.is_err()
 }
 
 fn frame_in_prog<T, E>(frame: &Result<Option<T>, E>) -> bool {
     matches!(frame, Ok(None))
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum CodecMode {
     Rtu,
     Net,
+    Ascii,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum CodecFlowType {
     Packet,
     Stream,
@@ -101,9 +222,53 @@ impl CodecFlowType {
     }
 }
 
+/// Read slave and function code of a request frame without decoding the PDU.
+/// Returns None if the buffer is too short
+pub fn peek_slave_and_func(mode: &CodecMode, bytes: &[u8]) -> Option<(u8, u8)> {
+    match (mode, bytes) {
+        (CodecMode::Rtu, [slave, func, ..]) => Some((*slave, *func)),
+        (CodecMode::Net, [_, _, _, _, _, _, slave, func, ..]) => Some((*slave, *func)),
+        (CodecMode::Ascii, [b':', s1, s2, f1, f2, ..]) => {
+            Some((hex_byte(*s1, *s2)?, hex_byte(*f1, *f2)?))
+        }
+        _ => None,
+    }
+}
+
+/// Total length of the RTU request frame (slave + PDU + CRC) if the buffer
+/// holds it completely. The CRC is not checked, except for 0x2b/0xD frames:
+/// their size isn't on the wire, so they are complete if the whole buffer
+/// ends with a valid CRC
+pub fn rtu_frame_complete(bytes: &[u8]) -> Option<usize> {
+    if let [_, 0x2b, 0xD, ..] = bytes {
+        let payload = bytes.len().checked_sub(5)?;
+        let valid = payload > 0 && payload <= MAX_DATA_SIZE && calc_crc_be(bytes) == 0;
+        return valid.then_some(bytes.len());
+    }
+    let mut ctx = ReadCtx::new(bytes);
+    ctx.read_u8()?;
+    let pdu = read_pdu(&mut ctx, &PduConfig::default()).ok()??;
+    let len = 1 + pdu.len() + 2;
+    (bytes.len() >= len).then_some(len)
+}
+
+/// Decode one request frame from the start of `bytes`. Ok(None) if the frame
+/// is incomplete. Never panics on arbitrary input, so it's usable as a fuzz target
+pub fn decode_request(mode: CodecMode, bytes: &[u8]) -> Result<Option<RequestFrame>, Error> {
+    let mut codec = match mode {
+        CodecMode::Rtu => SlaveCodec::new_rtu(),
+        CodecMode::Net => SlaveCodec::new_tcp(),
+        CodecMode::Ascii => SlaveCodec::new_ascii(),
+    };
+    codec.decode(&mut BytesMut::from(bytes))
+}
+
+#[derive(Clone)]
 pub struct SlaveCodec {
     mode: CodecMode,
     data: CodecFlowType,
+    config: PduConfig,
+    rtu_leading_zeros: usize,
 }
 
 impl SlaveCodec {
@@ -111,6 +276,8 @@ impl SlaveCodec {
         SlaveCodec {
             mode: CodecMode::Rtu,
             data: CodecFlowType::Stream,
+            config: PduConfig::default(),
+            rtu_leading_zeros: 0,
         }
     }
 
@@ -118,6 +285,18 @@ impl SlaveCodec {
         SlaveCodec {
             mode: CodecMode::Net,
             data: CodecFlowType::Stream,
+            config: PduConfig::default(),
+            rtu_leading_zeros: 0,
+        }
+    }
+
+    /// Modbus ASCII: ':', hex of slave + PDU + LRC, CRLF
+    pub fn new_ascii() -> SlaveCodec {
+        SlaveCodec {
+            mode: CodecMode::Ascii,
+            data: CodecFlowType::Stream,
+            config: PduConfig::default(),
+            rtu_leading_zeros: 0,
         }
     }
 
@@ -125,9 +304,66 @@ impl SlaveCodec {
         SlaveCodec {
             mode: CodecMode::Net,
             data: CodecFlowType::Packet,
+            config: PduConfig::default(),
+            rtu_leading_zeros: 0,
         }
     }
-    fn advance_buffer(
+
+    pub fn mode(&self) -> &CodecMode {
+        &self.mode
+    }
+
+    /// accept only Read Device Identification (0xE) in 0x2b requests. 0xD
+    /// requests are decoded as Raw PDUs, so they can be answered with
+    /// IllegalDataValue. By default 0xD is accepted too
+    pub fn with_strict_mei(mut self, strict: bool) -> SlaveCodec {
+        self.config.strict_mei = strict;
+        self
+    }
+
+    /// order of coils in fc1/fc2 responses and fc15 requests. LSB first by default
+    pub fn with_coil_order(mut self, order: BitOrder) -> SlaveCodec {
+        self.config.coil_order = order;
+        self
+    }
+
+    /// skip up to `zeros` leading 0x00 bytes (line noise) before the slave id
+    /// if the rest of the RTU frame has a valid CRC. Strict (0) by default
+    pub fn with_rtu_leading_zeros(mut self, zeros: usize) -> SlaveCodec {
+        self.rtu_leading_zeros = zeros;
+        self
+    }
+
+    /// decode fc15/fc16 with zero count as Raw PDUs instead of failing, so
+    /// they can be answered with an exception. Off by default
+    pub fn with_zero_count_writes(mut self, enable: bool) -> SlaveCodec {
+        self.config.zero_count_writes = enable;
+        self
+    }
+
+    /// max. number of objects in requests. Spec values by default. Values
+    /// above them are clamped, since responses couldn't carry the objects
+    pub fn with_limits(mut self, limits: Limits) -> SlaveCodec {
+        self.config.limits = limits.clamp_to_spec();
+        self
+    }
+
+    /// decode a frame without touching the input. Returns the result and the
+    /// number of used bytes, see advance_buffer
+    pub(crate) fn read(&mut self, src: &[u8]) -> (Result<Option<RequestFrame>, Error>, usize) {
+        let mut ctx = ReadCtx::new(src);
+        let res = match self.mode {
+            CodecMode::Rtu => {
+                read_rtu_frame_lenient(&mut ctx, &self.config, self.rtu_leading_zeros)
+            }
+            CodecMode::Net => read_net_frame(&mut ctx, &self.config),
+            CodecMode::Ascii => read_ascii_frame(&mut ctx, &self.config),
+        };
+        (res, ctx.processed())
+    }
+
+    /// drop the used bytes of a decoded frame or the whole input on errors
+    pub(crate) fn advance_buffer(
         &self,
         src: &mut BytesMut,
         msg: &Result<Option<RequestFrame>, Error>,
@@ -149,13 +385,8 @@ impl Decoder for SlaveCodec {
     type Error = Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        let mut ctx = ReadCtx::new(src);
-        let res = match self.mode {
-            CodecMode::Rtu => read_rtu_frame(&mut ctx),
-            CodecMode::Net => read_net_frame(&mut ctx),
-        };
-
-        self.advance_buffer(src, &res, ctx.processed());
+        let (res, processed) = self.read(src);
+        self.advance_buffer(src, &res, processed);
         res
     }
 }
@@ -163,15 +394,20 @@ impl Decoder for SlaveCodec {
 impl Encoder<ResponseFrame> for SlaveCodec {
     type Error = Error;
     fn encode(&mut self, frame: ResponseFrame, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        // an oversized PDU (e.g. a long Raw answer) can't be framed. Drop it
+        if frame.pdu.len() > MAX_PDU_SIZE {
+            return Err(Error::InvalidData);
+        }
         let res = match self.mode {
             CodecMode::Rtu => {
-                resize_buffer(dst, frame.pdu.len() + 3);
-                write_rtu_frame(&mut WriteCtx::new(dst.as_mut()), &frame)
+                resize_buffer(dst, frame.wire_len(&self.mode));
+                write_rtu_frame(&mut WriteCtx::new(dst.as_mut()), &frame, &self.config)
             }
             CodecMode::Net => {
-                resize_buffer(dst, frame.pdu.len() + 7);
-                write_net_frame(&mut WriteCtx::new(dst.as_mut()), &frame)
+                resize_buffer(dst, frame.wire_len(&self.mode));
+                write_net_frame(&mut WriteCtx::new(dst.as_mut()), &frame, &self.config)
             }
+            CodecMode::Ascii => write_ascii_frame(dst, &frame, &self.config),
         };
         res
     }
@@ -179,12 +415,13 @@ impl Encoder<ResponseFrame> for SlaveCodec {
 
 #[cfg(test)]
 mod test {
-    use super::SlaveCodec;
+    use super::{decode_request, CodecMode, Limits, SlaveCodec};
     use super::{
-        read_mbap, read_net_frame, read_rtu_frame, write_crc, Error, ReadCtx, ResponseFrame,
-        WriteCtx,
+        peek_slave_and_func, read_mbap, read_net_frame, read_rtu_frame, rtu_frame_complete,
+        write_crc, Error, PduConfig, ReadCtx, ResponseFrame, WriteCtx,
     };
     use crate::data::coils::CoilsSlice;
+    use crate::data::prelude::Data;
     use crate::frame::prelude::*;
     use bytes::{Buf, BytesMut};
     use tokio_util::codec::{Decoder, Encoder};
@@ -192,7 +429,7 @@ mod test {
     #[test]
     fn read_rtu_frame_empty() {
         let buffer = [];
-        let frame = read_rtu_frame(&mut ReadCtx::new(&buffer));
+        let frame = read_rtu_frame(&mut ReadCtx::new(&buffer), &PduConfig::default());
         assert!(frame.is_ok());
         assert!(frame.unwrap().is_none());
     }
@@ -200,7 +437,7 @@ mod test {
     #[test]
     fn read_rtu_frame_short1() {
         let buffer = [0x1];
-        let frame = read_rtu_frame(&mut ReadCtx::new(&buffer));
+        let frame = read_rtu_frame(&mut ReadCtx::new(&buffer), &PduConfig::default());
         assert!(frame.is_ok());
         assert!(frame.unwrap().is_none());
     }
@@ -208,7 +445,7 @@ mod test {
     #[test]
     fn read_rtu_frame_short2() {
         let buffer = [0x1, 0x1];
-        let frame = read_rtu_frame(&mut ReadCtx::new(&buffer));
+        let frame = read_rtu_frame(&mut ReadCtx::new(&buffer), &PduConfig::default());
         assert!(frame.is_ok());
         assert!(frame.unwrap().is_none());
     }
@@ -216,7 +453,9 @@ mod test {
     #[test]
     fn read_rtu_frame_fc1() {
         let buffer = [0x11, 0x01, 0x00, 0x13, 0x00, 0x25, 0x0E, 0x84];
-        let frame = read_rtu_frame(&mut ReadCtx::new(&buffer)).unwrap().unwrap();
+        let frame = read_rtu_frame(&mut ReadCtx::new(&buffer), &PduConfig::default())
+            .unwrap()
+            .unwrap();
         assert_eq!(frame.id, 0);
         assert_eq!(frame.slave, 0x11);
         match frame.pdu {
@@ -237,7 +476,7 @@ mod test {
         ];
 
         for rec in check {
-            let frame = read_rtu_frame(&mut ReadCtx::new(&rec));
+            let frame = read_rtu_frame(&mut ReadCtx::new(&rec), &PduConfig::default());
             match frame {
                 Err(Error::InvalidCrc) => {}
                 _ => unreachable!(),
@@ -254,7 +493,7 @@ mod test {
         ];
 
         for rec in check {
-            let res = read_rtu_frame(&mut ReadCtx::new(&rec));
+            let res = read_rtu_frame(&mut ReadCtx::new(&rec), &PduConfig::default());
             match res {
                 Ok(None) => {}
                 _ => unreachable!(),
@@ -267,7 +506,9 @@ mod test {
         let buffer = [
             0x0, 0x1, 0x0, 0x0, 0x0, 0x6, 0x11, 0x01, 0x00, 0x13, 0x00, 0x25,
         ];
-        let frame = read_net_frame(&mut ReadCtx::new(&buffer)).unwrap().unwrap();
+        let frame = read_net_frame(&mut ReadCtx::new(&buffer), &PduConfig::default())
+            .unwrap()
+            .unwrap();
         assert_eq!(frame.id, 1);
         assert_eq!(frame.slave, 0x11);
         match frame.pdu {
@@ -293,6 +534,76 @@ mod test {
         }
         assert_eq!(buffer.len(), 0);
     }
+    #[test]
+    fn decode_rtu_unknown_function() {
+        let input = [0x11u8, 0x41, 0x01, 0x02];
+        let mut buffer = BytesMut::from(&input[..]);
+        let crc = crate::codec::rtuext::calc_crc_be(&input);
+        let mut codec = SlaveCodec::new_rtu();
+        assert!(codec.decode(&mut buffer).unwrap().is_none());
+
+        buffer.extend_from_slice(&crc.to_be_bytes());
+        let frame = codec.decode(&mut buffer).unwrap().unwrap();
+        assert_eq!(frame.pdu, RequestPdu::raw(0x41, Data::raw(&[0x01, 0x02])));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn decode_rtu_canopen_oversized() {
+        let mut input = vec![0x11u8, 0x2b, 0x0d];
+        input.extend_from_slice(&[0x55; 300]);
+        let crc = crate::codec::rtuext::calc_crc_be(&input);
+        input.extend_from_slice(&crc.to_be_bytes());
+        let mut buffer = BytesMut::from(&input[..]);
+        let frame = SlaveCodec::new_rtu().decode(&mut buffer);
+        assert_eq!(frame, Err(Error::InvalidData));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn decode_rtu_leading_zero() {
+        let input = [0x0u8, 0x11, 0x01, 0x00, 0x13, 0x00, 0x25, 0x0E, 0x84];
+
+        let mut buffer = BytesMut::from(&input[..]);
+        let frame = SlaveCodec::new_rtu().decode(&mut buffer);
+        assert!(!matches!(frame, Ok(Some(_))));
+
+        let mut buffer = BytesMut::from(&input[..]);
+        let frame = SlaveCodec::new_rtu()
+            .with_rtu_leading_zeros(1)
+            .decode(&mut buffer)
+            .unwrap()
+            .unwrap();
+        assert_eq!(frame.slave, 0x11);
+        assert_eq!(frame.pdu, RequestPdu::read_coils(0x13, 0x25));
+        assert_eq!(buffer.len(), 0);
+    }
+
+    #[test]
+    fn decode_rtu_leading_zero_chunked() {
+        // slave 0 + fc 0x11 is a complete frame with a bad CRC for the strict parse
+        let input = [0x0u8, 0x11, 0x01, 0x00, 0x13, 0x00, 0x25, 0x0E, 0x84];
+        for split in 1..input.len() {
+            let mut codec = SlaveCodec::new_rtu().with_rtu_leading_zeros(1);
+            let mut buffer = BytesMut::from(&input[..split]);
+            assert_eq!(codec.decode(&mut buffer), Ok(None), "split {}", split);
+            buffer.extend_from_slice(&input[split..]);
+            let frame = codec.decode(&mut buffer).unwrap().unwrap();
+            assert_eq!(frame.pdu, RequestPdu::read_coils(0x13, 0x25));
+            assert!(buffer.is_empty());
+        }
+    }
+
+    #[test]
+    fn decode_rtu_leading_zeros_limit() {
+        let input = [0x0u8, 0x0, 0x11, 0x01, 0x00, 0x13, 0x00, 0x25, 0x0E, 0x84];
+        let mut buffer = BytesMut::from(&input[..]);
+        let frame = SlaveCodec::new_rtu()
+            .with_rtu_leading_zeros(1)
+            .decode(&mut buffer);
+        assert!(!matches!(frame, Ok(Some(_))));
+    }
+
     #[test]
     fn decode_fc1_crc_err() {
         let input = [0x11u8, 0x01, 0x00, 0x13, 0x00, 0x25, 0x1E, 0x84];
@@ -316,6 +627,162 @@ mod test {
         assert_eq!(buffer.len(), 7);
     }
 
+    #[test]
+    fn read_net_fc16_len_mismatch() {
+        // MBAP length says 0x20 bytes, but the PDU describes only 4 bytes of data
+        let input = [
+            0x0, 0x1, 0x0, 0x0, 0x0, 0x20, 0x11, 0x10, 0x00, 0x01, 0x00, 0x02, 0x04,
+        ];
+        let res = read_net_frame(&mut ReadCtx::new(&input), &PduConfig::default());
+        assert_eq!(res, Err(Error::InvalidData));
+
+        let input = [
+            0x0, 0x1, 0x0, 0x0, 0x0, 0x5, 0x11, 0x0F, 0x00, 0x01, 0x00, 0x0A, 0x02,
+        ];
+        let res = read_net_frame(&mut ReadCtx::new(&input), &PduConfig::default());
+        assert_eq!(res, Err(Error::InvalidData));
+
+        let input = [
+            0x0, 0x1, 0x0, 0x0, 0x0, 0xB, 0x11, 0x10, 0x00, 0x01, 0x00, 0x02, 0x04, 0x00, 0x0A,
+            0x01, 0x02,
+        ];
+        let res = read_net_frame(&mut ReadCtx::new(&input), &PduConfig::default());
+        assert!(res.unwrap().is_some());
+    }
+
+    fn decode_net_chunked(chunk: usize) {
+        let header = [0x12u8, 0x34, 0x0, 0x0, 0x0, 0x6, 0x11];
+        let pdu = [0x03u8, 0x00, 0x6B, 0x00, 0x03];
+        let mut codec = SlaveCodec::new_tcp();
+        let mut buffer = BytesMut::new();
+
+        for part in header.chunks(chunk) {
+            buffer.extend_from_slice(part);
+            assert_eq!(codec.decode(&mut buffer), Ok(None));
+        }
+        assert_eq!(buffer.len(), header.len());
+
+        buffer.extend_from_slice(&pdu);
+        let frame = codec.decode(&mut buffer).unwrap().unwrap();
+        assert_eq!(frame.id, 0x1234);
+        assert_eq!(frame.slave, 0x11);
+        assert_eq!(frame.pdu, RequestPdu::read_holding_registers(0x6B, 3));
+        assert_eq!(buffer.len(), 0);
+    }
+
+    #[test]
+    fn decode_net_header_by_one_byte() {
+        decode_net_chunked(1);
+    }
+
+    #[test]
+    fn decode_net_header_by_two_bytes() {
+        decode_net_chunked(2);
+    }
+
+    #[test]
+    fn decode_net_pdu_by_one_byte() {
+        let input = [
+            0x12u8, 0x34, 0x0, 0x0, 0x0, 0x6, 0x11, 0x03, 0x00, 0x6B, 0x00, 0x03,
+        ];
+        let mut codec = SlaveCodec::new_tcp();
+        let mut buffer = BytesMut::new();
+        let (last, head) = input.split_last().unwrap();
+        for byte in head {
+            buffer.extend_from_slice(&[*byte]);
+            assert_eq!(codec.decode(&mut buffer), Ok(None));
+        }
+        buffer.extend_from_slice(&[*last]);
+        assert!(codec.decode(&mut buffer).unwrap().is_some());
+        assert_eq!(buffer.len(), 0);
+    }
+
+    #[test]
+    fn peek_rtu() {
+        let input = [0x11u8, 0x01, 0x00, 0x13, 0x00, 0x25, 0x0E, 0x84];
+        assert_eq!(
+            peek_slave_and_func(&CodecMode::Rtu, &input),
+            Some((0x11, 0x01))
+        );
+        assert_eq!(
+            peek_slave_and_func(&CodecMode::Rtu, &input[..2]),
+            Some((0x11, 0x01))
+        );
+        assert_eq!(peek_slave_and_func(&CodecMode::Rtu, &input[..1]), None);
+        assert_eq!(peek_slave_and_func(&CodecMode::Rtu, &[]), None);
+    }
+
+    #[test]
+    fn rtu_complete() {
+        let input = [0x11u8, 0x03, 0x00, 0x6B, 0x00, 0x03, 0x76, 0x87];
+        assert_eq!(rtu_frame_complete(&input), Some(8));
+        assert_eq!(rtu_frame_complete(&input[..7]), None);
+        assert_eq!(rtu_frame_complete(&input[..3]), None);
+        assert_eq!(rtu_frame_complete(&[]), None);
+
+        // the next frame is already in the buffer
+        let mut input = input.to_vec();
+        input.extend_from_slice(&[0x11, 0x03]);
+        assert_eq!(rtu_frame_complete(&input), Some(8));
+
+        // fc16 with data
+        let input = [
+            0x11u8, 0x10, 0x00, 0x01, 0x00, 0x02, 0x04, 0x00, 0x0A, 0x01, 0x02, 0xC6, 0xF0,
+        ];
+        assert_eq!(rtu_frame_complete(&input), Some(13));
+        assert_eq!(rtu_frame_complete(&input[..12]), None);
+    }
+
+    #[test]
+    fn rtu_complete_canopen() {
+        let mut input = vec![0x11u8, 0x2b, 0x0d, 0x01, 0x20, 0x00];
+        let crc = crate::codec::rtuext::calc_crc_be(&input);
+        input.extend_from_slice(&crc.to_be_bytes());
+        assert_eq!(rtu_frame_complete(&input), Some(8));
+        assert_eq!(rtu_frame_complete(&input[..7]), None);
+
+        // too long to be a PDU, with a valid CRC
+        let mut input = vec![0x11u8, 0x2b, 0x0d];
+        input.extend_from_slice(&[0x55; 300]);
+        let crc = crate::codec::rtuext::calc_crc_be(&input);
+        input.extend_from_slice(&crc.to_be_bytes());
+        assert_eq!(rtu_frame_complete(&input), None);
+    }
+
+    #[test]
+    fn peek_net() {
+        let input = [
+            0x12u8, 0x34, 0x0, 0x0, 0x0, 0x6, 0x11, 0x03, 0x00, 0x6B, 0x00, 0x03,
+        ];
+        assert_eq!(
+            peek_slave_and_func(&CodecMode::Net, &input),
+            Some((0x11, 0x03))
+        );
+        assert_eq!(
+            peek_slave_and_func(&CodecMode::Net, &input[..8]),
+            Some((0x11, 0x03))
+        );
+        // header only, no function byte
+        assert_eq!(peek_slave_and_func(&CodecMode::Net, &input[..7]), None);
+        assert_eq!(peek_slave_and_func(&CodecMode::Net, &input[..3]), None);
+    }
+
+    #[test]
+    fn decode_strict_mei() {
+        let input = [0x0, 0x1, 0x0, 0x0, 0x0, 0x5, 0x11, 0x2B, 0x0D, 0x01, 0x02];
+        let mut buffer = BytesMut::from(&input[..]);
+        assert!(SlaveCodec::new_tcp().decode(&mut buffer).unwrap().is_some());
+
+        let mut buffer = BytesMut::from(&input[..]);
+        let mut codec = SlaveCodec::new_tcp().with_strict_mei(true);
+        let frame = codec.decode(&mut buffer).unwrap().unwrap();
+        assert_eq!(
+            frame.pdu,
+            RequestPdu::raw(0x2B, Data::raw(&[0x0D, 0x01, 0x02]))
+        );
+        assert_eq!(buffer.len(), 0);
+    }
+
     #[test]
     fn write_data_crc() {
         let control = [0x11u8, 0x01, 0x00, 0x13, 0x00, 0x25, 0x0E, 0x84];
@@ -359,6 +826,226 @@ mod test {
         assert_eq!(control, buffer.chunk());
     }
 
+    #[test]
+    fn wire_len() {
+        let check = |mode: CodecMode, mut codec: SlaveCodec| {
+            let responses = [
+                ResponsePdu::read_holding_registers([1u16, 2, 3].as_slice()),
+                ResponsePdu::exception(0x3, ExceptionCode::IllegalDataAddress),
+            ];
+            for pdu in responses {
+                let frame = ResponseFrame::from_parts(0x1, 0x11, pdu);
+                let len = frame.wire_len(&mode);
+                let mut buffer = BytesMut::new();
+                codec.encode(frame, &mut buffer).unwrap();
+                assert_eq!(len, buffer.len());
+            }
+        };
+        check(CodecMode::Rtu, SlaveCodec::new_rtu());
+        check(CodecMode::Net, SlaveCodec::new_tcp());
+    }
+
+    #[test]
+    fn encode_oversized() {
+        let codecs = [
+            SlaveCodec::new_rtu(),
+            SlaveCodec::new_tcp(),
+            SlaveCodec::new_ascii(),
+        ];
+        for mut codec in codecs {
+            let pdu = ResponsePdu::raw(0x41, Data::raw(&[0u8; 255]));
+            let frame = ResponseFrame::from_parts(0x1, 0x11, pdu);
+            let mut buffer = BytesMut::new();
+            assert_eq!(codec.encode(frame, &mut buffer), Err(Error::InvalidData));
+        }
+    }
+
+    #[test]
+    fn decode_zero_count_writes() {
+        let requests = [
+            [0x11u8, 0x0F, 0x00, 0x13, 0x00, 0x00, 0x00],
+            [0x11u8, 0x10, 0x00, 0x13, 0x00, 0x00, 0x00],
+        ];
+        for request in requests {
+            let mut input = [0u8; 9];
+            let mut ctx = WriteCtx::new(&mut input);
+            for b in request {
+                ctx.write_u8(b).unwrap();
+            }
+            write_crc(&mut ctx).unwrap();
+
+            let mut buffer = BytesMut::from(&input[..]);
+            assert!(SlaveCodec::new_rtu().decode(&mut buffer).is_err());
+
+            let mut buffer = BytesMut::from(&input[..]);
+            let frame = SlaveCodec::new_rtu()
+                .with_zero_count_writes(true)
+                .decode(&mut buffer)
+                .unwrap()
+                .unwrap();
+            let data = Data::raw(&[0x00, 0x13, 0x00, 0x00, 0x00]);
+            assert_eq!(frame.pdu, RequestPdu::raw(request[1], data));
+            assert!(buffer.is_empty());
+        }
+    }
+
+    #[test]
+    fn decode_max_registers() {
+        let input = [
+            0x0, 0x1, 0x0, 0x0, 0x0, 0x6, 0x11, 0x03, 0x00, 0x00, 0x00, 0x0B,
+        ];
+        let limits = Limits {
+            max_registers: 10,
+            ..Default::default()
+        };
+        let mut codec = SlaveCodec::new_tcp().with_limits(limits);
+        assert!(codec.decode(&mut BytesMut::from(&input[..])).is_err());
+
+        let input = [
+            0x0, 0x1, 0x0, 0x0, 0x0, 0x6, 0x11, 0x03, 0x00, 0x00, 0x00, 0x0A,
+        ];
+        let frame = codec.decode(&mut BytesMut::from(&input[..])).unwrap();
+        assert_eq!(
+            frame.unwrap().pdu,
+            RequestPdu::read_holding_registers(0, 10)
+        );
+    }
+
+    #[test]
+    fn decode_limits() {
+        // fc3 for 200 registers
+        let input = [
+            0x0, 0x1, 0x0, 0x0, 0x0, 0x6, 0x11, 0x03, 0x00, 0x00, 0x00, 0xC8,
+        ];
+        let mut codec = SlaveCodec::new_tcp();
+        assert_eq!(
+            codec.decode(&mut BytesMut::from(&input[..])),
+            Err(Error::InvalidData)
+        );
+
+        // no response could carry them, so the spec limits stay
+        let limits = Limits {
+            max_coils: 3000,
+            max_registers: 300,
+            max_write_bytes: 255,
+        };
+        let mut codec = SlaveCodec::new_tcp().with_limits(limits);
+        assert_eq!(
+            codec.decode(&mut BytesMut::from(&input[..])),
+            Err(Error::InvalidData)
+        );
+        assert_eq!(codec.config.limits, Limits::default());
+
+        // writes are bounded by the spec
+        let mut input = vec![
+            0x0, 0x1, 0x0, 0x0, 0x0, 0xFF, 0x11, 0x10, 0x0, 0x0, 0x0, 0x7C, 0xF8,
+        ];
+        input.extend_from_slice(&[0u8; 248]);
+        assert_eq!(
+            codec.decode(&mut BytesMut::from(&input[..])),
+            Err(Error::InvalidData)
+        );
+    }
+
+    // xorshift, to keep the runs reproducible
+    struct Rng(u64);
+
+    impl Rng {
+        fn byte(&mut self) -> u8 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 as u8
+        }
+
+        fn bytes(&mut self, len: usize) -> Vec<u8> {
+            (0..len).map(|_| self.byte()).collect()
+        }
+
+        // function code and data with a size up to a bit above the limits.
+        // Byte counts and MEI types are often valid to get past the first checks
+        fn pdu(&mut self) -> Vec<u8> {
+            let functions = [
+                0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0x8, 0xF, 0x10, 0x11, 0x16, 0x17, 0x18, 0x2B,
+            ];
+            let func = match self.byte() as usize % (functions.len() + 1) {
+                idx if idx < functions.len() => functions[idx],
+                _ => self.byte(),
+            };
+            let len = self.byte() as usize + self.byte() as usize % 64;
+            let mut pdu = vec![func];
+            pdu.extend(self.bytes(len));
+            let valid = self.byte() & 1 == 0;
+            match (func, pdu.len()) {
+                (0xF | 0x10, len) if valid && len > 5 => pdu[5] = (len - 6) as u8,
+                (0x17, len) if valid && len > 9 => pdu[9] = (len - 10) as u8,
+                (0x2B, len) if len > 1 => pdu[1] = [0xD, 0xE][valid as usize],
+                _ => {}
+            }
+            pdu
+        }
+    }
+
+    fn rtu_frame(pdu: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0x11];
+        frame.extend_from_slice(pdu);
+        let crc = crate::codec::rtuext::calc_crc_be(&frame);
+        frame.extend_from_slice(&crc.to_be_bytes());
+        frame
+    }
+
+    fn net_frame(pdu: &[u8]) -> Vec<u8> {
+        let len = pdu.len() as u16 + 1;
+        let mut frame = vec![0x0, 0x1, 0x0, 0x0];
+        frame.extend_from_slice(&len.to_be_bytes());
+        frame.push(0x11);
+        frame.extend_from_slice(pdu);
+        frame
+    }
+
+    fn ascii_frame(pdu: &[u8]) -> Vec<u8> {
+        let mut data = vec![0x11];
+        data.extend_from_slice(pdu);
+        data.push(crate::codec::asciiext::calc_lrc(&data));
+        let mut frame = vec![b':'];
+        for byte in data {
+            frame.extend_from_slice(format!("{:02X}", byte).as_bytes());
+        }
+        frame.extend_from_slice(b"\r\n");
+        frame
+    }
+
+    #[test]
+    fn decode_request_random() {
+        let mut rng = Rng(0x2545F4914F6CDD1D);
+        for _ in 0..10000 {
+            // plain noise
+            let len = rng.byte() as usize + rng.byte() as usize % 64;
+            let bytes = rng.bytes(len);
+            for mode in [CodecMode::Rtu, CodecMode::Net, CodecMode::Ascii] {
+                let _ = decode_request(mode, &bytes);
+            }
+
+            // frames with valid CRC/LRC and MBAP length, so the PDU is decoded
+            let pdu = rng.pdu();
+            let frames = [
+                (CodecMode::Rtu, rtu_frame(&pdu)),
+                (CodecMode::Net, net_frame(&pdu)),
+                (CodecMode::Ascii, ascii_frame(&pdu)),
+            ];
+            for (mode, frame) in frames {
+                for len in [frame.len(), frame.len() / 2] {
+                    let _ = decode_request(mode.clone(), &frame[..len]);
+                }
+            }
+        }
+
+        let bytes = [0x11, 0x03, 0x00, 0x6B, 0x00, 0x03, 0x76, 0x87];
+        let frame = decode_request(CodecMode::Rtu, &bytes).unwrap().unwrap();
+        assert_eq!(frame.pdu, RequestPdu::read_holding_registers(0x6B, 3));
+        assert_eq!(decode_request(CodecMode::Rtu, &bytes[..7]), Ok(None));
+    }
+
     #[test]
     fn mbap_part() {
         let buffer = [0x0, 0x1, 0x0, 0x0];
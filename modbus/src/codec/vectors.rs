@@ -0,0 +1,151 @@
+//! Canonical requests of every supported function with their RTU and MBAP
+//! encodings. Taken from the examples of the Modbus specification, so they
+//! can be used for interop tests outside of the crate (`testing` feature)
+use crate::codec::rtuext::calc_crc_be;
+use crate::data::helpers;
+use crate::frame::prelude::*;
+
+pub(crate) const SLAVE: u8 = 0x11;
+pub(crate) const ID: u16 = 0x1234;
+
+pub(crate) fn rtu_bytes(pdu: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![SLAVE];
+    bytes.extend_from_slice(pdu);
+    let crc = calc_crc_be(&bytes);
+    bytes.extend_from_slice(&crc.to_be_bytes());
+    bytes
+}
+
+pub(crate) fn net_bytes(pdu: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&ID.to_be_bytes());
+    bytes.extend_from_slice(&[0x0, 0x0]);
+    bytes.extend_from_slice(&(pdu.len() as u16 + 1).to_be_bytes());
+    bytes.push(SLAVE);
+    bytes.extend_from_slice(pdu);
+    bytes
+}
+
+fn requests() -> Vec<(&'static str, RequestPdu, Vec<u8>)> {
+    let coils = helpers::bits_from_bytes(&[0xCD, 0x01], 10);
+    vec![
+        (
+            "read coils",
+            RequestPdu::read_coils(0x13, 0x25),
+            vec![0x01, 0x00, 0x13, 0x00, 0x25],
+        ),
+        (
+            "read discrete inputs",
+            RequestPdu::read_discrete_inputs(0xC4, 0x16),
+            vec![0x02, 0x00, 0xC4, 0x00, 0x16],
+        ),
+        (
+            "read holding registers",
+            RequestPdu::read_holding_registers(0x6B, 0x3),
+            vec![0x03, 0x00, 0x6B, 0x00, 0x03],
+        ),
+        (
+            "read input registers",
+            RequestPdu::read_input_registers(0x8, 0x1),
+            vec![0x04, 0x00, 0x08, 0x00, 0x01],
+        ),
+        (
+            "write single coil",
+            RequestPdu::write_single_coil(0xAC, true),
+            vec![0x05, 0x00, 0xAC, 0xFF, 0x00],
+        ),
+        (
+            "write single register",
+            RequestPdu::write_single_register(0x1, 0x3),
+            vec![0x06, 0x00, 0x01, 0x00, 0x03],
+        ),
+        (
+            "read exception status",
+            RequestPdu::read_exception_status(),
+            vec![0x07],
+        ),
+        (
+            "diagnostics return query data",
+            RequestPdu::diagnostic(0x0, 0xA537),
+            vec![0x08, 0x00, 0x00, 0xA5, 0x37],
+        ),
+        (
+            "write multiple coils",
+            RequestPdu::write_multiple_coils(0x13, coils.as_slice()),
+            vec![0x0F, 0x00, 0x13, 0x00, 0x0A, 0x02, 0xCD, 0x01],
+        ),
+        (
+            "write multiple registers",
+            RequestPdu::write_multiple_registers(0x1, [0x000Au16, 0x0102].as_slice()),
+            vec![0x10, 0x00, 0x01, 0x00, 0x02, 0x04, 0x00, 0x0A, 0x01, 0x02],
+        ),
+        ("report slave id", RequestPdu::report_slave_id(), vec![0x11]),
+        (
+            "mask write register",
+            RequestPdu::mask_write_register(0x4, 0xF2, 0x25),
+            vec![0x16, 0x00, 0x04, 0x00, 0xF2, 0x00, 0x25],
+        ),
+        (
+            "read/write multiple registers",
+            RequestPdu::read_write_multiple_registers(
+                0x3,
+                0x6,
+                0xE,
+                [0x00FFu16, 0x00FF, 0x00FF].as_slice(),
+            ),
+            vec![
+                0x17, 0x00, 0x03, 0x00, 0x06, 0x00, 0x0E, 0x00, 0x03, 0x06, 0x00, 0xFF, 0x00, 0xFF,
+                0x00, 0xFF,
+            ],
+        ),
+        (
+            "read fifo queue",
+            RequestPdu::read_fifo_queue(0x04DE),
+            vec![0x18, 0x04, 0xDE],
+        ),
+        (
+            "read device identification",
+            RequestPdu::read_device_identification(0x1, 0x0),
+            vec![0x2B, 0x0E, 0x01, 0x00],
+        ),
+    ]
+}
+
+/// label, frame, RTU bytes, MBAP bytes. The transaction id of the frame is
+/// used by MBAP only, RTU frames are decoded with id 0
+pub type TestVector = (&'static str, RequestFrame, Vec<u8>, Vec<u8>);
+
+pub fn test_vectors() -> Vec<TestVector> {
+    requests()
+        .into_iter()
+        .map(|(label, pdu, bytes)| {
+            let frame = RequestFrame::from_parts(ID, SLAVE, pdu);
+            (label, frame, rtu_bytes(&bytes), net_bytes(&bytes))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::codec::slave::SlaveCodec;
+    use bytes::BytesMut;
+    use tokio_util::codec::Decoder;
+
+    #[test]
+    fn vectors_decode() {
+        for (label, frame, rtu, net) in test_vectors() {
+            let mut input = BytesMut::from(&rtu[..]);
+            let decoded = SlaveCodec::new_rtu().decode(&mut input).unwrap().unwrap();
+            assert_eq!(decoded.slave, frame.slave, "{}", label);
+            assert_eq!(decoded.pdu, frame.pdu, "{}", label);
+            assert_eq!(decoded.pdu.len(), rtu.len() - 3, "{}", label);
+            assert!(input.is_empty(), "{}", label);
+
+            let mut input = BytesMut::from(&net[..]);
+            let decoded = SlaveCodec::new_tcp().decode(&mut input).unwrap().unwrap();
+            assert_eq!(decoded, frame, "{}", label);
+            assert!(input.is_empty(), "{}", label);
+        }
+    }
+}
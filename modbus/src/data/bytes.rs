@@ -61,7 +61,6 @@ impl<'a, 'b> Bytes for BytesCursor<'a, 'b> {
 }
 
 #[cfg(test)]
-
 mod test {
     use super::*;
 
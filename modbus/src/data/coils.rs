@@ -4,6 +4,26 @@ use bytes::Buf;
 use std::cell::RefCell;
 use std::io::Cursor;
 
+/// Order of coils within a byte on the wire
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BitOrder {
+    /// the first coil is the least significant bit. Modbus standard
+    #[default]
+    LsbFirst,
+    /// the first coil is the most significant bit. Used by some devices
+    MsbFirst,
+}
+
+impl BitOrder {
+    /// convert a byte of LSB-first coils to this order and back
+    pub fn convert(&self, byte: u8) -> u8 {
+        match self {
+            BitOrder::LsbFirst => byte,
+            BitOrder::MsbFirst => byte.reverse_bits(),
+        }
+    }
+}
+
 pub trait Coils {
     /// write coils to a buffer
     /// return number of written coils
@@ -105,6 +125,16 @@ impl<'a, 'b> Coils for CoilsCursor<'a, 'b> {
 mod test {
     use super::*;
 
+    #[test]
+    fn bit_order() {
+        let coils = [true, true, false, false, false, false, false, false];
+        let mut buffer = [0u8];
+        coils.as_slice().coils_write(&mut buffer);
+        assert_eq!(BitOrder::LsbFirst.convert(buffer[0]), 0x03);
+        assert_eq!(BitOrder::MsbFirst.convert(buffer[0]), 0xC0);
+        assert_eq!(BitOrder::MsbFirst.convert(0xC0), 0x03);
+    }
+
     #[test]
     fn coils_slice() {
         let slice = CoilsSlice::new(&[0x0F], 4);
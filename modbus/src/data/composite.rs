@@ -0,0 +1,133 @@
+use crate::frame::exception::Code;
+use crate::frame::pdu::{RequestPdu, ResponsePdu};
+use std::ops::Range;
+
+/// Backend of a register range. Gets the absolute address and the number of
+/// registers that always fit into the range it was added with
+pub trait RegisterSource {
+    fn read_registers(&self, address: u16, nobjs: u16) -> Result<Vec<u16>, Code>;
+}
+
+impl<F> RegisterSource for F
+where
+    F: Fn(u16, u16) -> Result<Vec<u16>, Code>,
+{
+    fn read_registers(&self, address: u16, nobjs: u16) -> Result<Vec<u16>, Code> {
+        self(address, nobjs)
+    }
+}
+
+/// Register model assembled from several sources. A read spanning several
+/// ranges is split between their sources and the results are concatenated
+#[derive(Default)]
+pub struct Composite {
+    sources: Vec<(Range<u32>, Box<dyn RegisterSource + Send>)>,
+}
+
+impl Composite {
+    /// back `nobjs` registers starting at `address` with `source`. The range
+    /// must not overlap the ranges added before
+    pub fn with_source<S>(mut self, address: u16, nobjs: u16, source: S) -> Composite
+    where
+        S: RegisterSource + Send + 'static,
+    {
+        let range = address as u32..address as u32 + nobjs as u32;
+        assert!(
+            self.sources
+                .iter()
+                .all(|(other, _)| range.end <= other.start || other.end <= range.start),
+            "overlapped register ranges"
+        );
+        self.sources.push((range, Box::new(source)));
+        self
+    }
+
+    /// IllegalDataAddress if a part of the range isn't backed by any source
+    pub fn read_registers(&self, address: u16, nobjs: u16) -> Result<Vec<u16>, Code> {
+        let end = address as u32 + nobjs as u32;
+        let mut current = address as u32;
+        let mut result = Vec::with_capacity(nobjs as usize);
+        while current < end {
+            let (range, source) = self
+                .sources
+                .iter()
+                .find(|(range, _)| range.contains(&current))
+                .ok_or(Code::IllegalDataAddress)?;
+            let count = (range.end.min(end) - current) as u16;
+            let values = source.read_registers(current as u16, count)?;
+            if values.len() != count as usize {
+                return Err(Code::SlaveDeviceFailure);
+            }
+            result.extend(values);
+            current += count as u32;
+        }
+        Ok(result)
+    }
+
+    /// answer fc3/fc4 requests. None for other functions
+    pub fn read(&self, pdu: &RequestPdu) -> Option<ResponsePdu> {
+        let (func, address, nobjs) = match pdu {
+            RequestPdu::ReadHoldingRegisters { address, nobjs } => (0x3, address, nobjs),
+            RequestPdu::ReadInputRegisters { address, nobjs } => (0x4, address, nobjs),
+            _ => return None,
+        };
+        let response = match self.read_registers(*address, *nobjs) {
+            Ok(values) if func == 0x3 => ResponsePdu::read_holding_registers(values.as_slice()),
+            Ok(values) => ResponsePdu::read_input_registers(values.as_slice()),
+            Err(code) => ResponsePdu::exception(func, code),
+        };
+        Some(response)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn composite() -> Composite {
+        let memory = [0x10u16, 0x11, 0x12, 0x13];
+        Composite::default()
+            .with_source(0x100, 4, move |address: u16, nobjs: u16| {
+                let start = (address - 0x100) as usize;
+                Ok(memory[start..start + nobjs as usize].to_vec())
+            })
+            .with_source(0x104, 2, |address: u16, nobjs: u16| {
+                Ok((address..address + nobjs).map(|a| a * 2).collect())
+            })
+    }
+
+    #[test]
+    fn read_across_sources() {
+        let model = composite();
+        assert_eq!(
+            model.read_registers(0x102, 4),
+            Ok(vec![0x12, 0x13, 0x208, 0x20A])
+        );
+        assert_eq!(
+            model.read(&RequestPdu::read_holding_registers(0x103, 2)),
+            Some(ResponsePdu::read_holding_registers(
+                [0x13u16, 0x208].as_slice()
+            ))
+        );
+    }
+
+    #[test]
+    fn read_unbacked() {
+        let model = composite();
+        assert_eq!(
+            model.read_registers(0x104, 3),
+            Err(Code::IllegalDataAddress)
+        );
+        assert_eq!(
+            model.read(&RequestPdu::read_input_registers(0xFF, 2)),
+            Some(ResponsePdu::exception(0x4, Code::IllegalDataAddress))
+        );
+        assert_eq!(model.read(&RequestPdu::read_coils(0x100, 1)), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn overlapped_sources() {
+        let _ = composite().with_source(0x105, 1, |_: u16, _: u16| Ok(vec![0]));
+    }
+}
@@ -1,20 +1,24 @@
 pub mod bytes;
 pub mod checks;
 pub mod coils;
+pub mod composite;
 pub mod helpers;
 pub mod registers;
 pub mod storage;
 
 pub const MAX_PDU_SIZE: usize = 253; // Max. size of  protocol data unit
 pub const MAX_NREGS: usize = 125; // Max. number of registers
+pub const MAX_RW_WRITE_NREGS: usize = 121; // Max. number of registers to write with 0x17
+pub const MAX_FIFO_COUNT: usize = 31; // Max. number of values in 0x18 responses
 pub const MAX_NCOILS: usize = MAX_NREGS * 16; // Max. number of coils
 pub const MAX_DATA_SIZE: usize = 256; // used for storing data in internal structs. Should has length that divides by 2
 
 pub mod prelude {
 
     pub use super::bytes::{Bytes, BytesCursor};
-    pub use super::coils::{Coils, CoilsCursor};
-    pub use super::registers::{Registers, RegistersCursorBe};
+    pub use super::coils::{BitOrder, Coils, CoilsCursor};
+    pub use super::composite::{Composite, RegisterSource};
+    pub use super::registers::{Registers, RegistersCursorBe, WordOrder};
     pub use super::storage::DataStorage as Data;
     pub use super::MAX_DATA_SIZE;
     pub use super::MAX_NCOILS;
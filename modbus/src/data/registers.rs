@@ -4,6 +4,46 @@ use bytes::Buf;
 use std::cell::RefCell;
 use std::io::Cursor;
 
+/// Layout of a 32-bit value in a pair of registers. Bytes of 0x47C35000 in
+/// the first and the second register
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WordOrder {
+    /// 47 C3, 50 00. Modbus standard
+    #[default]
+    BigEndian,
+    /// 00 50, C3 47
+    LittleEndian,
+    /// 50 00, 47 C3
+    BigEndianSwap,
+    /// C3 47, 00 50
+    LittleEndianSwap,
+}
+
+impl WordOrder {
+    /// registers of the value in this order
+    pub fn split(&self, value: u32) -> [u16; 2] {
+        let (hi, lo) = ((value >> 16) as u16, value as u16);
+        match self {
+            WordOrder::BigEndian => [hi, lo],
+            WordOrder::LittleEndian => [lo.swap_bytes(), hi.swap_bytes()],
+            WordOrder::BigEndianSwap => [lo, hi],
+            WordOrder::LittleEndianSwap => [hi.swap_bytes(), lo.swap_bytes()],
+        }
+    }
+
+    /// value of registers in this order
+    pub fn join(&self, registers: [u16; 2]) -> u32 {
+        let [first, second] = registers;
+        let (hi, lo) = match self {
+            WordOrder::BigEndian => (first, second),
+            WordOrder::LittleEndian => (second.swap_bytes(), first.swap_bytes()),
+            WordOrder::BigEndianSwap => (second, first),
+            WordOrder::LittleEndianSwap => (first.swap_bytes(), second.swap_bytes()),
+        };
+        (hi as u32) << 16 | lo as u32
+    }
+}
+
 pub trait Registers {
     /// write registers to a buffer
     /// return number of written registers
@@ -89,7 +129,6 @@ impl<'a, 'b> Registers for RegistersCursorBe<'a, 'b> {
 }
 
 #[cfg(test)]
-
 mod test {
     use super::*;
 
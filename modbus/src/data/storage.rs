@@ -1,8 +1,14 @@
 use crate::data::prelude::*;
 use crate::data::{checks, helpers};
 use smallvec::SmallVec;
+use std::fmt;
 
-#[derive(Debug, PartialEq, Eq)]
+// Debug output shows buffers up to this size in full. Longer ones are cut to
+// the first and the last DEBUG_EDGE bytes
+const DEBUG_FULL: usize = 16;
+const DEBUG_EDGE: usize = 4;
+
+#[derive(Clone, PartialEq, Eq)]
 pub struct DataStorage {
     buffer: SmallVec<[u8; MAX_DATA_SIZE]>,
 }
@@ -50,6 +56,23 @@ impl DataStorage {
         &self.buffer[0..len]
     }
 
+    pub fn iter_bytes(&self) -> impl Iterator<Item = u8> + '_ {
+        self.get().iter().copied()
+    }
+
+    /// register values. A trailing odd byte is not a register
+    pub fn registers_iter(&self) -> impl Iterator<Item = u16> + '_ {
+        self.get()
+            .chunks_exact(2)
+            .map(|bytes| u16::from_ne_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// the first `nobjs` coils, fewer if the buffer is shorter
+    pub fn coils_iter(&self, nobjs: u16) -> impl Iterator<Item = bool> + '_ {
+        let count = (nobjs as usize).min(self.len() * 8);
+        (0..count).map(|idx| self.get_bit(idx).unwrap())
+    }
+
     pub fn extend(&mut self, slice: &[u8]) {
         self.buffer.extend_from_slice(slice);
     }
@@ -80,6 +103,15 @@ impl DataStorage {
         helpers::get_bit(self.get(), idx)
     }
 
+    /// compare the first `nobjs` coils only. Padding bits of the last byte
+    /// are ignored. False if any of the buffers is shorter than `nobjs`
+    pub fn coils_eq(&self, other: &Self, nobjs: u16) -> bool {
+        (0..nobjs as usize).all(|idx| match (self.get_bit(idx), other.get_bit(idx)) {
+            (Some(a), Some(b)) => a == b,
+            _ => false,
+        })
+    }
+
     pub fn set_bit(&mut self, idx: usize, value: bool) -> bool {
         assert!(idx < self.len() * 8);
 
@@ -95,25 +127,43 @@ impl DataStorage {
 
     pub fn set_u16(&mut self, idx: usize, value: u16) -> bool {
         let start = idx * 2;
-        let end = start + 1;
-        assert!(end < self.len());
-        self.get_mut()[start..end + 1].copy_from_slice(&value.to_ne_bytes());
+        assert!(start + 2 <= self.len());
+        self.get_mut()[start..start + 2].copy_from_slice(&value.to_ne_bytes());
         true
     }
 
     pub fn get_u16(&self, idx: usize) -> Option<u16> {
         let start = idx * 2;
-        let end = start + 1;
-
-        if end < self.len() {
+        if start + 2 <= self.len() {
             Some(u16::from_ne_bytes(
-                self.get()[start..end + 1].try_into().unwrap(),
+                self.get()[start..start + 2].try_into().unwrap(),
             ))
         } else {
             None
         }
     }
 
+    /// 32-bit value in registers `idx` and `idx + 1`
+    pub fn get_u32(&self, idx: usize, order: WordOrder) -> Option<u32> {
+        let first = self.get_u16(idx)?;
+        let second = self.get_u16(idx + 1)?;
+        Some(order.join([first, second]))
+    }
+
+    pub fn set_u32(&mut self, idx: usize, value: u32, order: WordOrder) -> bool {
+        assert!((idx + 2) * 2 <= self.len());
+        let [first, second] = order.split(value);
+        self.set_u16(idx, first) && self.set_u16(idx + 1, second)
+    }
+
+    pub fn get_f32(&self, idx: usize, order: WordOrder) -> Option<f32> {
+        self.get_u32(idx, order).map(f32::from_bits)
+    }
+
+    pub fn set_f32(&mut self, idx: usize, value: f32, order: WordOrder) -> bool {
+        self.set_u32(idx, value.to_bits(), order)
+    }
+
     fn registers_empty(nobjs: u16) -> DataStorage {
         assert!(checks::check_registers_count(nobjs));
         let len = helpers::get_registers_len(nobjs);
@@ -132,6 +182,45 @@ impl DataStorage {
     }
 }
 
+impl fmt::Debug for DataStorage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hex = |f: &mut fmt::Formatter, bytes: &[u8]| -> fmt::Result {
+            bytes.iter().try_for_each(|byte| write!(f, " {:02X}", byte))
+        };
+
+        let data = self.get();
+        write!(f, "Data(len:{} [", data.len())?;
+        if data.len() <= DEBUG_FULL {
+            hex(f, data)?;
+        } else {
+            hex(f, &data[..DEBUG_EDGE])?;
+            write!(f, " ..")?;
+            hex(f, &data[data.len() - DEBUG_EDGE..])?;
+        }
+        write!(f, " ])")
+    }
+}
+
+// plain byte array instead of SmallVec internals
+#[cfg(feature = "serde")]
+impl serde::Serialize for DataStorage {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.get())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for DataStorage {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bytes = Vec::<u8>::deserialize(deserializer)?;
+        if bytes.len() > MAX_DATA_SIZE {
+            let expected = "at most 256 bytes";
+            return Err(serde::de::Error::invalid_length(bytes.len(), &expected));
+        }
+        Ok(DataStorage::raw(&bytes))
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -142,17 +231,32 @@ mod test {
 
         let data = DataStorage::coils(&input[0..1]);
         assert_eq!(data.len(), 1);
-        assert_eq!(data.get_bit(0).unwrap(), true);
+        assert!(data.get_bit(0).unwrap());
         assert_eq!(data.get_u8(0).unwrap(), 0x1);
         assert!(data.get_u16(0).is_none());
 
         let data = DataStorage::coils(&input[..]);
         assert_eq!(data.len(), 1);
-        assert_eq!(data.get_bit(0).unwrap(), true);
+        assert!(data.get_bit(0).unwrap());
         assert_eq!(data.get_u8(0).unwrap(), 0x1 | 0x10);
         assert!(data.get_u16(0).is_none());
     }
 
+    #[test]
+    fn data_coils_eq() {
+        let a = DataStorage::raw(&[0xCD, 0x02]);
+        let b = DataStorage::raw(&[0xCD, 0xFC]);
+        assert_ne!(a, b);
+        assert!(a.coils_eq(&b, 9));
+        assert!(!a.coils_eq(&b, 10));
+
+        let c = DataStorage::raw(&[0xCC, 0x02]);
+        assert!(!a.coils_eq(&c, 9));
+
+        // shorter than requested
+        assert!(!a.coils_eq(&DataStorage::raw(&[0xCD]), 9));
+    }
+
     #[test]
     fn data_registers() {
         let input = [1u16, 2, 3, 4];
@@ -169,6 +273,42 @@ mod test {
         assert_eq!(data.get_u16(3).unwrap(), 0x4);
     }
 
+    #[test]
+    fn data_last_register() {
+        let mut data = DataStorage::raw_empty(4);
+        assert!(data.set_u16(1, 0xABCD));
+        assert_eq!(data.get_u16(1), Some(0xABCD));
+        assert_eq!(data.get_u16(2), None);
+
+        // odd length: the trailing byte is not a register
+        let data = DataStorage::raw(&[0x1, 0x2, 0x3]);
+        assert!(data.get_u16(0).is_some());
+        assert_eq!(data.get_u16(1), None);
+    }
+
+    #[test]
+    fn data_u32() {
+        let layouts = [
+            (WordOrder::BigEndian, [0x47C3, 0x5000]),
+            (WordOrder::LittleEndian, [0x0050, 0xC347]),
+            (WordOrder::BigEndianSwap, [0x5000, 0x47C3]),
+            (WordOrder::LittleEndianSwap, [0xC347, 0x0050]),
+        ];
+        for (order, registers) in layouts {
+            let data = DataStorage::registers([0x1u16, registers[0], registers[1]].as_slice());
+            assert_eq!(data.get_u32(1, order), Some(0x47C35000));
+            assert_eq!(data.get_f32(1, order), Some(100000.0));
+            assert_eq!(data.get_u32(2, order), None);
+
+            let mut data = DataStorage::registers([0x0u16; 3].as_slice());
+            assert!(data.set_f32(1, 100000.0, order));
+            assert_eq!(data.get_u16(1), Some(registers[0]));
+            assert_eq!(data.get_u16(2), Some(registers[1]));
+            assert!(data.set_u32(0, 0x47C35000, order));
+            assert_eq!(data.get_u32(0, order), Some(0x47C35000));
+        }
+    }
+
     #[test]
     fn data_raw() {
         let input = [1u8, 2, 3, 4];
@@ -193,12 +333,69 @@ mod test {
         assert_eq!(data.get_u8(1).unwrap(), 0xBB);
         assert_eq!(data.get_u16(0).unwrap(), 0xBBAA);
 
-        assert_eq!(data.get_bit(0).unwrap(), false);
-        assert_eq!(data.get_bit(1).unwrap(), true);
+        assert!(!data.get_bit(0).unwrap());
+        assert!(data.get_bit(1).unwrap());
 
         data.set_bit(0, true);
         data.set_bit(1, false);
-        assert_eq!(data.get_bit(0).unwrap(), true);
-        assert_eq!(data.get_bit(1).unwrap(), false);
+        assert!(data.get_bit(0).unwrap());
+        assert!(!data.get_bit(1).unwrap());
+    }
+
+    #[test]
+    fn debug_short() {
+        let data = DataStorage::raw(&[0x01, 0x02, 0xAB]);
+        assert_eq!(format!("{:?}", data), "Data(len:3 [ 01 02 AB ])");
+        assert_eq!(format!("{:?}", DataStorage::raw(&[])), "Data(len:0 [ ])");
+    }
+
+    #[test]
+    fn debug_long() {
+        let bytes: Vec<u8> = (0..200).collect();
+        let data = DataStorage::raw(&bytes);
+        assert_eq!(
+            format!("{:?}", data),
+            "Data(len:200 [ 00 01 02 03 .. C4 C5 C6 C7 ])"
+        );
+    }
+
+    #[test]
+    fn iter_bytes() {
+        let data = DataStorage::raw(&[0x01, 0x02, 0x03]);
+        assert_eq!(data.iter_bytes().collect::<Vec<u8>>(), vec![1, 2, 3]);
+        assert_eq!(data.iter_bytes().sum::<u8>(), 6);
+    }
+
+    #[test]
+    fn registers_iter() {
+        let data = DataStorage::registers([0x1234u16, 0xABCD, 0x0].as_slice());
+        assert_eq!(
+            data.registers_iter().collect::<Vec<u16>>(),
+            vec![0x1234, 0xABCD, 0x0]
+        );
+        let data = DataStorage::raw(&[0x1, 0x2, 0x3]);
+        assert_eq!(data.registers_iter().count(), 1);
+    }
+
+    #[test]
+    fn coils_iter() {
+        let data = DataStorage::raw(&[0xCD, 0x01]);
+        assert_eq!(
+            data.coils_iter(10).collect::<Vec<bool>>(),
+            vec![true, false, true, true, false, false, true, true, true, false]
+        );
+        assert_eq!(data.coils_iter(3).count(), 3);
+        assert_eq!(data.coils_iter(100).count(), 16);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn deserialize_too_long() {
+        let json = serde_json::to_string(&vec![0u8; MAX_DATA_SIZE]).unwrap();
+        let data = serde_json::from_str::<DataStorage>(&json).unwrap();
+        assert_eq!(data.len(), MAX_DATA_SIZE);
+
+        let json = serde_json::to_string(&vec![0u8; MAX_DATA_SIZE + 1]).unwrap();
+        assert!(serde_json::from_str::<DataStorage>(&json).is_err());
     }
 }
@@ -0,0 +1,105 @@
+use crate::data::checks;
+use crate::frame::pdu::{RequestPdu, ResponsePdu};
+
+/// MEI type of Read Device Identification
+pub const MEI_READ_DEVICE_ID: u8 = 0xE;
+
+/// read device id code: basic identification, stream access
+pub const READ_BASIC: u8 = 0x1;
+/// read device id code: one specific object
+pub const READ_INDIVIDUAL: u8 = 0x4;
+
+/// Response of Read Device Identification (0x2B/0x0E)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceId {
+    read_code: u8,
+    conformity: u8,
+    next_object_id: Option<u8>,
+    objects: Vec<(u8, Vec<u8>)>,
+}
+
+impl DeviceId {
+    /// answer to the request with `read_code`. Conformity level is basic
+    /// identification with stream access (0x01)
+    pub fn new(read_code: u8) -> DeviceId {
+        DeviceId {
+            read_code,
+            conformity: 0x1,
+            next_object_id: None,
+            objects: Vec::new(),
+        }
+    }
+
+    pub fn with_conformity(mut self, conformity: u8) -> DeviceId {
+        self.conformity = conformity;
+        self
+    }
+
+    /// the answer doesn't fit into one response. The master continues from
+    /// `object_id`
+    pub fn with_more_follows(mut self, object_id: u8) -> DeviceId {
+        self.next_object_id = Some(object_id);
+        self
+    }
+
+    pub fn with_object(mut self, object_id: u8, value: &[u8]) -> DeviceId {
+        assert!(value.len() <= u8::MAX as usize);
+        self.objects.push((object_id, value.to_vec()));
+        self
+    }
+
+    pub fn build(&self) -> ResponsePdu {
+        let mut data = vec![
+            self.read_code,
+            self.conformity,
+            if self.next_object_id.is_some() {
+                0xFF
+            } else {
+                0x0
+            },
+            self.next_object_id.unwrap_or(0),
+            self.objects.len() as u8,
+        ];
+        for (object_id, value) in &self.objects {
+            data.push(*object_id);
+            data.push(value.len() as u8);
+            data.extend_from_slice(value);
+        }
+        assert!(checks::checks_bytes_count(data.len()));
+        ResponsePdu::encapsulated_interface_transport(MEI_READ_DEVICE_ID, &data)
+    }
+}
+
+impl RequestPdu {
+    /// 0x2b/0xE
+    pub fn read_device_identification(read_code: u8, object_id: u8) -> RequestPdu {
+        RequestPdu::encapsulated_interface_transport(
+            MEI_READ_DEVICE_ID,
+            [read_code, object_id].as_slice(),
+        )
+    }
+
+    /// read device id code and object id of a Read Device Identification
+    /// request
+    pub fn device_id_request(&self) -> Option<(u8, u8)> {
+        match self {
+            RequestPdu::EncapsulatedInterfaceTransport {
+                mei_type: MEI_READ_DEVICE_ID,
+                data,
+            } => Some((data.get_u8(0)?, data.get_u8(1)?)),
+            _ => None,
+        }
+    }
+}
+
+impl ResponsePdu {
+    /// 0x2b/0xE. Basic stream read of all `objects` in one response
+    pub fn read_device_identification(objects: &[(u8, &[u8])]) -> ResponsePdu {
+        objects
+            .iter()
+            .fold(DeviceId::new(READ_BASIC), |id, (object_id, value)| {
+                id.with_object(*object_id, value)
+            })
+            .build()
+    }
+}
@@ -1,6 +1,8 @@
 use std::convert::From;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
 pub enum Code {
     IllegalFunction = 0x01,
     IllegalDataAddress = 0x02,
@@ -11,11 +13,53 @@ pub enum Code {
     MemoryParityError = 0x08,
     GatewayPathUnavailable = 0x0A,
     GatewayTargetDeciveFailedToRespond = 0x0B,
+    /// code that is not in the spec, e.g. from a newer or vendor device
+    Unknown(u8),
+}
+
+impl Code {
+    /// Standard codes, Unknown for the others. None for 0x0, it's not an
+    /// exception code
+    pub fn from_u8(value: u8) -> Option<Code> {
+        match value {
+            0x0 => None,
+            value => Some(Code::try_from(value).unwrap_or(Code::Unknown(value))),
+        }
+    }
 }
 
 impl From<Code> for u8 {
     fn from(value: Code) -> u8 {
-        value as u8
+        match value {
+            Code::IllegalFunction => 0x01,
+            Code::IllegalDataAddress => 0x02,
+            Code::IllegalDataValue => 0x03,
+            Code::SlaveDeviceFailure => 0x04,
+            Code::Acknowledge => 0x05,
+            Code::SlaveDeviceBusy => 0x06,
+            Code::MemoryParityError => 0x08,
+            Code::GatewayPathUnavailable => 0x0A,
+            Code::GatewayTargetDeciveFailedToRespond => 0x0B,
+            Code::Unknown(value) => value,
+        }
+    }
+}
+
+impl TryFrom<u8> for Code {
+    type Error = u8;
+    fn try_from(value: u8) -> Result<Code, u8> {
+        match value {
+            0x01 => Ok(Code::IllegalFunction),
+            0x02 => Ok(Code::IllegalDataAddress),
+            0x03 => Ok(Code::IllegalDataValue),
+            0x04 => Ok(Code::SlaveDeviceFailure),
+            0x05 => Ok(Code::Acknowledge),
+            0x06 => Ok(Code::SlaveDeviceBusy),
+            0x08 => Ok(Code::MemoryParityError),
+            0x0A => Ok(Code::GatewayPathUnavailable),
+            0x0B => Ok(Code::GatewayTargetDeciveFailedToRespond),
+            _ => Err(value),
+        }
     }
 }
 
@@ -35,4 +79,39 @@ mod test {
         assert_eq!(u8::from(Code::GatewayPathUnavailable), 0x0A);
         assert_eq!(u8::from(Code::GatewayTargetDeciveFailedToRespond), 0x0B);
     }
+
+    #[test]
+    fn try_from_u8() {
+        for value in 0..=u8::MAX {
+            if let Ok(code) = Code::try_from(value) {
+                assert_eq!(u8::from(code), value);
+            }
+        }
+        assert_eq!(Code::try_from(0x02), Ok(Code::IllegalDataAddress));
+        assert_eq!(Code::try_from(0x07), Err(0x07));
+        assert_eq!(Code::try_from(0x00), Err(0x00));
+    }
+
+    #[test]
+    fn from_u8() {
+        let codes = [
+            Code::IllegalFunction,
+            Code::IllegalDataAddress,
+            Code::IllegalDataValue,
+            Code::SlaveDeviceFailure,
+            Code::Acknowledge,
+            Code::SlaveDeviceBusy,
+            Code::MemoryParityError,
+            Code::GatewayPathUnavailable,
+            Code::GatewayTargetDeciveFailedToRespond,
+        ];
+        for code in codes {
+            assert_eq!(Code::from_u8(u8::from(code)), Some(code));
+        }
+        for value in 0x1..=u8::MAX {
+            assert_eq!(u8::from(Code::from_u8(value).unwrap()), value);
+        }
+        assert_eq!(Code::from_u8(0x07), Some(Code::Unknown(0x07)));
+        assert_eq!(Code::from_u8(0x00), None);
+    }
 }
@@ -1,10 +1,17 @@
+pub mod device_id;
 pub mod exception;
 pub mod pdu;
 
+use crate::codec::error::Error as CodecError;
+use crate::codec::pduext::is_known_function;
+use crate::codec::slave::CodecMode;
+use exception::Code;
 use pdu::{RequestPdu, ResponsePdu};
 
 pub mod prelude {
+    pub use super::device_id::DeviceId;
     pub use super::exception::Code as ExceptionCode;
+    pub use super::pdu::ReadResult;
     pub use super::pdu::RequestPdu;
     pub use super::pdu::ResponsePdu;
     pub use super::RequestFrame;
@@ -42,12 +49,43 @@ impl ResponseFrame {
     pub fn from_parts(id: u16, slave: u8, pdu: ResponsePdu) -> ResponseFrame {
         ResponseFrame { id, slave, pdu }
     }
+
+    /// length of the encoded frame: slave + PDU + CRC for RTU, MBAP + PDU for Net
+    pub fn wire_len(&self, mode: &CodecMode) -> usize {
+        match mode {
+            CodecMode::Rtu => self.pdu.len() + 3,
+            CodecMode::Net => self.pdu.len() + 7,
+            // ':' + hex of slave, PDU and LRC + CRLF
+            CodecMode::Ascii => 2 * (self.pdu.len() + 2) + 3,
+        }
+    }
+
+    /// Exception answer for a request that can't be decoded, for gateways
+    /// that parse the slave id and function themselves. None for bad CRC:
+    /// the id and function of such frames can't be trusted, so they must
+    /// not be answered. The transports of the crate drop undecodable input
+    pub fn from_decode_error(
+        id: u16,
+        slave: u8,
+        func: u8,
+        err: &CodecError,
+    ) -> Option<ResponseFrame> {
+        let code = match err {
+            CodecError::InvalidCrc => return None,
+            _ if !is_known_function(func) => Code::IllegalFunction,
+            CodecError::InvalidData | CodecError::BufferToSmall => Code::IllegalDataValue,
+            CodecError::InvalidVersion | CodecError::Other => Code::SlaveDeviceFailure,
+        };
+        let pdu = ResponsePdu::exception(func, code);
+        Some(ResponseFrame::from_parts(id, slave, pdu))
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::data::helpers;
+    use crate::data::prelude::Data;
     use crate::frame::prelude::*;
 
     #[test]
@@ -60,6 +98,223 @@ mod test {
         }
     }
 
+    #[test]
+    fn from_decode_error() {
+        let check = [
+            (
+                0x3,
+                CodecError::InvalidData,
+                ExceptionCode::IllegalDataValue,
+            ),
+            (
+                0x10,
+                CodecError::BufferToSmall,
+                ExceptionCode::IllegalDataValue,
+            ),
+            (
+                0x3,
+                CodecError::InvalidVersion,
+                ExceptionCode::SlaveDeviceFailure,
+            ),
+            (0x3, CodecError::Other, ExceptionCode::SlaveDeviceFailure),
+            (
+                0x17,
+                CodecError::InvalidData,
+                ExceptionCode::IllegalDataValue,
+            ),
+            (
+                0x18,
+                CodecError::BufferToSmall,
+                ExceptionCode::IllegalDataValue,
+            ),
+            (
+                0x41,
+                CodecError::InvalidData,
+                ExceptionCode::IllegalFunction,
+            ),
+            (0x41, CodecError::Other, ExceptionCode::IllegalFunction),
+        ];
+
+        for (func, err, code) in check {
+            let frame = ResponseFrame::from_decode_error(0x1234, 0x11, func, &err);
+            assert_eq!(
+                frame,
+                Some(ResponseFrame::from_parts(
+                    0x1234,
+                    0x11,
+                    ResponsePdu::exception(func, code)
+                ))
+            );
+        }
+
+        // never answered
+        for func in [0x3, 0x41] {
+            let err = CodecError::InvalidCrc;
+            assert!(ResponseFrame::from_decode_error(0x1234, 0x11, func, &err).is_none());
+        }
+    }
+
+    #[test]
+    fn read_result() {
+        let coils = [
+            true, false, true, true, false, false, false, false, true, true,
+        ];
+        let pdu = ResponsePdu::read_coils(coils.as_slice());
+        assert_eq!(
+            pdu.into_read_result(10),
+            Some(ReadResult::Coils(coils.to_vec()))
+        );
+
+        // padding bits of the last byte are not coils
+        let pdu = ResponsePdu::read_discrete_inputs(coils[..3].as_ref());
+        assert_eq!(
+            pdu.into_read_result(3),
+            Some(ReadResult::Coils(vec![true, false, true]))
+        );
+
+        let pdu = ResponsePdu::read_holding_registers([0x1234u16, 0x5678].as_slice());
+        assert_eq!(
+            pdu.into_read_result(2),
+            Some(ReadResult::Registers(vec![0x1234, 0x5678]))
+        );
+
+        let pdu = ResponsePdu::read_input_registers([0x1234u16, 0x5678].as_slice());
+        assert_eq!(
+            pdu.into_read_result(1),
+            Some(ReadResult::Registers(vec![0x1234]))
+        );
+
+        let pdu = ResponsePdu::write_single_register(0x1, 0x2);
+        assert_eq!(pdu.into_read_result(1), None);
+    }
+
+    #[test]
+    fn accessors() {
+        let coils = [
+            true, false, true, true, false, false, false, false, true, true,
+        ];
+        let pdu = ResponsePdu::read_coils(coils.as_slice());
+        assert_eq!(pdu.as_coils(), Some(coils.to_vec()));
+        assert_eq!(pdu.as_registers(), None);
+        let pdu = ResponsePdu::read_discrete_inputs(coils[..3].as_ref());
+        assert_eq!(pdu.as_coils(), Some(vec![true, false, true]));
+
+        let pdu = ResponsePdu::read_holding_registers([0x1234u16, 0x5678].as_slice());
+        assert_eq!(pdu.as_registers(), Some(vec![0x1234, 0x5678]));
+        assert_eq!(pdu.as_coils(), None);
+        let pdu = ResponsePdu::read_fifo_queue([0x1B8u16].as_slice());
+        assert_eq!(pdu.as_registers(), Some(vec![0x1B8]));
+
+        let pdu = ResponsePdu::exception(0x3, ExceptionCode::IllegalDataAddress);
+        assert_eq!(
+            pdu.exception_code(),
+            Some(ExceptionCode::IllegalDataAddress)
+        );
+        assert_eq!(pdu.as_registers(), None);
+        let pdu = ResponsePdu::write_single_register(0x1, 0x2);
+        assert_eq!(pdu.exception_code(), None);
+    }
+
+    #[test]
+    fn prefer_single_register() {
+        assert!(!pdu::prefer_single_register(0));
+        assert!(pdu::prefer_single_register(1));
+        assert!(!pdu::prefer_single_register(2));
+        assert!(!pdu::prefer_single_register(125));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_json() {
+        let pdu = RequestPdu::write_multiple_registers(0x10, [0x1234u16, 0x5678].as_slice());
+        let json = serde_json::to_string(&pdu).unwrap();
+        assert!(json.starts_with(r#"{"WriteMultipleRegisters":{"address":16,"nobjs":2,"data":["#));
+        assert_eq!(serde_json::from_str::<RequestPdu>(&json).unwrap(), pdu);
+
+        let pdu = ResponsePdu::exception(0x3, ExceptionCode::IllegalDataAddress);
+        let json = serde_json::to_string(&pdu).unwrap();
+        assert_eq!(serde_json::from_str::<ResponsePdu>(&json).unwrap(), pdu);
+    }
+
+    #[test]
+    fn display() {
+        let pdu = RequestPdu::read_holding_registers(0x6B, 3);
+        assert_eq!(
+            pdu.to_string(),
+            "FC3 read_holding_registers addr=0x006B count=3"
+        );
+        let pdu = RequestPdu::write_single_coil(0xAC, true);
+        assert_eq!(
+            pdu.to_string(),
+            "FC5 write_single_coil addr=0x00AC value=true"
+        );
+        let pdu = RequestPdu::raw(0x41, Data::raw(&[0x1, 0x2]));
+        assert_eq!(pdu.to_string(), "FC65 raw len=2");
+
+        let pdu = ResponsePdu::write_multiple_registers(0x1, 2);
+        assert_eq!(
+            pdu.to_string(),
+            "FC16 write_multiple_registers addr=0x0001 count=2"
+        );
+        let pdu = ResponsePdu::exception(0x3, ExceptionCode::IllegalDataAddress);
+        assert_eq!(pdu.to_string(), "FC3 exception IllegalDataAddress");
+    }
+
+    #[test]
+    fn try_constructors() {
+        assert_eq!(
+            RequestPdu::try_read_coils(0x10, 2000),
+            Ok(RequestPdu::read_coils(0x10, 2000))
+        );
+        assert_eq!(
+            RequestPdu::try_read_holding_registers(0x10, 125),
+            Ok(RequestPdu::read_holding_registers(0x10, 125))
+        );
+        assert_eq!(
+            RequestPdu::try_write_multiple_registers(0x10, [0x1u16, 0x2].as_slice()),
+            Ok(RequestPdu::write_multiple_registers(
+                0x10,
+                [0x1u16, 0x2].as_slice()
+            ))
+        );
+
+        let invalid = Err(CodecError::InvalidData);
+        assert_eq!(RequestPdu::try_read_coils(0x10, 0), invalid);
+        assert_eq!(RequestPdu::try_read_discrete_inputs(0x10, 2001), invalid);
+        assert_eq!(RequestPdu::try_read_holding_registers(0x10, 126), invalid);
+        assert_eq!(RequestPdu::try_read_input_registers(0x10, 0), invalid);
+        assert_eq!(
+            RequestPdu::try_write_multiple_coils(0x10, [true; 2001].as_slice()),
+            invalid
+        );
+        assert_eq!(
+            RequestPdu::try_write_multiple_registers(0x10, [0u16; 126].as_slice()),
+            invalid
+        );
+        assert_eq!(
+            RequestPdu::try_read_write_multiple_registers(0x10, 1, 0x20, [0u16; 122].as_slice()),
+            invalid
+        );
+    }
+
+    #[test]
+    fn build_write_registers() {
+        let pdu = RequestPdu::write_registers(0x10, [0x1234u16].as_slice(), true);
+        assert_eq!(pdu, RequestPdu::write_single_register(0x10, 0x1234));
+
+        let pdu = RequestPdu::write_registers(0x10, [0x1234u16].as_slice(), false);
+        assert_eq!(
+            pdu,
+            RequestPdu::write_multiple_registers(0x10, [0x1234u16].as_slice())
+        );
+
+        let pdu = RequestPdu::write_registers(0x10, [1u16, 2].as_slice(), true);
+        assert_eq!(
+            pdu,
+            RequestPdu::write_multiple_registers(0x10, [1u16, 2].as_slice())
+        );
+    }
+
     #[test]
     fn build_fc1_response_builder() {
         let nbits = 37;
@@ -171,7 +426,7 @@ mod test {
         match frame.pdu {
             ResponsePdu::WriteSingleCoil { address, value } => {
                 assert_eq!(address, 0x00AC);
-                assert_eq!(value, true);
+                assert!(value);
             }
             _ => unreachable!(),
         }
@@ -1,8 +1,24 @@
 use super::exception::Code;
-use crate::data::checks;
+use crate::codec::error::Error;
 use crate::data::prelude::*;
+use crate::data::{checks, MAX_FIFO_COUNT, MAX_RW_WRITE_NREGS};
+use std::fmt;
 
-#[derive(Debug, PartialEq, Eq)]
+/// true if a block of registers could be written with 0x6 instead of 0x10
+pub fn prefer_single_register(len: u16) -> bool {
+    len == 1
+}
+
+fn valid(ok: bool) -> Result<(), Error> {
+    if ok {
+        Ok(())
+    } else {
+        Err(Error::InvalidData)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum RequestPdu {
     /// 0x1
     ReadCoils {
@@ -40,6 +56,15 @@ pub enum RequestPdu {
         value: u16,
     },
 
+    /// 0x7
+    ReadExceptionStatus,
+
+    /// 0x8
+    Diagnostic {
+        sub_function: u16,
+        data: u16,
+    },
+
     /// 0xF
     WriteMultipleCoils {
         address: u16,
@@ -54,6 +79,30 @@ pub enum RequestPdu {
         data: Data,
     },
 
+    /// 0x11
+    ReportSlaveId,
+
+    /// 0x16
+    MaskWriteRegister {
+        address: u16,
+        and_mask: u16,
+        or_mask: u16,
+    },
+
+    /// 0x17. The write is done before the read
+    ReadWriteMultipleRegisters {
+        read_address: u16,
+        read_nobjs: u16,
+        write_address: u16,
+        write_nobjs: u16,
+        data: Data,
+    },
+
+    /// 0x18
+    ReadFifoQueue {
+        address: u16,
+    },
+
     /// 0x2b
     EncapsulatedInterfaceTransport {
         mei_type: u8,
@@ -101,6 +150,55 @@ impl RequestPdu {
         RequestPdu::WriteSingleRegister { address, value }
     }
 
+    /// 0x7
+    pub fn read_exception_status() -> RequestPdu {
+        RequestPdu::ReadExceptionStatus
+    }
+
+    /// 0x8
+    pub fn diagnostic(sub_function: u16, data: u16) -> RequestPdu {
+        RequestPdu::Diagnostic { sub_function, data }
+    }
+
+    /// 0x11
+    pub fn report_slave_id() -> RequestPdu {
+        RequestPdu::ReportSlaveId
+    }
+
+    /// 0x16
+    pub fn mask_write_register(address: u16, and_mask: u16, or_mask: u16) -> RequestPdu {
+        RequestPdu::MaskWriteRegister {
+            address,
+            and_mask,
+            or_mask,
+        }
+    }
+
+    /// 0x17
+    pub fn read_write_multiple_registers(
+        read_address: u16,
+        read_nobjs: u16,
+        write_address: u16,
+        registers: impl Registers,
+    ) -> RequestPdu {
+        let write_nobjs = registers.registers_count();
+        assert!(checks::check_registers_count(read_nobjs));
+        assert!(checks::check_registers_count(write_nobjs));
+        assert!(write_nobjs as usize <= MAX_RW_WRITE_NREGS);
+        RequestPdu::ReadWriteMultipleRegisters {
+            read_address,
+            read_nobjs,
+            write_address,
+            write_nobjs,
+            data: Data::registers(registers),
+        }
+    }
+
+    /// 0x18
+    pub fn read_fifo_queue(address: u16) -> RequestPdu {
+        RequestPdu::ReadFifoQueue { address }
+    }
+
     /// 0xF
     pub fn write_multiple_coils(address: u16, coils: impl Coils) -> RequestPdu {
         let nobjs = coils.coils_count();
@@ -123,6 +221,21 @@ impl RequestPdu {
         }
     }
 
+    /// 0x6 or 0x10. A single register is sent with 0x6 if prefer_single is set.
+    /// Useful for slaves that don't implement 0x10
+    pub fn write_registers(
+        address: u16,
+        registers: impl Registers,
+        prefer_single: bool,
+    ) -> RequestPdu {
+        if prefer_single && prefer_single_register(registers.registers_count()) {
+            let data = Data::registers(registers);
+            RequestPdu::write_single_register(address, data.get_u16(0).unwrap())
+        } else {
+            RequestPdu::write_multiple_registers(address, registers)
+        }
+    }
+
     /// 0x2b
     pub fn encapsulated_interface_transport(mei_type: u8, bytes: impl Bytes) -> RequestPdu {
         let len = bytes.bytes_count() as usize;
@@ -135,6 +248,64 @@ impl RequestPdu {
         RequestPdu::EncapsulatedInterfaceTransport { mei_type, data }
     }
 
+    /// 0x1. Error instead of a panic if the count is out of range
+    pub fn try_read_coils(address: u16, nobjs: u16) -> Result<RequestPdu, Error> {
+        valid(checks::check_coils_count(nobjs))?;
+        Ok(RequestPdu::read_coils(address, nobjs))
+    }
+
+    /// 0x2. Error instead of a panic if the count is out of range
+    pub fn try_read_discrete_inputs(address: u16, nobjs: u16) -> Result<RequestPdu, Error> {
+        valid(checks::check_coils_count(nobjs))?;
+        Ok(RequestPdu::read_discrete_inputs(address, nobjs))
+    }
+
+    /// 0x3. Error instead of a panic if the count is out of range
+    pub fn try_read_holding_registers(address: u16, nobjs: u16) -> Result<RequestPdu, Error> {
+        valid(checks::check_registers_count(nobjs))?;
+        Ok(RequestPdu::read_holding_registers(address, nobjs))
+    }
+
+    /// 0x4. Error instead of a panic if the count is out of range
+    pub fn try_read_input_registers(address: u16, nobjs: u16) -> Result<RequestPdu, Error> {
+        valid(checks::check_registers_count(nobjs))?;
+        Ok(RequestPdu::read_input_registers(address, nobjs))
+    }
+
+    /// 0xF. Error instead of a panic if the count is out of range
+    pub fn try_write_multiple_coils(address: u16, coils: impl Coils) -> Result<RequestPdu, Error> {
+        valid(checks::check_coils_count(coils.coils_count()))?;
+        Ok(RequestPdu::write_multiple_coils(address, coils))
+    }
+
+    /// 0x10. Error instead of a panic if the count is out of range
+    pub fn try_write_multiple_registers(
+        address: u16,
+        registers: impl Registers,
+    ) -> Result<RequestPdu, Error> {
+        valid(checks::check_registers_count(registers.registers_count()))?;
+        Ok(RequestPdu::write_multiple_registers(address, registers))
+    }
+
+    /// 0x17. Error instead of a panic if a count is out of range
+    pub fn try_read_write_multiple_registers(
+        read_address: u16,
+        read_nobjs: u16,
+        write_address: u16,
+        registers: impl Registers,
+    ) -> Result<RequestPdu, Error> {
+        let write_nobjs = registers.registers_count();
+        valid(checks::check_registers_count(read_nobjs))?;
+        valid(checks::check_registers_count(write_nobjs))?;
+        valid(write_nobjs as usize <= MAX_RW_WRITE_NREGS)?;
+        Ok(RequestPdu::read_write_multiple_registers(
+            read_address,
+            read_nobjs,
+            write_address,
+            registers,
+        ))
+    }
+
     /// Raw
     pub fn raw(func: u8, data: Data) -> RequestPdu {
         RequestPdu::Raw {
@@ -150,7 +321,12 @@ impl RequestPdu {
             | RequestPdu::ReadHoldingRegisters { .. }
             | RequestPdu::ReadInputRegisters { .. }
             | RequestPdu::WriteSingleCoil { .. }
-            | RequestPdu::WriteSingleRegister { .. } => 5,
+            | RequestPdu::WriteSingleRegister { .. }
+            | RequestPdu::Diagnostic { .. } => 5,
+            RequestPdu::ReadExceptionStatus | RequestPdu::ReportSlaveId => 1,
+            RequestPdu::MaskWriteRegister { .. } => 7,
+            RequestPdu::ReadWriteMultipleRegisters { data, .. } => 10 + data.len(),
+            RequestPdu::ReadFifoQueue { .. } => 3,
 
             RequestPdu::WriteMultipleCoils { data, .. }
             | RequestPdu::WriteMultipleRegisters { data, .. } => 6 + data.len(),
@@ -172,15 +348,22 @@ impl RequestPdu {
             RequestPdu::ReadInputRegisters { .. } => Some(0x4),
             RequestPdu::WriteSingleCoil { .. } => Some(0x5),
             RequestPdu::WriteSingleRegister { .. } => Some(0x6),
+            RequestPdu::ReadExceptionStatus => Some(0x7),
+            RequestPdu::Diagnostic { .. } => Some(0x8),
             RequestPdu::WriteMultipleCoils { .. } => Some(0xF),
             RequestPdu::WriteMultipleRegisters { .. } => Some(0x10),
+            RequestPdu::ReportSlaveId => Some(0x11),
+            RequestPdu::MaskWriteRegister { .. } => Some(0x16),
+            RequestPdu::ReadWriteMultipleRegisters { .. } => Some(0x17),
+            RequestPdu::ReadFifoQueue { .. } => Some(0x18),
             RequestPdu::EncapsulatedInterfaceTransport { .. } => Some(0x2b),
             RequestPdu::Raw { function, .. } => Some(*function),
         }
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ResponsePdu {
     /// 0x1
     ReadCoils {
@@ -218,6 +401,17 @@ pub enum ResponsePdu {
         value: u16,
     },
 
+    /// 0x7
+    ReadExceptionStatus {
+        data: u8,
+    },
+
+    /// 0x8
+    Diagnostic {
+        sub_function: u16,
+        data: u16,
+    },
+
     /// 0xF
     WriteMultipleCoils {
         address: u16,
@@ -230,6 +424,30 @@ pub enum ResponsePdu {
         nobjs: u16,
     },
 
+    /// 0x11. Slave id, run indicator and device specific data
+    ReportSlaveId {
+        data: Data,
+    },
+
+    /// 0x16
+    MaskWriteRegister {
+        address: u16,
+        and_mask: u16,
+        or_mask: u16,
+    },
+
+    /// 0x17
+    ReadWriteMultipleRegisters {
+        nobjs: u16,
+        data: Data,
+    },
+
+    /// 0x18. Up to 31 values
+    ReadFifoQueue {
+        count: u16,
+        data: Data,
+    },
+
     /// 0x2b
     EncapsulatedInterfaceTransport {
         mei_type: u8,
@@ -248,17 +466,86 @@ pub enum ResponsePdu {
     },
 }
 
+/// Values of a read response regardless of the function
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReadResult {
+    Coils(Vec<bool>),
+    Registers(Vec<u16>),
+}
+
 impl ResponsePdu {
+    /// Values of fc1-fc4 and fc23 responses. Packed coils are expanded to the
+    /// requested count, so padding bits of the last byte are dropped
+    pub fn into_read_result(self, requested_count: u16) -> Option<ReadResult> {
+        match self {
+            ResponsePdu::ReadCoils { data, .. } | ResponsePdu::ReadDiscreteInputs { data, .. } => {
+                let count = (requested_count as usize).min(data.len() * 8);
+                let coils = (0..count).filter_map(|idx| data.get_bit(idx)).collect();
+                Some(ReadResult::Coils(coils))
+            }
+            ResponsePdu::ReadHoldingRegisters { data, .. }
+            | ResponsePdu::ReadInputRegisters { data, .. }
+            | ResponsePdu::ReadWriteMultipleRegisters { data, .. } => {
+                let count = (requested_count as usize).min(data.len() / 2);
+                let registers = (0..count).filter_map(|idx| data.get_u16(idx)).collect();
+                Some(ReadResult::Registers(registers))
+            }
+            _ => None,
+        }
+    }
+
+    /// Values of fc3/fc4/fc23/fc24 responses
+    pub fn as_registers(&self) -> Option<Vec<u16>> {
+        match self {
+            ResponsePdu::ReadHoldingRegisters { nobjs, data }
+            | ResponsePdu::ReadInputRegisters { nobjs, data }
+            | ResponsePdu::ReadWriteMultipleRegisters { nobjs, data }
+            | ResponsePdu::ReadFifoQueue { count: nobjs, data } => Some(
+                (0..*nobjs as usize)
+                    .map_while(|idx| data.get_u16(idx))
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Values of fc1/fc2 responses. Decoded responses don't know the
+    /// requested count, so padding bits of the last byte are included
+    pub fn as_coils(&self) -> Option<Vec<bool>> {
+        match self {
+            ResponsePdu::ReadCoils { nobjs, data }
+            | ResponsePdu::ReadDiscreteInputs { nobjs, data } => Some(
+                (0..*nobjs as usize)
+                    .map_while(|idx| data.get_bit(idx))
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+
+    pub fn exception_code(&self) -> Option<Code> {
+        match self {
+            ResponsePdu::Exception { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+
     pub fn len(&self) -> usize {
         match self {
             ResponsePdu::ReadCoils { data, .. }
             | ResponsePdu::ReadDiscreteInputs { data, .. }
             | ResponsePdu::ReadHoldingRegisters { data, .. }
-            | ResponsePdu::ReadInputRegisters { data, .. } => 2 + data.len(),
+            | ResponsePdu::ReadInputRegisters { data, .. }
+            | ResponsePdu::ReadWriteMultipleRegisters { data, .. }
+            | ResponsePdu::ReportSlaveId { data } => 2 + data.len(),
             ResponsePdu::WriteSingleCoil { .. }
             | ResponsePdu::WriteSingleRegister { .. }
             | ResponsePdu::WriteMultipleCoils { .. }
-            | ResponsePdu::WriteMultipleRegisters { .. } => 5,
+            | ResponsePdu::WriteMultipleRegisters { .. }
+            | ResponsePdu::Diagnostic { .. } => 5,
+            ResponsePdu::MaskWriteRegister { .. } => 7,
+            ResponsePdu::ReadFifoQueue { data, .. } => 5 + data.len(),
+            ResponsePdu::ReadExceptionStatus { .. } => 2,
             ResponsePdu::EncapsulatedInterfaceTransport { data, .. } => 2 + data.len(),
             ResponsePdu::Raw { data, .. } => 1 + data.len(),
             ResponsePdu::Exception { .. } => 2,
@@ -301,6 +588,58 @@ impl ResponsePdu {
         ResponsePdu::WriteSingleRegister { address, value }
     }
 
+    /// 0x7
+    pub fn read_exception_status(data: u8) -> ResponsePdu {
+        ResponsePdu::ReadExceptionStatus { data }
+    }
+
+    /// 0x8
+    pub fn diagnostic(sub_function: u16, data: u16) -> ResponsePdu {
+        ResponsePdu::Diagnostic { sub_function, data }
+    }
+
+    /// 0x16
+    pub fn mask_write_register(address: u16, and_mask: u16, or_mask: u16) -> ResponsePdu {
+        ResponsePdu::MaskWriteRegister {
+            address,
+            and_mask,
+            or_mask,
+        }
+    }
+
+    /// 0x17
+    pub fn read_write_multiple_registers(registers: impl Registers) -> ResponsePdu {
+        let nobjs = registers.registers_count();
+        assert!(checks::check_registers_count(nobjs));
+        ResponsePdu::ReadWriteMultipleRegisters {
+            nobjs,
+            data: Data::registers(registers),
+        }
+    }
+
+    /// 0x18
+    pub fn read_fifo_queue(values: &[u16]) -> ResponsePdu {
+        assert!(values.len() <= MAX_FIFO_COUNT);
+        // an empty queue is valid
+        let data = if values.is_empty() {
+            Data::raw_empty(0)
+        } else {
+            Data::registers(values)
+        };
+        ResponsePdu::ReadFifoQueue {
+            count: values.len() as u16,
+            data,
+        }
+    }
+
+    /// 0x11
+    pub fn report_slave_id(data: &[u8]) -> ResponsePdu {
+        assert!(checks::checks_bytes_count(data.len()));
+        ResponsePdu::ReportSlaveId {
+            data: Data::raw(data),
+        }
+    }
+
     /// 0xF
     pub fn write_multiple_coils(address: u16, nobjs: u16) -> ResponsePdu {
         assert!(checks::check_coils_count(nobjs));
@@ -375,3 +714,165 @@ impl ResponsePdu {
         }
     }
 }
+
+/// One line summary, e.g. `FC3 read_holding_registers addr=0x006B count=3`
+impl fmt::Display for RequestPdu {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "FC{} ", self.func().unwrap_or(0))?;
+        match self {
+            RequestPdu::ReadCoils { address, nobjs } => {
+                write!(f, "read_coils addr=0x{:04X} count={}", address, nobjs)
+            }
+            RequestPdu::ReadDiscreteInputs { address, nobjs } => write!(
+                f,
+                "read_discrete_inputs addr=0x{:04X} count={}",
+                address, nobjs
+            ),
+            RequestPdu::ReadHoldingRegisters { address, nobjs } => write!(
+                f,
+                "read_holding_registers addr=0x{:04X} count={}",
+                address, nobjs
+            ),
+            RequestPdu::ReadInputRegisters { address, nobjs } => write!(
+                f,
+                "read_input_registers addr=0x{:04X} count={}",
+                address, nobjs
+            ),
+            RequestPdu::WriteSingleCoil { address, value } => {
+                write!(
+                    f,
+                    "write_single_coil addr=0x{:04X} value={}",
+                    address, value
+                )
+            }
+            RequestPdu::WriteSingleRegister { address, value } => write!(
+                f,
+                "write_single_register addr=0x{:04X} value=0x{:04X}",
+                address, value
+            ),
+            RequestPdu::ReadExceptionStatus => write!(f, "read_exception_status"),
+            RequestPdu::Diagnostic { sub_function, data } => write!(
+                f,
+                "diagnostic sub=0x{:04X} data=0x{:04X}",
+                sub_function, data
+            ),
+            RequestPdu::WriteMultipleCoils { address, nobjs, .. } => write!(
+                f,
+                "write_multiple_coils addr=0x{:04X} count={}",
+                address, nobjs
+            ),
+            RequestPdu::WriteMultipleRegisters { address, nobjs, .. } => write!(
+                f,
+                "write_multiple_registers addr=0x{:04X} count={}",
+                address, nobjs
+            ),
+            RequestPdu::ReportSlaveId => write!(f, "report_slave_id"),
+            RequestPdu::MaskWriteRegister {
+                address,
+                and_mask,
+                or_mask,
+            } => write!(
+                f,
+                "mask_write_register addr=0x{:04X} and=0x{:04X} or=0x{:04X}",
+                address, and_mask, or_mask
+            ),
+            RequestPdu::ReadWriteMultipleRegisters {
+                read_address,
+                read_nobjs,
+                write_address,
+                write_nobjs,
+                ..
+            } => write!(
+                f,
+                "read_write_multiple_registers read=0x{:04X} count={} write=0x{:04X} count={}",
+                read_address, read_nobjs, write_address, write_nobjs
+            ),
+            RequestPdu::ReadFifoQueue { address } => {
+                write!(f, "read_fifo_queue addr=0x{:04X}", address)
+            }
+            RequestPdu::EncapsulatedInterfaceTransport { mei_type, data } => write!(
+                f,
+                "encapsulated_interface_transport mei=0x{:02X} len={}",
+                mei_type,
+                data.len()
+            ),
+            RequestPdu::Raw { data, .. } => write!(f, "raw len={}", data.len()),
+        }
+    }
+}
+
+/// One line summary, e.g. `FC3 read_holding_registers count=3`
+impl fmt::Display for ResponsePdu {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResponsePdu::ReadCoils { nobjs, .. } => write!(f, "FC1 read_coils count={}", nobjs),
+            ResponsePdu::ReadDiscreteInputs { nobjs, .. } => {
+                write!(f, "FC2 read_discrete_inputs count={}", nobjs)
+            }
+            ResponsePdu::ReadHoldingRegisters { nobjs, .. } => {
+                write!(f, "FC3 read_holding_registers count={}", nobjs)
+            }
+            ResponsePdu::ReadInputRegisters { nobjs, .. } => {
+                write!(f, "FC4 read_input_registers count={}", nobjs)
+            }
+            ResponsePdu::WriteSingleCoil { address, value } => write!(
+                f,
+                "FC5 write_single_coil addr=0x{:04X} value={}",
+                address, value
+            ),
+            ResponsePdu::WriteSingleRegister { address, value } => write!(
+                f,
+                "FC6 write_single_register addr=0x{:04X} value=0x{:04X}",
+                address, value
+            ),
+            ResponsePdu::ReadExceptionStatus { data } => {
+                write!(f, "FC7 read_exception_status data=0x{:02X}", data)
+            }
+            ResponsePdu::Diagnostic { sub_function, data } => write!(
+                f,
+                "FC8 diagnostic sub=0x{:04X} data=0x{:04X}",
+                sub_function, data
+            ),
+            ResponsePdu::WriteMultipleCoils { address, nobjs } => write!(
+                f,
+                "FC15 write_multiple_coils addr=0x{:04X} count={}",
+                address, nobjs
+            ),
+            ResponsePdu::WriteMultipleRegisters { address, nobjs } => write!(
+                f,
+                "FC16 write_multiple_registers addr=0x{:04X} count={}",
+                address, nobjs
+            ),
+            ResponsePdu::ReportSlaveId { data } => {
+                write!(f, "FC17 report_slave_id len={}", data.len())
+            }
+            ResponsePdu::MaskWriteRegister {
+                address,
+                and_mask,
+                or_mask,
+            } => write!(
+                f,
+                "FC22 mask_write_register addr=0x{:04X} and=0x{:04X} or=0x{:04X}",
+                address, and_mask, or_mask
+            ),
+            ResponsePdu::ReadWriteMultipleRegisters { nobjs, .. } => {
+                write!(f, "FC23 read_write_multiple_registers count={}", nobjs)
+            }
+            ResponsePdu::ReadFifoQueue { count, .. } => {
+                write!(f, "FC24 read_fifo_queue count={}", count)
+            }
+            ResponsePdu::EncapsulatedInterfaceTransport { mei_type, data } => write!(
+                f,
+                "FC43 encapsulated_interface_transport mei=0x{:02X} len={}",
+                mei_type,
+                data.len()
+            ),
+            ResponsePdu::Raw { function, data } => {
+                write!(f, "FC{} raw len={}", function, data.len())
+            }
+            ResponsePdu::Exception { function, code } => {
+                write!(f, "FC{} exception {:?}", function & 0x7F, code)
+            }
+        }
+    }
+}
@@ -1,30 +1,88 @@
+use crate::codec::slave::SlaveCodec;
+use crate::frame::prelude::*;
 use crate::transport::{
+    cache::StaleCache,
+    error::TransportError,
     rtu::slave::RtuSlaveChannel,
     settings::{Settings, TransportAddress},
     tcp::server::TcpServer,
     udp::server::UdpServer,
-    Request,
+    Request, Response,
 };
 
 use futures::{Stream, StreamExt};
 use log::info;
-use std::io::Error;
+use std::collections::HashMap;
 
-pub async fn build(settings: Settings) -> Result<impl Stream<Item = Request>, Error> {
+pub async fn build(settings: Settings) -> Result<impl Stream<Item = Request>, TransportError> {
+    build_with(settings, None).await
+}
+
+/// build with a pre-configured codec instead of the default one for the
+/// address scheme. The codec must match the transport (RTU, TCP or UDP)
+pub async fn build_with_codec(
+    settings: Settings,
+    codec: SlaveCodec,
+) -> Result<impl Stream<Item = Request>, TransportError> {
+    build_with(settings, Some(codec)).await
+}
+
+async fn build_with(
+    settings: Settings,
+    codec: Option<SlaveCodec>,
+) -> Result<impl Stream<Item = Request>, TransportError> {
+    let timeout = settings.processing_timeout;
+    let deadline = settings.request_deadline;
+    let min_time = settings.min_response_time;
+    let cache = settings.stale_cache.map(StaleCache::new);
+    let stream = build_stream(settings, codec).await?;
+    Ok(stream.map(move |request| {
+        let request = match deadline {
+            Some(deadline) => request.with_deadline(deadline),
+            None => request,
+        };
+        let request = match timeout {
+            Some(timeout) => request.with_processing_timeout(timeout),
+            None => request,
+        };
+        let request = match min_time {
+            Some(min_time) => request.with_min_response_time(min_time),
+            None => request,
+        };
+        match &cache {
+            Some(cache) => request.with_stale_cache(cache),
+            None => request,
+        }
+    }))
+}
+
+async fn build_stream(
+    settings: Settings,
+    codec: Option<SlaveCodec>,
+) -> Result<impl Stream<Item = Request>, TransportError> {
     match &settings.address {
         TransportAddress::Tcp(address) => {
             info!("start tcp server {}", address);
-            let handler = TcpServer::build(settings).await?;
+            let codec = codec.unwrap_or_else(SlaveCodec::new_tcp);
+            let handler = TcpServer::build_with_codec(settings, codec).await?;
             Ok(handler.to_stream())
         }
         TransportAddress::Udp(address) => {
             info!("start udp server {}", address);
-            let handler = UdpServer::build(settings).await?;
+            let codec = codec.unwrap_or_else(SlaveCodec::new_udp);
+            let handler = UdpServer::build_with_codec(settings, codec).await?;
             Ok(handler.to_stream())
         }
         TransportAddress::Serial(address) => {
             info!("start rtu slave {}", address);
-            let handler = RtuSlaveChannel::build(settings).await?;
+            let codec = codec.unwrap_or_else(SlaveCodec::new_rtu);
+            let handler = RtuSlaveChannel::build_with_codec(settings, codec).await?;
+            Ok(handler.to_stream())
+        }
+        TransportAddress::Ascii(address) => {
+            info!("start ascii slave {}", address);
+            let codec = codec.unwrap_or_else(SlaveCodec::new_ascii);
+            let handler = RtuSlaveChannel::build_with_codec(settings, codec).await?;
             Ok(handler.to_stream())
         }
     }
@@ -32,12 +90,70 @@ pub async fn build(settings: Settings) -> Result<impl Stream<Item = Request>, Er
 
 pub struct SlaveTransport {}
 
+/// Requirements for request handlers. The handler is moved into a spawned
+/// task, so it must be `Send + 'static`: no borrowed data, and shared state
+/// goes behind `Arc<Mutex<..>>` (not `Rc`/`RefCell`). It's called from that one
+/// task only, so `Sync` is not required
+pub trait SlaveHandler: Fn(Request) + Send + 'static {}
+
+impl<H> SlaveHandler for H where H: Fn(Request) + Send + 'static {}
+
 //TODO:sas: For now, Fn handler is good enough. But it's a nice place for using Service
-pub async fn build_slave<H>(settings: Settings, handler: H) -> Result<SlaveTransport, Error>
+/// Run the transport and pass every request to `handler`. See SlaveHandler
+/// for the bounds
+pub async fn build_slave<H>(
+    settings: Settings,
+    handler: H,
+) -> Result<SlaveTransport, TransportError>
+where
+    H: SlaveHandler,
+{
+    Ok(run_slave(build(settings).await?, handler))
+}
+
+/// build_slave with a pre-configured codec. Useful for codec options that
+/// are not in Settings (limits, strictness, bit order)
+pub async fn build_slave_with_codec<H>(
+    settings: Settings,
+    codec: SlaveCodec,
+    handler: H,
+) -> Result<SlaveTransport, TransportError>
 where
-    H: Fn(Request) + std::marker::Send + 'static,
+    H: SlaveHandler,
+{
+    Ok(run_slave(build_with_codec(settings, codec).await?, handler))
+}
+
+/// Run the transport and pass every request to the handler of its slave id.
+/// Other ids are answered with GatewayTargetDeviceFailedToRespond. Handlers
+/// of different types can be boxed: `Box<dyn Fn(Request) + Send>`
+pub async fn build_slave_router<H>(
+    settings: Settings,
+    handlers: HashMap<u8, H>,
+) -> Result<SlaveTransport, TransportError>
+where
+    H: SlaveHandler,
+{
+    build_slave(settings, move |request: Request| {
+        match handlers.get(&request.slave) {
+            Some(handler) => handler(request),
+            None => {
+                let func = request.pdu.func().unwrap_or(0);
+                let code = ExceptionCode::GatewayTargetDeciveFailedToRespond;
+                let _ = Response::make(request, ResponsePdu::exception(func, code)).send();
+            }
+        }
+    })
+    .await
+}
+
+fn run_slave<H>(
+    mut stream: impl Stream<Item = Request> + Unpin + Send + 'static,
+    handler: H,
+) -> SlaveTransport
+where
+    H: SlaveHandler,
 {
-    let mut stream = build(settings).await?;
     tokio::spawn(async move {
         loop {
             tokio::select! {
@@ -48,5 +164,197 @@ where
         }
     });
 
-    Ok(SlaveTransport {})
+    SlaveTransport {}
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::codec::slave::Limits;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn assert_handler<H: SlaveHandler>(_: &H) {}
+
+    #[tokio::test]
+    async fn build_address_error() {
+        let settings = Settings {
+            address: TransportAddress::Serial("/dev/ttyUSB0".to_owned()),
+            ..Default::default()
+        };
+        let err = build_slave(settings, |_| {}).await.err().unwrap();
+        assert!(matches!(err, TransportError::Address(address) if address == "/dev/ttyUSB0"));
+    }
+
+    #[tokio::test]
+    async fn build_bind_error() {
+        let busy = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = busy.local_addr().unwrap().to_string();
+        let settings = Settings {
+            address: TransportAddress::Tcp(address),
+            ..Default::default()
+        };
+        let err = build_slave(settings, |_| {}).await.err().unwrap();
+        assert!(matches!(err, TransportError::Bind(_)));
+    }
+
+    #[tokio::test]
+    async fn build_with_max_registers() {
+        let settings = Settings {
+            address: TransportAddress::Tcp("127.0.0.1:15230".to_owned()),
+            ..Default::default()
+        };
+        let limits = Limits {
+            max_registers: 10,
+            ..Default::default()
+        };
+        let codec = SlaveCodec::new_tcp().with_limits(limits);
+        build_slave_with_codec(settings, codec, |request| {
+            let pdu = ResponsePdu::read_holding_registers([0u16; 10].as_slice());
+            let _ = Response::make(request, pdu).send();
+        })
+        .await
+        .unwrap();
+
+        let mut stream = tokio::net::TcpStream::connect("127.0.0.1:15230")
+            .await
+            .unwrap();
+        let mut buffer = [0u8; 256];
+        let request = [0x0, 0x1, 0x0, 0x0, 0x0, 0x6, 0x1, 0x3, 0x0, 0x0, 0x0, 0xA];
+        stream.write_all(&request).await.unwrap();
+        let size = stream.read(&mut buffer).await.unwrap();
+        assert_eq!(buffer[7..9], [0x3, 20]);
+        assert_eq!(size, 29);
+
+        // over the limit, the connection is dropped
+        let request = [0x0, 0x2, 0x0, 0x0, 0x0, 0x6, 0x1, 0x3, 0x0, 0x0, 0x0, 0xB];
+        stream.write_all(&request).await.unwrap();
+        assert_eq!(stream.read(&mut buffer).await.unwrap(), 0);
+    }
+
+    // answers fc3 with `value`
+    fn responder(value: u16) -> Box<dyn Fn(Request) + Send> {
+        Box::new(move |request| {
+            let pdu = ResponsePdu::read_holding_registers([value].as_slice());
+            let _ = Response::make(request, pdu).send();
+        })
+    }
+
+    #[tokio::test]
+    async fn slave_router() {
+        let settings = Settings {
+            address: TransportAddress::Tcp("127.0.0.1:15245".to_owned()),
+            ..Default::default()
+        };
+        let handlers = HashMap::from([(1, responder(0x1111)), (2, responder(0x2222))]);
+        build_slave_router(settings, handlers).await.unwrap();
+
+        let mut stream = tokio::net::TcpStream::connect("127.0.0.1:15245")
+            .await
+            .unwrap();
+        let responses: [(u8, &[u8]); 3] = [
+            (1, &[0x3, 0x2, 0x11, 0x11]),
+            (2, &[0x3, 0x2, 0x22, 0x22]),
+            (3, &[0x83, 0x0B]),
+        ];
+        for (slave, pdu) in responses {
+            let request = [
+                0x0, slave, 0x0, 0x0, 0x0, 0x6, slave, 0x3, 0x0, 0x0, 0x0, 0x1,
+            ];
+            stream.write_all(&request).await.unwrap();
+            let mut buffer = [0u8; 256];
+            let size = stream.read(&mut buffer).await.unwrap();
+            assert_eq!(buffer[6], slave);
+            assert_eq!(&buffer[7..size], pdu);
+        }
+    }
+
+    #[tokio::test]
+    async fn many_responses() {
+        let settings = Settings {
+            address: TransportAddress::Tcp("127.0.0.1:15248".to_owned()),
+            ..Default::default()
+        };
+        build_slave(settings, |request: Request| {
+            let pdus = [0x1111u16, 0x2222, 0x3333]
+                .map(|value| ResponsePdu::read_holding_registers([value].as_slice()));
+            for response in Response::make_many(request, pdus.into()) {
+                let _ = response.send();
+            }
+        })
+        .await
+        .unwrap();
+
+        let mut stream = tokio::net::TcpStream::connect("127.0.0.1:15248")
+            .await
+            .unwrap();
+        let request = [0x0, 0x7, 0x0, 0x0, 0x0, 0x6, 0x1, 0x3, 0x0, 0x0, 0x0, 0x1];
+        stream.write_all(&request).await.unwrap();
+
+        // all three with the transaction id of the request
+        let mut response = [0u8; 11];
+        for value in [0x11, 0x22, 0x33] {
+            stream.read_exact(&mut response).await.unwrap();
+            assert_eq!(
+                response,
+                [0x0, 0x7, 0x0, 0x0, 0x0, 0x5, 0x1, 0x3, 0x2, value, value]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn request_deadline() {
+        let settings = Settings {
+            address: TransportAddress::Tcp("127.0.0.1:15247".to_owned()),
+            request_deadline: Some(Duration::from_millis(20)),
+            ..Default::default()
+        };
+        build_slave(settings, |request: Request| {
+            tokio::spawn(async move {
+                // slave 1 gives up on expired requests, slave 2 still answers
+                if request.slave != 3 {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                }
+                if request.slave == 1 && request.is_expired() {
+                    return;
+                }
+                let pdu = ResponsePdu::read_holding_registers([0x1234u16].as_slice());
+                let _ = Response::make(request, pdu).send();
+            });
+        })
+        .await
+        .unwrap();
+
+        let mut stream = tokio::net::TcpStream::connect("127.0.0.1:15247")
+            .await
+            .unwrap();
+        for slave in [1, 2, 3] {
+            let request = [
+                0x0, slave, 0x0, 0x0, 0x0, 0x6, slave, 0x3, 0x0, 0x0, 0x0, 0x1,
+            ];
+            stream.write_all(&request).await.unwrap();
+        }
+
+        // only the request answered in time
+        let mut buffer = [0u8; 256];
+        let size = stream.read(&mut buffer).await.unwrap();
+        assert_eq!(
+            &buffer[..size],
+            [0x0, 0x3, 0x0, 0x0, 0x0, 0x5, 0x3, 0x3, 0x2, 0x12, 0x34]
+        );
+        let read = tokio::time::timeout(Duration::from_millis(150), stream.read(&mut buffer));
+        assert!(read.await.is_err());
+    }
+
+    #[test]
+    fn shared_state_handler() {
+        let counter = Arc::new(Mutex::new(0u32));
+        let handler = move |request: Request| {
+            *counter.lock().unwrap() += 1;
+            let pdu = ResponsePdu::exception(0x3, ExceptionCode::IllegalFunction);
+            let _ = Response::make(request, pdu).send();
+        };
+        assert_handler(&handler);
+    }
 }
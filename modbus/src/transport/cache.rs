@@ -0,0 +1,154 @@
+use crate::frame::prelude::*;
+use crate::transport::{event::EventLog, Request, Response};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// slave, function, address, count
+type Key = (u8, u8, u16, u16);
+
+/// Last good responses to reads (fc1-fc4). If the handler is slower than the
+/// threshold, the cached response is sent instead and the live one only
+/// refreshes the cache
+#[derive(Clone)]
+pub(crate) struct StaleCache {
+    threshold: Duration,
+    entries: Arc<Mutex<HashMap<Key, ResponsePdu>>>,
+}
+
+impl StaleCache {
+    pub fn new(threshold: Duration) -> StaleCache {
+        StaleCache {
+            threshold,
+            entries: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn get(&self, key: &Key) -> Option<ResponsePdu> {
+        self.entries.lock().unwrap().get(key).cloned()
+    }
+
+    fn update(&self, key: Key, pdu: &ResponsePdu) {
+        if !matches!(pdu, ResponsePdu::Exception { .. }) {
+            self.entries.lock().unwrap().insert(key, pdu.clone());
+        }
+    }
+}
+
+fn read_key(slave: u8, pdu: &RequestPdu) -> Option<Key> {
+    match pdu {
+        RequestPdu::ReadCoils { address, nobjs } => Some((slave, 0x1, *address, *nobjs)),
+        RequestPdu::ReadDiscreteInputs { address, nobjs } => Some((slave, 0x2, *address, *nobjs)),
+        RequestPdu::ReadHoldingRegisters { address, nobjs } => Some((slave, 0x3, *address, *nobjs)),
+        RequestPdu::ReadInputRegisters { address, nobjs } => Some((slave, 0x4, *address, *nobjs)),
+        _ => None,
+    }
+}
+
+impl Request {
+    /// Answer reads with the cached response if the handler doesn't finish
+    /// processing in time. Without a cached value the live response is awaited
+    pub(crate) fn with_stale_cache(mut self, cache: &StaleCache) -> Request {
+        let Some(key) = read_key(self.slave, &self.pdu) else {
+            return self;
+        };
+        let Some((server_tx, mut rx)) = self.intercept() else {
+            return self;
+        };
+        let (uuid, slave) = (self.uuid, self.slave);
+        let cache = cache.clone();
+
+        tokio::spawn(async move {
+            let deadline = tokio::time::sleep(cache.threshold);
+            tokio::pin!(deadline);
+            let mut answered = false;
+            let mut expired = false;
+            loop {
+                tokio::select! {
+                    response = rx.recv() => {
+                        let Some(response) = response else {
+                            return;
+                        };
+                        if !response.is_dropped() {
+                            cache.update(key, &response.pdu);
+                        }
+                        let last = response.is_last();
+                        if !answered {
+                            let _ = server_tx.send(response);
+                        }
+                        if last {
+                            return;
+                        }
+                    },
+                    _ = &mut deadline, if !expired => {
+                        expired = true;
+                        let Some(pdu) = cache.get(&key) else {
+                            // nothing to answer with, keep waiting for the handler
+                            continue;
+                        };
+                        EventLog::warning(&uuid, &"answered with cached data");
+                        let stale = Response {
+                            uuid,
+                            slave,
+                            pdu,
+                            last: true,
+                            dropped: false,
+                            deadline: None,
+                            response_tx: None,
+                        };
+                        let _ = server_tx.send(stale);
+                        answered = true;
+                    }
+                }
+            }
+        });
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::sync::mpsc;
+    use uuid::Uuid;
+
+    async fn slow_read(cache: &StaleCache, value: u16, handler_time: Duration) -> ResponsePdu {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let request = Request {
+            uuid: Uuid::new_v4(),
+            slave: 0x11,
+            pdu: RequestPdu::read_holding_registers(0, 1),
+            deadline: None,
+            response_tx: Some(tx),
+        }
+        .with_stale_cache(cache);
+
+        tokio::spawn(async move {
+            tokio::time::sleep(handler_time).await;
+            let pdu = ResponsePdu::read_holding_registers([value].as_slice());
+            let _ = Response::make(request, pdu).send();
+        });
+        let response = rx.recv().await.unwrap();
+        assert!(response.is_last());
+        response.pdu
+    }
+
+    #[tokio::test]
+    async fn stale_cache() {
+        let cache = StaleCache::new(Duration::from_millis(20));
+
+        // nothing cached yet, wait for the handler
+        let pdu = slow_read(&cache, 1, Duration::from_millis(50)).await;
+        assert_eq!(pdu, ResponsePdu::read_holding_registers([1u16].as_slice()));
+
+        let start = std::time::Instant::now();
+        let pdu = slow_read(&cache, 2, Duration::from_millis(100)).await;
+        assert!(start.elapsed() < Duration::from_millis(100));
+        assert_eq!(pdu, ResponsePdu::read_holding_registers([1u16].as_slice()));
+
+        // the late response refreshed the cache
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        let pdu = slow_read(&cache, 3, Duration::from_millis(100)).await;
+        assert_eq!(pdu, ResponsePdu::read_holding_registers([2u16].as_slice()));
+    }
+}
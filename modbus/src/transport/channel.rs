@@ -0,0 +1,88 @@
+use super::{error::TransportError, Request};
+use futures::Stream;
+use tokio::sync::mpsc::{
+    self,
+    error::{TryRecvError, TrySendError},
+};
+
+/// Why a request wasn't passed to the handler
+pub(crate) enum SendError {
+    /// bounded queue is full. The request is returned to be answered
+    Full(Box<Request>),
+    Closed,
+}
+
+/// Transport side of the request channel
+#[derive(Clone)]
+pub(crate) enum RequestTx {
+    Unbounded(mpsc::UnboundedSender<Request>),
+    Bounded(mpsc::Sender<Request>),
+}
+
+impl RequestTx {
+    pub fn send(&self, request: Request) -> Result<(), SendError> {
+        match self {
+            RequestTx::Unbounded(tx) => tx.send(request).map_err(|_| SendError::Closed),
+            RequestTx::Bounded(tx) => tx.try_send(request).map_err(|err| match err {
+                TrySendError::Full(request) => SendError::Full(Box::new(request)),
+                TrySendError::Closed(_) => SendError::Closed,
+            }),
+        }
+    }
+}
+
+enum Rx {
+    Unbounded(mpsc::UnboundedReceiver<Request>),
+    Bounded(mpsc::Receiver<Request>),
+}
+
+/// Handler side of the request channel
+pub struct RequestRx(Rx);
+
+impl RequestRx {
+    pub async fn recv(&mut self) -> Option<Request> {
+        match &mut self.0 {
+            Rx::Unbounded(rx) => rx.recv().await,
+            Rx::Bounded(rx) => rx.recv().await,
+        }
+    }
+
+    pub fn try_recv(&mut self) -> Result<Request, TryRecvError> {
+        match &mut self.0 {
+            Rx::Unbounded(rx) => rx.try_recv(),
+            Rx::Bounded(rx) => rx.try_recv(),
+        }
+    }
+
+    pub(crate) fn into_stream(self) -> impl Stream<Item = Request> + Unpin {
+        Box::pin(futures::stream::unfold(self, |mut rx| async move {
+            let request = rx.recv().await?;
+            Some((request, rx))
+        }))
+    }
+}
+
+/// A bounded queue must hold at least one request
+pub(crate) fn check_queue_depth(depth: Option<usize>) -> Result<(), TransportError> {
+    match depth {
+        Some(0) => Err(TransportError::InvalidSettings(
+            "queue depth must be greater than 0".to_owned(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Unbounded channel by default. With `depth` the transport answers
+/// SlaveDeviceBusy instead of queuing more requests
+pub(crate) fn request_channel(depth: Option<usize>) -> (RequestTx, RequestRx) {
+    match depth {
+        Some(depth) => {
+            let (tx, rx) = mpsc::channel(depth);
+            (RequestTx::Bounded(tx), RequestRx(Rx::Bounded(rx)))
+        }
+        None => {
+            let (tx, rx) = mpsc::unbounded_channel();
+            (RequestTx::Unbounded(tx), RequestRx(Rx::Unbounded(rx)))
+        }
+    }
+}
@@ -1,14 +1,27 @@
 use crate::codec::error::Error as MbError;
 use crate::codec::slave::SlaveCodec;
 use crate::frame::prelude::*;
+use crate::transport::{
+    event::{EventCallback, EventLog, LastError, TransportEvent},
+    settings::BytesLog,
+    stats::Stats,
+};
 use bytes::BytesMut;
+use std::fmt::Debug;
 use std::io::{Error, ErrorKind};
-use tokio_util::codec::{Decoder, Encoder};
+use std::sync::Arc;
+use tokio_util::codec::Encoder;
 
 pub struct IoContext {
     pub codec: SlaveCodec,
     pub input: BytesMut,
     pub output: BytesMut,
+    pub bytes_log: BytesLog,
+    // head of the compact log line for the last encoded response
+    output_head: String,
+    pub(crate) last_error: LastError,
+    pub(crate) stats: Arc<Stats>,
+    pub(crate) on_event: Option<EventCallback>,
 }
 
 impl IoContext {
@@ -17,20 +30,111 @@ impl IoContext {
             codec,
             input: BytesMut::new(),
             output: BytesMut::new(),
+            bytes_log: BytesLog::Always,
+            output_head: String::new(),
+            last_error: LastError::default(),
+            stats: Arc::default(),
+            on_event: None,
         }
     }
 
+    pub(crate) fn notify(&self, event: TransportEvent) {
+        if let Some(on_event) = self.on_event.as_ref() {
+            on_event(event);
+        }
+    }
+
+    pub(crate) fn timeout(&self) {
+        Stats::inc(&self.stats.timeouts);
+        self.notify(TransportEvent::Timeout);
+    }
+
     pub fn decode(&mut self) -> Result<Option<RequestFrame>, Error> {
-        self.codec.decode(&mut self.input).map_err(|err| match err {
+        self.decode_with(|_, _| {})
+    }
+
+    // `inspect` sees the input before the codec drops it: the bytes of the
+    // frame if it's decoded, all the input otherwise
+    fn decode_with<F>(&mut self, inspect: F) -> Result<Option<RequestFrame>, Error>
+    where
+        F: FnOnce(&Result<Option<RequestFrame>, MbError>, &[u8]),
+    {
+        let (res, processed) = self.codec.read(&self.input);
+        match &res {
+            Ok(Some(_)) => inspect(&res, &self.input[..processed]),
+            _ => inspect(&res, &self.input),
+        }
+        self.codec.advance_buffer(&mut self.input, &res, processed);
+        match res {
+            Ok(Some(_)) => Stats::inc(&self.stats.frames_decoded),
+            Ok(None) => {}
+            Err(err) => {
+                if err == MbError::InvalidCrc {
+                    Stats::inc(&self.stats.crc_errors);
+                }
+                self.last_error.set(err);
+                self.notify(TransportEvent::DecodeError);
+            }
+        }
+        res.map_err(|err| match err {
             MbError::InvalidCrc => Error::new(ErrorKind::InvalidData, "bad CRC"),
             _ => Error::new(ErrorKind::InvalidData, "bad input"),
         })
     }
 
+    /// decode input and log raw bytes according to the bytes_log mode
+    pub(crate) fn decode_logged(
+        &mut self,
+        name: &dyn Debug,
+    ) -> Result<Option<RequestFrame>, Error> {
+        match self.bytes_log {
+            BytesLog::Always => {
+                EventLog::input(name, &self.input);
+                self.decode()
+            }
+            BytesLog::OnError => self.decode_with(|res, input| {
+                if let Err(err) = res {
+                    EventLog::bad_input(name, err, input);
+                }
+            }),
+            BytesLog::Compact => self.decode_with(|res, input| match res {
+                Ok(Some(frame)) => {
+                    let head = format!("id={} slave={} {}", frame.id, frame.slave, frame.pdu);
+                    EventLog::compact("IN", name, &head, input);
+                }
+                Err(err) => EventLog::bad_input(name, err, input),
+                Ok(None) => {}
+            }),
+        }
+    }
+
     pub fn encode(&mut self, response: ResponseFrame) -> Result<(), Error> {
+        if self.bytes_log == BytesLog::Compact {
+            self.output_head = format!(
+                "id={} slave={} {}",
+                response.id, response.slave, response.pdu
+            );
+        }
+        let exception = matches!(response.pdu, ResponsePdu::Exception { .. });
         self.codec
             .encode(response, &mut self.output)
-            .map_err(|_| Error::new(ErrorKind::InvalidData, "codec error"))
+            .map_err(|err| {
+                self.last_error.set(err);
+                Error::new(ErrorKind::InvalidData, "codec error")
+            })?;
+        if exception {
+            Stats::inc(&self.stats.exceptions_sent);
+        }
+        Stats::add(&self.stats.bytes_out, self.output.len());
+        Ok(())
+    }
+
+    pub(crate) fn log_output(&self, name: &dyn Debug) {
+        match self.bytes_log {
+            BytesLog::Always => EventLog::output(name, &self.output),
+            BytesLog::Compact => EventLog::compact("OUT", name, &self.output_head, &self.output),
+            BytesLog::OnError => {}
+        }
     }
 
     pub fn reset(&mut self) {
@@ -42,3 +146,126 @@ impl IoContext {
         self.input.resize(size, 0);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use log::{Level, LevelFilter, Log, Metadata, Record};
+    use std::sync::atomic::Ordering;
+    use std::sync::Mutex;
+
+    static RECORDS: Mutex<Vec<(Level, String)>> = Mutex::new(Vec::new());
+
+    struct Capture;
+
+    impl Log for Capture {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record) {
+            let msg = format!("{}", record.args());
+            RECORDS.lock().unwrap().push((record.level(), msg));
+        }
+
+        fn flush(&self) {}
+    }
+
+    static CAPTURE: Capture = Capture;
+
+    fn init_log() {
+        if log::set_logger(&CAPTURE).is_ok() {
+            log::set_max_level(LevelFilter::Trace);
+        }
+    }
+
+    fn records(level: Level, name: &str) -> Vec<String> {
+        RECORDS
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(lvl, msg)| *lvl == level && msg.contains(name))
+            .map(|(_, msg)| msg.clone())
+            .collect()
+    }
+
+    fn warnings(name: &str) -> Vec<String> {
+        records(Level::Warn, name)
+    }
+
+    #[test]
+    fn bytes_log_compact() {
+        init_log();
+
+        let mut context = IoContext::new(SlaveCodec::new_tcp());
+        context.bytes_log = BytesLog::Compact;
+        context.input.extend_from_slice(&[
+            0x0, 0x1, 0x0, 0x0, 0x0, 0x6, 0x11, 0x03, 0x00, 0x6B, 0x00, 0x03,
+        ]);
+        let frame = context.decode_logged(&"ctx-compact").unwrap().unwrap();
+        let pdu = ResponsePdu::read_holding_registers([0x1u16].as_slice());
+        context
+            .encode(ResponseFrame::from_parts(frame.id, frame.slave, pdu))
+            .unwrap();
+        context.log_output(&"ctx-compact");
+
+        let logged = records(Level::Debug, "ctx-compact");
+        assert_eq!(
+            logged,
+            vec![
+                "IN \"ctx-compact\" id=1 slave=17 FC3 read_holding_registers addr=0x006B count=3 \
+                 [00 01 00 00 00 06 11 03 00 6b 00 03]",
+                "OUT \"ctx-compact\" id=1 slave=17 FC3 read_holding_registers count=1 \
+                 [00 01 00 00 00 05 11 03 02 00 01]",
+            ]
+        );
+    }
+
+    #[test]
+    fn bytes_log_on_error() {
+        init_log();
+
+        let mut context = IoContext::new(SlaveCodec::new_rtu());
+        context.bytes_log = BytesLog::OnError;
+        context
+            .input
+            .extend_from_slice(&[0x11, 0x01, 0x00, 0x13, 0x00, 0x25, 0x0E, 0x84]);
+        assert!(context.decode_logged(&"ctx-ok").unwrap().is_some());
+        assert!(warnings("ctx-ok").is_empty());
+
+        context
+            .input
+            .extend_from_slice(&[0x11, 0x01, 0x00, 0x13, 0x00, 0x25, 0x0E, 0x85]);
+        assert!(context.decode_logged(&"ctx-bad").is_err());
+        let logged = warnings("ctx-bad");
+        assert_eq!(logged.len(), 1);
+        assert!(logged[0].contains("[17, 1, 0, 19, 0, 37, 14, 133]"));
+    }
+
+    #[test]
+    fn stats() {
+        let mut context = IoContext::new(SlaveCodec::new_rtu());
+        let stats = context.stats.clone();
+        context
+            .input
+            .extend_from_slice(&[0x11, 0x01, 0x00, 0x13, 0x00, 0x25, 0x0E, 0x84]);
+        let frame = context.decode().unwrap().unwrap();
+        assert_eq!(stats.frames_decoded.load(Ordering::Relaxed), 1);
+        assert_eq!(stats.crc_errors.load(Ordering::Relaxed), 0);
+
+        // corrupted CRC
+        context
+            .input
+            .extend_from_slice(&[0x11, 0x01, 0x00, 0x13, 0x00, 0x25, 0x0E, 0x85]);
+        assert!(context.decode().is_err());
+        assert_eq!(stats.frames_decoded.load(Ordering::Relaxed), 1);
+        assert_eq!(stats.crc_errors.load(Ordering::Relaxed), 1);
+
+        let pdu = ResponsePdu::exception(0x1, ExceptionCode::IllegalDataAddress);
+        context
+            .encode(ResponseFrame::new(frame.slave, pdu))
+            .unwrap();
+        assert_eq!(stats.exceptions_sent.load(Ordering::Relaxed), 1);
+        assert_eq!(stats.bytes_out.load(Ordering::Relaxed), 5);
+    }
+}
@@ -0,0 +1,99 @@
+use crate::codec::error::Error as CodecError;
+use crate::frame::exception::Code;
+use std::fmt;
+use std::io;
+
+/// Failure to start a transport
+#[derive(Debug)]
+pub enum TransportError {
+    /// transport address or port settings can't be parsed
+    Address(String),
+    /// serial port can't be opened or configured
+    PortOpen(tokio_serial::Error),
+    /// TCP/UDP socket can't be bound to the address
+    Bind(io::Error),
+    /// settings value is out of the allowed range
+    InvalidSettings(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransportError::Address(address) => write!(f, "invalid address {}", address),
+            TransportError::PortOpen(err) => write!(f, "can't open port: {}", err),
+            TransportError::Bind(err) => write!(f, "can't bind socket: {}", err),
+            TransportError::InvalidSettings(msg) => write!(f, "invalid settings: {}", msg),
+            TransportError::Io(err) => write!(f, "i/o error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for TransportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TransportError::Address(_) | TransportError::InvalidSettings(_) => None,
+            TransportError::PortOpen(err) => Some(err),
+            TransportError::Bind(err) | TransportError::Io(err) => Some(err),
+        }
+    }
+}
+
+impl From<tokio_serial::Error> for TransportError {
+    fn from(err: tokio_serial::Error) -> TransportError {
+        TransportError::PortOpen(err)
+    }
+}
+
+impl From<io::Error> for TransportError {
+    fn from(err: io::Error) -> TransportError {
+        TransportError::Io(err)
+    }
+}
+
+/// Failure of a client request
+#[derive(Debug)]
+pub enum ClientError {
+    Io(io::Error),
+    /// response can't be decoded
+    Codec(CodecError),
+    /// slave answered with an exception
+    Exception(Code),
+    /// no response in time
+    Timeout,
+    /// response of another function
+    UnexpectedResponse,
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientError::Io(err) => write!(f, "i/o error: {}", err),
+            ClientError::Codec(err) => write!(f, "invalid response: {:?}", err),
+            ClientError::Exception(code) => write!(f, "exception {:?}", code),
+            ClientError::Timeout => write!(f, "response timeout"),
+            ClientError::UnexpectedResponse => write!(f, "unexpected response"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ClientError {
+    fn from(err: io::Error) -> ClientError {
+        ClientError::Io(err)
+    }
+}
+
+impl From<CodecError> for ClientError {
+    fn from(err: CodecError) -> ClientError {
+        ClientError::Codec(err)
+    }
+}
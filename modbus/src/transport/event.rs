@@ -1,54 +1,207 @@
 use super::{Request, Response};
-use crate::frame::prelude::*;
-use log::{debug, error, info, trace, warn};
-use std::fmt::Debug;
+use crate::codec::error::Error as MbError;
+use log::{debug, error, info, log_enabled, trace, warn, Level};
+use std::fmt::{Debug, Display, Write};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
-#[derive(Debug)]
-enum Event<'a> {
-    Input(&'a dyn Debug, &'a [u8]),
-    Output(&'a dyn Debug, &'a [u8]),
-    Request(&'a dyn Debug, u128, &'a u8, &'a RequestPdu),
-    Response(&'a dyn Debug, u128, &'a u8, &'a ResponsePdu),
-    Error(&'a dyn Debug, &'a dyn Debug),
-    Warning(&'a dyn Debug, &'a dyn Debug),
-    Info(&'a dyn Debug, &'a dyn Debug),
+/// The most recent error of a transport and when it happened. Shared between
+/// the transport tasks and its Handler
+#[derive(Clone, Default)]
+pub(crate) struct LastError(Arc<Mutex<Option<(MbError, Instant)>>>);
+
+impl LastError {
+    pub fn set(&self, err: MbError) {
+        *self.0.lock().unwrap() = Some((err, Instant::now()));
+    }
+
+    pub fn set_io(&self, err: &io::Error) {
+        self.set(MbError::from(io::Error::from(err.kind())));
+    }
+
+    pub fn get(&self) -> Option<(MbError, Instant)> {
+        *self.0.lock().unwrap()
+    }
+}
+
+/// Connection changes and errors reported to Settings::on_event
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TransportEvent {
+    /// TCP client connected
+    Connected(SocketAddr),
+    /// TCP client disconnected
+    Disconnected(SocketAddr),
+    /// input can't be decoded
+    DecodeError,
+    /// partial frame or idle connection timed out
+    Timeout,
 }
 
+pub type EventCallback = Arc<dyn Fn(TransportEvent) + Send + Sync>;
+
 pub(crate) struct EventLog {}
 
 impl EventLog {
+    /// multiline dump like in Wireshark. Formatted only if trace is enabled
     pub fn input(name: &dyn Debug, data: &[u8]) {
-        let event = Event::Input(&name, data);
-        trace!("{:?}", event);
+        if log_enabled!(Level::Trace) {
+            trace!("{}", dump_text("IN", name, data));
+        }
     }
 
     pub fn output(name: &dyn Debug, data: &[u8]) {
-        let event = Event::Output(&name, data);
-        trace!("{:?}", event);
+        if log_enabled!(Level::Trace) {
+            trace!("{}", dump_text("OUT", name, data));
+        }
+    }
+
+    pub fn bad_input(name: &dyn Debug, err: &dyn Debug, data: &[u8]) {
+        warn!("BadInput({:?}, {:?}, {:?})", name, err, data);
     }
 
     pub fn request(name: &dyn Debug, msg: &Request) {
-        let event = Event::Request(&name, msg.uuid.as_u128(), &msg.slave, &msg.pdu);
-        debug!("{:?}", event);
+        let uuid = msg.uuid.as_u128();
+        debug!("{}", pdu_line("Request", name, uuid, msg.slave, &msg.pdu));
     }
 
     pub fn response(name: &dyn Debug, msg: &Response) {
-        let event = Event::Response(&name, msg.uuid.as_u128(), &msg.slave, &msg.pdu);
-        debug!("{:?}", event);
+        let uuid = msg.uuid.as_u128();
+        debug!("{}", pdu_line("Response", name, uuid, msg.slave, &msg.pdu));
     }
 
     pub fn warning(name: &dyn Debug, warn: &dyn Debug) {
-        let event = Event::Warning(&name, warn);
-        warn!("{:?}", event);
+        warn!("Warning({:?}, {:?})", name, warn);
     }
 
     pub fn error(name: &dyn Debug, err: &dyn Debug) {
-        let event = Event::Error(&name, err);
-        error!("{:?}", event);
+        error!("Error({:?}, {:?})", name, err);
     }
 
     pub fn info(name: &dyn Debug, err: &dyn Debug) {
-        let event = Event::Info(&name, err);
-        info!("{:?}", event);
+        info!("Info({:?}, {:?})", name, err);
+    }
+
+    /// one line frame log. `head` is the frame id, slave and PDU summary
+    pub fn compact(dir: &str, name: &dyn Debug, head: &str, data: &[u8]) {
+        debug!("{}", compact_line(dir, name, head, data));
+    }
+}
+
+// PDUs are logged with their Display summary
+fn pdu_line(kind: &str, name: &dyn Debug, uuid: u128, slave: u8, pdu: &dyn Display) -> String {
+    format!("{}({:?}, {}, {}, {})", kind, name, uuid, slave, pdu)
+}
+
+fn dump_text(dir: &str, name: &dyn Debug, data: &[u8]) -> String {
+    format!("{} {:?}\n{}", dir, name, hex_dump(data))
+}
+
+// 16 bytes per line: offset, hex split into two groups of 8 and ASCII
+fn hex_dump(data: &[u8]) -> String {
+    let mut dump = String::new();
+    for (line, chunk) in data.chunks(16).enumerate() {
+        if line > 0 {
+            dump.push('\n');
+        }
+        let _ = write!(dump, "{:04x} ", line * 16);
+        for idx in 0..16 {
+            let sep = if idx == 8 { "  " } else { " " };
+            match chunk.get(idx) {
+                Some(byte) => {
+                    let _ = write!(dump, "{}{:02x}", sep, byte);
+                }
+                None => dump.push_str(&" ".repeat(sep.len() + 2)),
+            }
+        }
+        dump.push_str("  ");
+        dump.extend(chunk.iter().map(|byte| match byte {
+            0x20..=0x7E => *byte as char,
+            _ => '.',
+        }));
+    }
+    dump
+}
+
+fn compact_line(dir: &str, name: &dyn Debug, head: &str, data: &[u8]) -> String {
+    let mut line = format!("{} {:?} {} [", dir, name, head);
+    for (idx, byte) in data.iter().enumerate() {
+        let sep = if idx == 0 { "" } else { " " };
+        let _ = write!(line, "{}{:02x}", sep, byte);
+    }
+    line.push(']');
+    line
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::frame::prelude::*;
+
+    #[test]
+    fn pdu_summary() {
+        let pdu = RequestPdu::read_holding_registers(0x6B, 3);
+        assert_eq!(
+            pdu_line("Request", &"mock", 1, 17, &pdu),
+            "Request(\"mock\", 1, 17, FC3 read_holding_registers addr=0x006B count=3)"
+        );
+    }
+
+    #[test]
+    fn compact_request() {
+        let frame = RequestFrame::from_parts(1, 17, RequestPdu::read_holding_registers(0x6B, 3));
+        let line = compact_line(
+            "IN",
+            &"127.0.0.1:5020",
+            &format!("id={} slave={} {}", frame.id, frame.slave, frame.pdu),
+            &[
+                0x0, 0x1, 0x0, 0x0, 0x0, 0x6, 0x11, 0x03, 0x00, 0x6B, 0x00, 0x03,
+            ],
+        );
+        assert_eq!(
+            line,
+            "IN \"127.0.0.1:5020\" id=1 slave=17 FC3 read_holding_registers addr=0x006B count=3 \
+             [00 01 00 00 00 06 11 03 00 6b 00 03]"
+        );
+    }
+
+    #[test]
+    fn dump() {
+        let data = [
+            0x0, 0x1, 0x0, 0x0, 0x0, 0x6, 0x11, 0x03, 0x00, 0x6B, 0x00, 0x03, b'm', b'b', b'u',
+            b's', b'!',
+        ];
+        assert_eq!(
+            hex_dump(&data),
+            "0000  00 01 00 00 00 06 11 03  00 6b 00 03 6d 62 75 73  .........k..mbus\n\
+             0010  21                                                !"
+        );
+        assert_eq!(
+            hex_dump(&data[..8]),
+            "0000  00 01 00 00 00 06 11 03                           ........"
+        );
+        assert_eq!(hex_dump(&[]), "");
+        assert_eq!(
+            dump_text("OUT", &"/dev/ttyUSB0", &[0x11, 0x83, 0x02]),
+            "OUT \"/dev/ttyUSB0\"\n\
+             0000  11 83 02                                          ..."
+        );
+    }
+
+    #[test]
+    fn compact_response() {
+        let pdu = ResponsePdu::exception(0x3, ExceptionCode::IllegalDataAddress);
+        let frame = ResponseFrame::from_parts(1, 17, pdu);
+        let line = compact_line(
+            "OUT",
+            &"/dev/ttyUSB0",
+            &format!("id={} slave={} {}", frame.id, frame.slave, frame.pdu),
+            &[0x83, 0x02],
+        );
+        assert_eq!(
+            line,
+            "OUT \"/dev/ttyUSB0\" id=1 slave=17 FC3 exception IllegalDataAddress [83 02]"
+        );
     }
 }
@@ -0,0 +1,101 @@
+use crate::frame::prelude::*;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Max rate of requests forwarded by a transport
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    /// requests per second
+    pub rate: f64,
+    /// requests that could be forwarded at once after a quiet period
+    pub burst: u32,
+}
+
+struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit) -> TokenBucket {
+        TokenBucket {
+            limit,
+            tokens: limit.burst as f64,
+            updated: Instant::now(),
+        }
+    }
+
+    fn acquire(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.updated = now;
+        self.tokens = (self.tokens + elapsed * self.limit.rate).min(self.limit.burst as f64);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Transport-wide limiter. Clones share the same bucket
+#[derive(Clone, Default)]
+pub(crate) struct Limiter {
+    bucket: Option<Arc<Mutex<TokenBucket>>>,
+}
+
+impl Limiter {
+    pub fn new(limit: Option<RateLimit>) -> Limiter {
+        Limiter {
+            bucket: limit.map(|limit| Arc::new(Mutex::new(TokenBucket::new(limit)))),
+        }
+    }
+
+    /// true if the request could be forwarded
+    pub fn allow(&self) -> bool {
+        self.bucket
+            .as_ref()
+            .is_none_or(|bucket| bucket.lock().unwrap().acquire(Instant::now()))
+    }
+
+    /// answer for requests over the limit
+    pub fn busy(pdu: &RequestPdu) -> ResponsePdu {
+        ResponsePdu::exception(pdu.func().unwrap_or(0), ExceptionCode::SlaveDeviceBusy)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn token_bucket() {
+        let mut bucket = TokenBucket::new(RateLimit {
+            rate: 10.0,
+            burst: 2,
+        });
+        let now = bucket.updated;
+        assert!(bucket.acquire(now));
+        assert!(bucket.acquire(now));
+        assert!(!bucket.acquire(now));
+
+        // 10 req/s => one token per 100ms
+        assert!(!bucket.acquire(now + Duration::from_millis(50)));
+        assert!(bucket.acquire(now + Duration::from_millis(150)));
+        assert!(!bucket.acquire(now + Duration::from_millis(150)));
+
+        // no more than burst after a long pause
+        let later = now + Duration::from_secs(10);
+        assert!(bucket.acquire(later));
+        assert!(bucket.acquire(later));
+        assert!(!bucket.acquire(later));
+    }
+
+    #[test]
+    fn unlimited() {
+        let limiter = Limiter::new(None);
+        assert!((0..1000).all(|_| limiter.allow()));
+    }
+}
@@ -1,17 +1,32 @@
 pub mod builder;
+mod cache;
+pub mod channel;
 pub mod context;
+pub mod error;
 pub mod event;
+pub mod limit;
+mod order;
+mod queue;
 pub mod rtu;
 pub mod settings;
+pub mod stats;
 pub mod tcp;
 pub mod udp;
 
+use crate::codec::error::Error as MbError;
 use crate::frame::prelude::*;
+use crate::transport::channel::RequestRx;
+use crate::transport::event::LastError;
+use crate::transport::stats::Stats;
+use crate::transport::tcp::server::ClientList;
 
 use futures::Stream;
 use std::fmt;
-use tokio::sync::mpsc;
-use tokio_stream::wrappers::UnboundedReceiverStream;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Notify};
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
 #[derive(Debug)]
@@ -19,6 +34,8 @@ pub struct Request {
     pub uuid: Uuid,
     pub slave: u8,
     pub pdu: RequestPdu,
+    /// time after which the response is not sent. Set from Settings::request_deadline
+    pub deadline: Option<Instant>,
     pub response_tx: Option<mpsc::UnboundedSender<Response>>,
 }
 
@@ -27,6 +44,11 @@ pub struct Response {
     pub uuid: Uuid,
     pub slave: u8,
     pub pdu: ResponsePdu,
+    last: bool,
+    // the request was dropped without an answer. Nothing is sent, the
+    // transport only releases what it holds for the request
+    dropped: bool,
+    deadline: Option<Instant>,
     response_tx: Option<mpsc::UnboundedSender<Response>>,
 }
 
@@ -50,17 +72,215 @@ impl fmt::Display for Request {
     }
 }
 
+/// Requests for slaves out of the set are dropped without an answer.
+/// Broadcasts (0) are always served
+pub(crate) fn is_served(slaves: &Option<Vec<u8>>, slave: u8) -> bool {
+    slave == 0 || slaves.as_ref().is_none_or(|ids| ids.contains(&slave))
+}
+
+/// Answer for fc15/fc16 with zero count and 0x2b/0xD in strict mode. The
+/// codec passes them as Raw PDUs if Settings::zero_count_exception or
+/// Settings::strict_mei is on
+pub(crate) fn rejected_request_exception(pdu: &RequestPdu) -> Option<ResponsePdu> {
+    match pdu {
+        RequestPdu::Raw {
+            function: func @ (0xF | 0x10 | 0x2b),
+            ..
+        } => Some(ResponsePdu::exception(
+            *func,
+            ExceptionCode::IllegalDataValue,
+        )),
+        _ => None,
+    }
+}
+
+type Intercepted = (
+    mpsc::UnboundedSender<Response>,
+    mpsc::UnboundedReceiver<Response>,
+);
+
+impl Request {
+    /// slave 0. Such requests are processed by all units but never answered
+    /// by serial transports
+    pub fn is_broadcast(&self) -> bool {
+        self.slave == 0
+    }
+
+    /// true if the deadline passed and computing a response makes no sense
+    pub fn is_expired(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| deadline <= Instant::now())
+    }
+
+    /// Put a channel between the handler and the transport. Returns the
+    /// transport sender and the receiver of handler responses
+    fn intercept(&mut self) -> Option<Intercepted> {
+        let server_tx = self.response_tx.take()?;
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.response_tx = Some(tx);
+        Some((server_tx, rx))
+    }
+
+    /// Answer with SlaveDeviceBusy if the handler doesn't finish processing in
+    /// time. Responses that come after that are dropped
+    pub(crate) fn with_processing_timeout(mut self, timeout: Duration) -> Request {
+        let Some(func) = self.pdu.func() else {
+            return self;
+        };
+        let Some((server_tx, mut rx)) = self.intercept() else {
+            return self;
+        };
+        let (uuid, slave) = (self.uuid, self.slave);
+
+        tokio::spawn(async move {
+            let deadline = tokio::time::sleep(timeout);
+            tokio::pin!(deadline);
+            loop {
+                tokio::select! {
+                    response = rx.recv() => {
+                        // None: handler dropped the request without an answer
+                        let Some(response) = response else {
+                            return;
+                        };
+                        let last = response.is_last();
+                        let _ = server_tx.send(response);
+                        if last {
+                            return;
+                        }
+                    },
+                    _ = &mut deadline => {
+                        let busy = Response {
+                            uuid,
+                            slave,
+                            pdu: ResponsePdu::exception(func, ExceptionCode::SlaveDeviceBusy),
+                            last: true,
+                            dropped: false,
+                            deadline: None,
+                            response_tx: None,
+                        };
+                        let _ = server_tx.send(busy);
+                        return;
+                    }
+                }
+            }
+        });
+        self
+    }
+
+    /// Drop responses that come later than `timeout` after the request was
+    /// dispatched. Nothing is sent for such requests
+    pub(crate) fn with_deadline(mut self, timeout: Duration) -> Request {
+        self.deadline = Some(Instant::now() + timeout);
+        self
+    }
+
+    /// Don't pass responses to the transport earlier than `min` after the
+    /// request was dispatched. Slower responses are not delayed
+    pub(crate) fn with_min_response_time(mut self, min: Duration) -> Request {
+        let Some((server_tx, mut rx)) = self.intercept() else {
+            return self;
+        };
+        let ready = tokio::time::Instant::now() + min;
+
+        tokio::spawn(async move {
+            while let Some(response) = rx.recv().await {
+                tokio::time::sleep_until(ready).await;
+                let last = response.is_last();
+                let _ = server_tx.send(response);
+                if last {
+                    return;
+                }
+            }
+        });
+        self
+    }
+}
+
+impl Drop for Request {
+    fn drop(&mut self) {
+        if let Some(tx) = self.response_tx.take() {
+            let _ = tx.send(Response::dropped(self));
+        }
+    }
+}
+
 impl Response {
     pub fn make(mut request: Request, response: ResponsePdu) -> Response {
         Response {
             uuid: request.uuid,
             slave: request.slave,
             pdu: response,
+            last: true,
+            dropped: false,
+            deadline: request.deadline,
             response_tx: request.response_tx.take(),
         }
     }
 
+    /// make intermediate response. The request stays open for the next responses
+    pub fn make_partial(request: &Request, response: ResponsePdu) -> Response {
+        Response {
+            uuid: request.uuid,
+            slave: request.slave,
+            pdu: response,
+            last: false,
+            dropped: false,
+            deadline: request.deadline,
+            response_tx: request.response_tx.clone(),
+        }
+    }
+
+    // the request won't be answered
+    fn dropped(request: &Request) -> Response {
+        let func = request.pdu.func().unwrap_or(0);
+        Response {
+            uuid: request.uuid,
+            slave: request.slave,
+            pdu: ResponsePdu::exception(func, ExceptionCode::SlaveDeviceFailure),
+            last: true,
+            dropped: true,
+            deadline: None,
+            response_tx: None,
+        }
+    }
+
+    /// make several responses for one request. Only the last one closes the request
+    pub fn make_many(request: Request, responses: Vec<ResponsePdu>) -> Vec<Response> {
+        assert!(!responses.is_empty());
+        let mut responses = responses.into_iter().peekable();
+        let mut result = Vec::new();
+        while let Some(pdu) = responses.next() {
+            if responses.peek().is_some() {
+                result.push(Response::make_partial(&request, pdu));
+            } else {
+                result.push(Response::make(request, pdu));
+                break;
+            }
+        }
+        result
+    }
+
+    /// true if no more responses are expected for the request
+    pub fn is_last(&self) -> bool {
+        self.last
+    }
+
+    /// true if the handler dropped the request without an answer
+    pub(crate) fn is_dropped(&self) -> bool {
+        self.dropped
+    }
+
+    /// Send the response to the transport. Responses past the request
+    /// deadline are silently dropped
     pub fn send(mut self) -> std::io::Result<()> {
+        if self
+            .deadline
+            .is_some_and(|deadline| deadline <= Instant::now())
+        {
+            // the transport still releases what it holds for the request
+            self.dropped = true;
+            self.last = true;
+        }
         self.response_tx.take().unwrap().send(self).map_err(|_| {
             std::io::Error::new(
                 std::io::ErrorKind::NotConnected,
@@ -71,18 +291,288 @@ impl Response {
 }
 
 pub struct Handler {
-    pub request_rx: mpsc::UnboundedReceiver<Request>,
+    pub request_rx: RequestRx,
+    clients: Option<ClientList>,
+    resync: Option<Arc<Notify>>,
+    last_error: LastError,
+    stats: Arc<Stats>,
+    shutdown: CancellationToken,
 }
 
 impl Handler {
+    pub(crate) fn new(request_rx: RequestRx) -> Handler {
+        Handler {
+            request_rx,
+            clients: None,
+            resync: None,
+            last_error: LastError::default(),
+            stats: Arc::default(),
+            shutdown: CancellationToken::new(),
+        }
+    }
+
+    pub(crate) fn with_last_error(mut self, last_error: LastError) -> Handler {
+        self.last_error = last_error;
+        self
+    }
+
+    pub(crate) fn with_stats(mut self, stats: Arc<Stats>) -> Handler {
+        self.stats = stats;
+        self
+    }
+
+    pub(crate) fn with_shutdown(mut self, shutdown: CancellationToken) -> Handler {
+        self.shutdown = shutdown;
+        self
+    }
+
+    pub(crate) fn with_clients(mut self, clients: ClientList) -> Handler {
+        self.clients = Some(clients);
+        self
+    }
+
+    pub(crate) fn with_resync(mut self, resync: Arc<Notify>) -> Handler {
+        self.resync = Some(resync);
+        self
+    }
+
+    /// Drop the partially received frame and the OS receive buffer. Returns
+    /// false if the transport has no such receiver (TCP/UDP)
+    pub fn resync(&self) -> bool {
+        self.resync
+            .as_ref()
+            .map(|resync| resync.notify_one())
+            .is_some()
+    }
+
+    /// addresses of connected clients. Always empty for connectionless transports
+    pub fn connected_clients(&self) -> Vec<SocketAddr> {
+        self.clients
+            .as_ref()
+            .map_or(Vec::new(), |clients| clients.lock().unwrap().clone())
+    }
+
+    /// the most recent error of the transport (bad input, i/o) and when it
+    /// happened. None if there were no errors
+    pub fn last_error(&self) -> Option<(MbError, Instant)> {
+        self.last_error.get()
+    }
+
+    /// Stop the transport. Listening sockets and ports are released, TCP
+    /// clients are closed after their in-flight responses
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+
+    /// live counters of the transport
+    pub fn stats(&self) -> Arc<Stats> {
+        self.stats.clone()
+    }
+
     pub fn to_stream(self) -> impl Stream<Item = Request> {
-        UnboundedReceiverStream::new(self.request_rx)
+        self.request_rx.into_stream()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn make_many_responses() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let request = Request {
+            uuid: Uuid::new_v4(),
+            slave: 0x11,
+            pdu: RequestPdu::read_holding_registers(0, 1),
+            deadline: None,
+            response_tx: Some(tx),
+        };
+        let uuid = request.uuid;
+
+        let responses = Response::make_many(
+            request,
+            vec![
+                ResponsePdu::read_holding_registers([1u16].as_slice()),
+                ResponsePdu::read_holding_registers([2u16].as_slice()),
+                ResponsePdu::read_holding_registers([3u16].as_slice()),
+            ],
+        );
+        for response in responses {
+            response.send().unwrap();
+        }
+
+        for (idx, value) in [1u16, 2, 3].iter().enumerate() {
+            let response = rx.try_recv().unwrap();
+            assert_eq!(response.uuid, uuid);
+            assert_eq!(response.is_last(), idx == 2);
+            assert_eq!(
+                response.pdu,
+                ResponsePdu::read_holding_registers([*value].as_slice())
+            );
+        }
+        assert!(rx.try_recv().is_err());
+    }
+
+    fn timed_request(timeout: Duration) -> (Request, mpsc::UnboundedReceiver<Response>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let request = Request {
+            uuid: Uuid::new_v4(),
+            slave: 0x11,
+            pdu: RequestPdu::read_holding_registers(0, 1),
+            deadline: None,
+            response_tx: Some(tx),
+        };
+        (request.with_processing_timeout(timeout), rx)
+    }
+
+    #[tokio::test]
+    async fn processing_timeout_busy() {
+        let (request, mut rx) = timed_request(Duration::from_millis(20));
+        let uuid = request.uuid;
+
+        // slow handler
+        let handler = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let pdu = ResponsePdu::read_holding_registers([1u16].as_slice());
+            let _ = Response::make(request, pdu).send();
+        });
+
+        let response = rx.recv().await.unwrap();
+        assert_eq!(response.uuid, uuid);
+        assert_eq!(response.slave, 0x11);
+        assert!(response.is_last());
+        assert_eq!(
+            response.pdu,
+            ResponsePdu::exception(0x3, ExceptionCode::SlaveDeviceBusy)
+        );
+
+        // late result is dropped
+        handler.await.unwrap();
+        assert!(rx.recv().await.is_none());
+    }
+
+    async fn min_response_time(handler_time: Duration) -> Duration {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let request = Request {
+            uuid: Uuid::new_v4(),
+            slave: 0x11,
+            pdu: RequestPdu::read_holding_registers(0, 1),
+            deadline: None,
+            response_tx: Some(tx),
+        }
+        .with_min_response_time(Duration::from_millis(50));
+
+        let start = std::time::Instant::now();
+        tokio::spawn(async move {
+            tokio::time::sleep(handler_time).await;
+            let pdu = ResponsePdu::read_holding_registers([1u16].as_slice());
+            Response::make(request, pdu).send().unwrap();
+        });
+        rx.recv().await.unwrap();
+        start.elapsed()
+    }
+
+    #[tokio::test]
+    async fn min_response_time_fast() {
+        let elapsed = min_response_time(Duration::ZERO).await;
+        assert!(elapsed >= Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn min_response_time_slow() {
+        // not delayed by another 50ms
+        let elapsed = min_response_time(Duration::from_millis(150)).await;
+        assert!(elapsed >= Duration::from_millis(150));
+        assert!(elapsed < Duration::from_millis(200));
+    }
+
+    #[test]
+    fn deadline() {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let request = Request {
+            uuid: Uuid::new_v4(),
+            slave: 0x11,
+            pdu: RequestPdu::read_holding_registers(0, 1),
+            deadline: None,
+            response_tx: Some(tx),
+        }
+        .with_deadline(Duration::from_millis(500));
+        let deadline = request.deadline.unwrap();
+        assert!(deadline > Instant::now());
+        assert!(deadline <= Instant::now() + Duration::from_millis(500));
+        assert!(!request.is_expired());
+    }
+
+    #[test]
+    fn expired_deadline_sends_nothing() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let request = Request {
+            uuid: Uuid::new_v4(),
+            slave: 0x11,
+            pdu: RequestPdu::read_holding_registers(0, 1),
+            deadline: Some(Instant::now()),
+            response_tx: Some(tx),
+        };
+        assert!(request.is_expired());
+
+        // handler that still answers. Nothing to send, only the transport is told
+        let pdu = ResponsePdu::read_holding_registers([1u16].as_slice());
+        Response::make(request, pdu).send().unwrap();
+        assert!(rx.try_recv().unwrap().is_dropped());
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn expired_deadline_short_circuit() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let request = Request {
+            uuid: Uuid::new_v4(),
+            slave: 0x11,
+            pdu: RequestPdu::read_holding_registers(0, 1),
+            deadline: Some(Instant::now()),
+            response_tx: Some(tx),
+        };
+
+        let handler = |request: Request| {
+            if request.is_expired() {
+                return;
+            }
+            let pdu = ResponsePdu::read_holding_registers([1u16].as_slice());
+            Response::make(request, pdu).send().unwrap();
+        };
+        handler(request);
+        // nothing to send, only the transport is told
+        assert!(rx.try_recv().unwrap().is_dropped());
+        assert!(matches!(
+            rx.try_recv(),
+            Err(mpsc::error::TryRecvError::Disconnected)
+        ));
+    }
+
+    #[tokio::test]
+    async fn processing_timeout_in_time() {
+        let (request, mut rx) = timed_request(Duration::from_millis(500));
+        let pdu = ResponsePdu::read_holding_registers([1u16].as_slice());
+        Response::make(request, pdu).send().unwrap();
+
+        let response = rx.recv().await.unwrap();
+        assert_eq!(
+            response.pdu,
+            ResponsePdu::read_holding_registers([1u16].as_slice())
+        );
+        assert!(rx.recv().await.is_none());
     }
 }
 
 pub mod prelude {
     pub use super::context::IoContext;
-    pub use super::settings::{Settings, TransportAddress};
+    pub use super::error::{ClientError, TransportError};
+    pub use super::event::{EventCallback, TransportEvent};
+    pub use super::limit::RateLimit;
+    pub use super::queue::QueuePolicy;
+    pub use super::settings::{BytesLog, Settings, TransportAddress};
+    pub use super::stats::Stats;
     pub use super::Handler;
     pub use super::Request;
     pub use super::Response;
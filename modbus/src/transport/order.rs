@@ -0,0 +1,168 @@
+use crate::frame::prelude::*;
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::net::SocketAddr;
+use uuid::Uuid;
+
+struct Pending {
+    uuid: Uuid,
+    frames: Vec<ResponseFrame>,
+    done: bool,
+}
+
+/// Holds responses back until all earlier requests of the same source are
+/// answered. Sources are UDP peers or `()` for the single RTU line
+pub(crate) struct ResponseOrder<K = SocketAddr> {
+    pending: HashMap<K, VecDeque<Pending>>,
+    limit: usize,
+}
+
+impl<K> ResponseOrder<K>
+where
+    K: Hash + Eq + Copy,
+{
+    /// `limit` - max. number of unanswered requests per source. The oldest one
+    /// is dropped on overflow
+    pub fn new(limit: usize) -> ResponseOrder<K> {
+        ResponseOrder {
+            pending: HashMap::new(),
+            limit,
+        }
+    }
+
+    /// register a request. Returns frames that became ready because of overflow
+    pub fn on_request(&mut self, address: K, uuid: Uuid) -> Vec<ResponseFrame> {
+        let queue = self.pending.entry(address).or_default();
+        queue.push_back(Pending {
+            uuid,
+            frames: Vec::new(),
+            done: false,
+        });
+
+        if queue.len() > self.limit {
+            queue.pop_front();
+            self.flush(address)
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// add a response. Returns frames that could be sent now, in order
+    pub fn on_response(
+        &mut self,
+        address: K,
+        uuid: Uuid,
+        frame: ResponseFrame,
+        last: bool,
+    ) -> Vec<ResponseFrame> {
+        let Some(pending) = self
+            .pending
+            .get_mut(&address)
+            .and_then(|queue| queue.iter_mut().find(|rec| rec.uuid == uuid))
+        else {
+            return vec![frame];
+        };
+
+        pending.frames.push(frame);
+        pending.done = last;
+        self.flush(address)
+    }
+
+    /// the request won't be answered (dropped by the handler or evicted).
+    /// Returns frames of later requests that could be sent now
+    pub fn on_drop(&mut self, address: K, uuid: Uuid) -> Vec<ResponseFrame> {
+        let Some(pending) = self
+            .pending
+            .get_mut(&address)
+            .and_then(|queue| queue.iter_mut().find(|rec| rec.uuid == uuid))
+        else {
+            return Vec::new();
+        };
+
+        pending.done = true;
+        self.flush(address)
+    }
+
+    fn flush(&mut self, address: K) -> Vec<ResponseFrame> {
+        let mut ready = Vec::new();
+        let Some(queue) = self.pending.get_mut(&address) else {
+            return ready;
+        };
+
+        while let Some(front) = queue.front_mut() {
+            ready.append(&mut front.frames);
+            if !front.done {
+                break;
+            }
+            queue.pop_front();
+        }
+
+        if queue.is_empty() {
+            self.pending.remove(&address);
+        }
+        ready
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn frame(id: u16) -> ResponseFrame {
+        ResponseFrame::from_parts(id, 1, ResponsePdu::write_single_register(0, id))
+    }
+
+    #[test]
+    fn keep_order() {
+        let address = "127.0.0.1:502".parse().unwrap();
+        let other = "127.0.0.1:503".parse().unwrap();
+        let (first, second, third) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let mut order: ResponseOrder = ResponseOrder::new(16);
+        order.on_request(address, first);
+        order.on_request(address, second);
+        order.on_request(other, third);
+
+        assert!(order
+            .on_response(address, second, frame(2), true)
+            .is_empty());
+        assert_eq!(
+            order.on_response(other, third, frame(3), true),
+            vec![frame(3)]
+        );
+        assert_eq!(
+            order.on_response(address, first, frame(1), true),
+            vec![frame(1), frame(2)]
+        );
+        assert!(order.pending.is_empty());
+    }
+
+    #[test]
+    fn dropped() {
+        let address = "127.0.0.1:502".parse().unwrap();
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut order: ResponseOrder = ResponseOrder::new(16);
+        order.on_request(address, first);
+        order.on_request(address, second);
+
+        assert!(order
+            .on_response(address, second, frame(2), true)
+            .is_empty());
+        assert_eq!(order.on_drop(address, first), vec![frame(2)]);
+        assert!(order.pending.is_empty());
+        assert!(order.on_drop(address, first).is_empty());
+    }
+
+    #[test]
+    fn overflow() {
+        let address = "127.0.0.1:502".parse().unwrap();
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut order: ResponseOrder = ResponseOrder::new(1);
+        order.on_request(address, first);
+        assert!(order.on_response(address, first, frame(1), false) == vec![frame(1)]);
+        assert!(order.on_request(address, second).is_empty());
+        assert_eq!(
+            order.on_response(address, second, frame(2), true),
+            vec![frame(2)]
+        );
+    }
+}
@@ -1,5 +1,15 @@
 use std::collections::VecDeque;
 
+/// What to do with a new entry when the queue is full
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum QueuePolicy {
+    /// evict the oldest entry. Its answer is dropped when it comes
+    #[default]
+    DropOldest,
+    /// keep the queue as is and refuse the new entry
+    Reject,
+}
+
 pub struct FixedQueue<T> {
     data: VecDeque<Option<T>>,
     limit: usize,
@@ -26,13 +36,15 @@ impl<T> FixedQueue<T> {
         self.limit - self.len()
     }
 
-    pub fn push_replace(&mut self, value: T) -> bool {
-        if self.count_free() > 0 {
-            self.push(value)
+    /// push the value. Returns the oldest entry if it was evicted for it
+    pub fn push_replace(&mut self, value: T) -> Option<T> {
+        let evicted = if self.count_free() > 0 {
+            None
         } else {
-            self.data.pop_front();
-            self.push(value)
-        }
+            self.data.pop_front().flatten()
+        };
+        self.push(value);
+        evicted
     }
 
     pub fn take_if<P>(&mut self, predicate: P) -> Option<T>
@@ -42,7 +54,7 @@ impl<T> FixedQueue<T> {
         if let Some(pos) = self
             .data
             .iter()
-            .position(|e| e.as_ref().map_or(false, &predicate))
+            .position(|e| e.as_ref().is_some_and(&predicate))
         {
             let value = self.data[pos].take();
             let len = self.data.len();
@@ -56,6 +68,15 @@ impl<T> FixedQueue<T> {
         }
     }
 
+    pub fn find_if<P>(&self, predicate: P) -> Option<&T>
+    where
+        P: Fn(&T) -> bool,
+    {
+        self.data
+            .iter()
+            .find_map(|e| e.as_ref().filter(|value| predicate(value)))
+    }
+
     pub fn len(&self) -> usize {
         self.data.len()
     }
@@ -95,12 +116,24 @@ mod test {
         assert_eq!(storage.count_free(), 2);
     }
 
+    #[test]
+    fn find() {
+        let mut storage = FixedQueue::<i32>::new(4);
+        storage.push(1);
+        storage.push(2);
+
+        assert_eq!(storage.find_if(|x| *x == 2), Some(&2));
+        assert_eq!(storage.find_if(|x| *x == 3), None);
+        assert_eq!(storage.len(), 2);
+    }
+
     #[test]
     fn push_replace() {
         let mut storage = FixedQueue::<i32>::new(4);
 
         for i in 0..10 {
-            storage.push_replace(i);
+            let evicted = storage.push_replace(i);
+            assert_eq!(evicted, (i >= 4).then_some(i - 4));
         }
 
         assert_eq!(storage.len(), 4);
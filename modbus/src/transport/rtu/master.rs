@@ -0,0 +1,183 @@
+use super::port::{self, PortSettings};
+use crate::codec::master::MasterCodec;
+use crate::frame::prelude::*;
+use crate::transport::error::{ClientError, TransportError};
+use bytes::BytesMut;
+use std::io;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::time::Instant;
+use tokio_serial::SerialStream;
+use tokio_util::codec::{Decoder, Encoder};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// Modbus RTU master. One request at a time
+pub struct RtuMasterChannel<S = SerialStream> {
+    stream: S,
+    codec: MasterCodec,
+    input: BytesMut,
+    timeout: Duration,
+    silent_interval: Duration,
+    // end of the last frame on the line
+    last_activity: Instant,
+}
+
+impl RtuMasterChannel {
+    /// open the port. Address is the same as for the RTU slave, e.g.
+    /// /dev/ttyUSB0:9600-8-N-1
+    pub fn open(address: &str) -> Result<RtuMasterChannel, TransportError> {
+        let settings = PortSettings::from_str(address)
+            .map_err(|_| TransportError::Address(address.to_owned()))?;
+        let silent_interval = settings.silent_interval();
+        let stream = port::build(settings)?;
+        Ok(RtuMasterChannel::with_stream(stream, silent_interval))
+    }
+}
+
+impl<S> RtuMasterChannel<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send,
+{
+    /// channel over the stream. Requests are sent after `silent_interval` of
+    /// silence on the line
+    pub fn with_stream(stream: S, silent_interval: Duration) -> RtuMasterChannel<S> {
+        RtuMasterChannel {
+            stream,
+            codec: MasterCodec::new_rtu(),
+            input: BytesMut::with_capacity(512),
+            timeout: DEFAULT_TIMEOUT,
+            silent_interval,
+            last_activity: Instant::now(),
+        }
+    }
+
+    /// turnaround time to wait for the response. 1s by default
+    pub fn with_timeout(mut self, timeout: Duration) -> RtuMasterChannel<S> {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Send the request and wait for the response of the same slave.
+    /// Exception responses are returned as ClientError::Exception
+    pub async fn request(
+        &mut self,
+        slave: u8,
+        pdu: RequestPdu,
+    ) -> Result<ResponsePdu, ClientError> {
+        let mut output = BytesMut::new();
+        self.codec
+            .encode(RequestFrame::new(slave, pdu), &mut output)?;
+
+        tokio::time::sleep_until(self.last_activity + self.silent_interval).await;
+        // leftovers of late responses don't belong to this request
+        self.input.clear();
+        self.stream.write_all(&output).await?;
+        self.last_activity = Instant::now();
+
+        let res = tokio::time::timeout(self.timeout, self.wait_for(slave))
+            .await
+            .map_err(|_| ClientError::Timeout);
+        self.last_activity = Instant::now();
+        match res?? {
+            ResponsePdu::Exception { code, .. } => Err(ClientError::Exception(code)),
+            pdu => Ok(pdu),
+        }
+    }
+
+    async fn wait_for(&mut self, slave: u8) -> Result<ResponsePdu, ClientError> {
+        loop {
+            while let Some(frame) = self.codec.decode(&mut self.input)? {
+                // answers of other devices on the bus are skipped
+                if frame.slave == slave {
+                    return Ok(frame.pdu);
+                }
+            }
+            if self.stream.read_buf(&mut self.input).await? == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::codec::rtuext::calc_crc_be;
+    use tokio::io::DuplexStream;
+
+    const SILENT_INTERVAL: Duration = Duration::from_millis(4);
+
+    fn rtu(frame: &[u8]) -> Vec<u8> {
+        let mut frame = frame.to_vec();
+        let crc = calc_crc_be(&frame);
+        frame.extend_from_slice(&crc.to_be_bytes());
+        frame
+    }
+
+    // reads one request and answers with `responses`. Returns the request
+    // and the time it came
+    async fn answer(line: &mut DuplexStream, responses: &[&[u8]]) -> (Vec<u8>, Instant) {
+        let mut buffer = [0u8; 256];
+        let size = line.read(&mut buffer).await.unwrap();
+        let time = Instant::now();
+        for response in responses {
+            line.write_all(&rtu(response)).await.unwrap();
+        }
+        (buffer[..size].to_vec(), time)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn request_response() {
+        let (stream, mut line) = tokio::io::duplex(512);
+        let mut channel = RtuMasterChannel::with_stream(stream, SILENT_INTERVAL)
+            .with_timeout(Duration::from_millis(100));
+
+        let slave = tokio::spawn(async move {
+            // another device answers first
+            let (request, first) = answer(
+                &mut line,
+                &[
+                    &[0x12, 0x03, 0x02, 0x0, 0x1],
+                    &[0x11, 0x03, 0x02, 0x12, 0x34],
+                ],
+            )
+            .await;
+            assert_eq!(request, rtu(&[0x11, 0x03, 0x0, 0x6B, 0x0, 0x1]));
+            let (_, second) = answer(&mut line, &[&[0x11, 0x84, 0x02]]).await;
+            // silent, but keeps the line open
+            let (_, third) = answer(&mut line, &[]).await;
+            (second - first, third - second, line)
+        });
+
+        let pdu = channel
+            .request(0x11, RequestPdu::read_holding_registers(0x6B, 1))
+            .await
+            .unwrap();
+        assert_eq!(
+            pdu,
+            ResponsePdu::read_holding_registers([0x1234u16].as_slice())
+        );
+
+        let err = channel
+            .request(0x11, RequestPdu::read_input_registers(0x6B, 1))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ClientError::Exception(ExceptionCode::IllegalDataAddress)
+        ));
+
+        let err = channel
+            .request(0x11, RequestPdu::read_input_registers(0x6B, 1))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ClientError::Timeout));
+
+        // every request waits for the silence after the previous response
+        let (second, third, _) = slave.await.unwrap();
+        assert!(second >= SILENT_INTERVAL);
+        assert!(third >= SILENT_INTERVAL);
+    }
+}
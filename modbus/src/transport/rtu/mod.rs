@@ -1,2 +1,3 @@
+pub mod master;
 pub mod port;
 pub mod slave;
@@ -1,10 +1,15 @@
 use std::io::Error;
 use std::str::FromStr;
-use tokio_serial::{Parity, SerialPort, SerialPortBuilderExt, SerialStream, StopBits};
+use std::time::Duration;
+use tokio_serial::{DataBits, Parity, SerialPort, SerialPortBuilderExt, SerialStream, StopBits};
+
+// Min. silent interval. The spec fixes it for baud rates above 19200
+const MIN_SILENT_INTERVAL: Duration = Duration::from_micros(1750);
 
 pub struct PortSettings {
     name: String,
     speed: u32,
+    data_bits: DataBits,
     parity: Parity,
     stop_bits: StopBits,
 }
@@ -25,6 +30,12 @@ impl FromStr for PortSettings {
         }
 
         let speed = u32::from_str(info[0]).map_err(|_| "invalid speed")?;
+        let data_bits = match info[1] {
+            "7" => Ok(DataBits::Seven),
+            "8" => Ok(DataBits::Eight),
+            _ => Err("invalid data bits"),
+        }?;
+
         let parity = match info[2] {
             "N" => Ok(Parity::None),
             "E" => Ok(Parity::Even),
@@ -41,14 +52,37 @@ impl FromStr for PortSettings {
         Ok(PortSettings {
             name,
             speed,
+            data_bits,
             parity,
             stop_bits,
         })
     }
 }
 
-pub fn build(parameters: PortSettings) -> Result<SerialStream, Error> {
+impl PortSettings {
+    /// 3.5 character times between RTU frames
+    pub fn silent_interval(&self) -> Duration {
+        let data = match self.data_bits {
+            DataBits::Five => 5,
+            DataBits::Six => 6,
+            DataBits::Seven => 7,
+            DataBits::Eight => 8,
+        };
+        let parity = if self.parity == Parity::None { 0 } else { 1 };
+        let stop = if self.stop_bits == StopBits::One {
+            1
+        } else {
+            2
+        };
+        let char_bits = 1 + data + parity + stop;
+        let interval = Duration::from_secs_f64(3.5 * char_bits as f64 / self.speed as f64);
+        interval.max(MIN_SILENT_INTERVAL)
+    }
+}
+
+pub fn build(parameters: PortSettings) -> tokio_serial::Result<SerialStream> {
     let port = tokio_serial::new(parameters.name, parameters.speed)
+        .data_bits(parameters.data_bits)
         .parity(parameters.parity)
         .stop_bits(parameters.stop_bits)
         .open_native_async()?;
@@ -57,26 +91,51 @@ pub fn build(parameters: PortSettings) -> Result<SerialStream, Error> {
     Ok(port)
 }
 
+/// Drop bytes the OS received but the application didn't read yet
+pub trait ClearInput {
+    fn clear_input(&self) -> Result<(), Error>;
+}
+
+impl ClearInput for SerialStream {
+    fn clear_input(&self) -> Result<(), Error> {
+        self.clear(tokio_serial::ClearBuffer::All)?;
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
     #[test]
     fn read_settings() {
-        assert_eq!(PortSettings::from_str(":").is_err(), true);
-        assert_eq!(PortSettings::from_str("").is_err(), true);
-        assert_eq!(PortSettings::from_str("/dev/ttyUSB0").is_err(), true);
-        assert_eq!(PortSettings::from_str("/dev/ttyUSB0:").is_err(), true);
-        assert_eq!(PortSettings::from_str("/dev/ttyUSB0:9600").is_err(), true);
-        assert_eq!(PortSettings::from_str("/dev/ttyUSB0:9600-8").is_err(), true);
-        assert_eq!(
-            PortSettings::from_str("/dev/ttyUSB0:9600-8-N").is_err(),
-            true
-        );
+        assert!(PortSettings::from_str(":").is_err());
+        assert!(PortSettings::from_str("").is_err());
+        assert!(PortSettings::from_str("/dev/ttyUSB0").is_err());
+        assert!(PortSettings::from_str("/dev/ttyUSB0:").is_err());
+        assert!(PortSettings::from_str("/dev/ttyUSB0:9600").is_err());
+        assert!(PortSettings::from_str("/dev/ttyUSB0:9600-8").is_err());
+        assert!(PortSettings::from_str("/dev/ttyUSB0:9600-8-N").is_err());
         let correct = PortSettings::from_str("/dev/ttyUSB0:9600-8-N-1").unwrap();
         assert_eq!(correct.name, "/dev/ttyUSB0");
         assert_eq!(correct.speed, 9600);
         assert_eq!(correct.parity, Parity::None);
         assert_eq!(correct.stop_bits, StopBits::One);
+
+        let ascii = PortSettings::from_str("/dev/ttyUSB0:9600-7-E-1").unwrap();
+        assert_eq!(ascii.data_bits, DataBits::Seven);
+        assert!(PortSettings::from_str("/dev/ttyUSB0:9600-9-E-1").is_err());
+    }
+
+    #[test]
+    fn silent_interval() {
+        // 11 bits per character
+        let port = PortSettings::from_str("/dev/ttyUSB0:9600-8-E-1").unwrap();
+        assert_eq!(port.silent_interval().as_micros(), 4010);
+        let port = PortSettings::from_str("/dev/ttyUSB0:9600-8-N-1").unwrap();
+        assert_eq!(port.silent_interval().as_micros(), 3645);
+        // floor for high baud rates
+        let port = PortSettings::from_str("/dev/ttyUSB0:115200-8-E-1").unwrap();
+        assert_eq!(port.silent_interval(), Duration::from_micros(1750));
     }
 }
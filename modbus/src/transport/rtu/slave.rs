@@ -1,74 +1,246 @@
-use super::port::{self, PortSettings};
-use crate::codec::slave::SlaveCodec;
+use super::port::{self, ClearInput, PortSettings};
+use crate::codec::pduext::is_known_function;
+use crate::codec::rtuext::FaultInjection;
+use crate::codec::slave::{CodecMode, SlaveCodec};
 use crate::frame::prelude::*;
-use crate::transport::{event::EventLog, prelude::*};
+use crate::transport::{
+    channel::{check_queue_depth, request_channel, RequestTx, SendError},
+    error::TransportError,
+    event::EventLog,
+    is_served,
+    limit::Limiter,
+    order::ResponseOrder,
+    prelude::*,
+    rejected_request_exception,
+};
+use bytes::Buf;
 use std::io::{Error, ErrorKind};
 use std::str::FromStr;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::sync::mpsc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{mpsc, Notify};
 use tokio_serial::SerialStream;
+use tokio_util::sync::CancellationToken;
 use uuid::{self, Uuid};
 
-// TODO: Reset buffer if no reading for N ms. It better to make configurable
-const INACTIVE_TIMEOUT: u64 = 250;
+// Reset buffer if no reading for N ms
+const INACTIVE_TIMEOUT: Duration = Duration::from_millis(250);
 
-pub struct RtuSlaveChannel {
-    stream: SerialStream,
+// Delays between attempts to reopen a lost port
+const REOPEN_DELAY_MIN: Duration = Duration::from_millis(100);
+const REOPEN_DELAY_MAX: Duration = Duration::from_secs(5);
+
+type Open<S> = Box<dyn FnMut() -> Result<S, Error> + Send>;
+
+pub struct RtuSlaveChannel<S = SerialStream> {
+    stream: S,
+    open: Open<S>,
+    reopen_delay: (Duration, Duration),
     context: IoContext,
-    request_tx: mpsc::UnboundedSender<Request>,
+    inactive_timeout: Duration,
+    fault: Option<FaultInjection>,
+    limiter: Limiter,
+    assembly_timeout: Option<Duration>,
+    assembly_start: Option<Instant>,
+    resync: Arc<Notify>,
+    shutdown: CancellationToken,
+    echo_suppression: bool,
+    // not yet received part of the local echo of the last response
+    echo: Vec<u8>,
+    silent_unknown_function: bool,
+    slaves: Option<Vec<u8>>,
+    order: Option<ResponseOrder<()>>,
+    request_tx: RequestTx,
     response_tx: mpsc::UnboundedSender<Response>,
     response_rx: mpsc::UnboundedReceiver<Response>,
 
     name: String,
 }
 
+fn parse_port(address: &str) -> Result<PortSettings, TransportError> {
+    PortSettings::from_str(address).map_err(|_| TransportError::Address(address.to_owned()))
+}
+
+fn open_port(address: &str) -> Result<SerialStream, TransportError> {
+    Ok(port::build(parse_port(address)?)?)
+}
+
 impl RtuSlaveChannel {
-    pub async fn build(settings: Settings) -> Result<Handler, Error> {
-        let address = settings.address.get();
-        let parameters = PortSettings::from_str(address)
-            .map_err(|_| Error::new(ErrorKind::Other, "invalid port settings"))?;
+    pub async fn build(settings: Settings) -> Result<Handler, TransportError> {
+        RtuSlaveChannel::build_with_codec(settings, SlaveCodec::new_rtu()).await
+    }
 
-        let port = port::build(parameters)?;
+    /// build with a pre-configured RTU codec
+    pub async fn build_with_codec(
+        settings: Settings,
+        mut codec: SlaveCodec,
+    ) -> Result<Handler, TransportError> {
+        check_queue_depth(settings.queue_depth)?;
+        let address = settings.address.get().to_owned();
+        let silent_interval = parse_port(&address)?.silent_interval();
+        let port = open_port(&address)?;
+        let name = address.clone();
+        let open = Box::new(move || open_port(&address).map_err(Error::other));
+        let (mut server, mut handler) = RtuSlaveChannel::with_stream(name, port, open);
+        if settings.queue_depth.is_some() {
+            (server.request_tx, handler.request_rx) = request_channel(settings.queue_depth);
+        }
+        if settings.zero_count_exception {
+            codec = codec.with_zero_count_writes(true);
+        }
+        if settings.strict_mei {
+            codec = codec.with_strict_mei(true);
+        }
+        server.inactive_timeout = match (settings.rtu_timeout, codec.mode()) {
+            (Some(timeout), _) => timeout,
+            (None, CodecMode::Rtu) if settings.rtu_silent_interval => silent_interval,
+            (None, _) => INACTIVE_TIMEOUT,
+        };
+        server.context.codec = codec;
+        server.context.bytes_log = settings.bytes_log;
+        server.context.on_event = settings.on_event;
+        server.fault = settings.rtu_fault;
+        server.limiter = Limiter::new(settings.rate_limit);
+        server.assembly_timeout = settings.rtu_assembly_timeout;
+        server.echo_suppression = settings.rtu_echo_suppression;
+        server.silent_unknown_function = settings.rtu_silent_unknown_function;
+        server.slaves = settings.slaves;
+        server.order = settings.rtu_response_depth.map(ResponseOrder::new);
+        server.spawn();
+        Ok(handler)
+    }
+}
+
+impl<S> RtuSlaveChannel<S>
+where
+    S: AsyncRead + AsyncWrite + ClearInput + Unpin + Send + 'static,
+{
+    /// Create channel over the stream. `open` is used to get a new stream
+    /// when the current one fails (e.g. USB adapter was unplugged)
+    fn with_stream(name: String, stream: S, open: Open<S>) -> (RtuSlaveChannel<S>, Handler) {
         let codec = SlaveCodec::new_rtu();
         let context = IoContext::new(codec);
-        let (tx, rx) = mpsc::unbounded_channel();
+        let (tx, rx) = request_channel(None);
         let (response_tx, response_rx) = mpsc::unbounded_channel();
+        let resync = Arc::new(Notify::new());
+        let shutdown = CancellationToken::new();
+        let handler = Handler::new(rx)
+            .with_resync(resync.clone())
+            .with_shutdown(shutdown.clone())
+            .with_last_error(context.last_error.clone())
+            .with_stats(context.stats.clone());
         let server = RtuSlaveChannel {
-            stream: port,
+            stream,
+            open,
+            reopen_delay: (REOPEN_DELAY_MIN, REOPEN_DELAY_MAX),
             context,
+            inactive_timeout: INACTIVE_TIMEOUT,
+            fault: None,
+            limiter: Limiter::default(),
+            assembly_timeout: None,
+            assembly_start: None,
+            resync,
+            shutdown,
+            echo_suppression: false,
+            echo: Vec::new(),
+            silent_unknown_function: false,
+            slaves: None,
+            order: None,
             request_tx: tx,
             response_tx,
             response_rx,
-            name: address.to_owned(),
+            name,
         };
 
-        let handler = Handler { request_rx: rx };
-        server.spawn();
-        Ok(handler)
+        (server, handler)
     }
 
     pub fn spawn(mut self) {
         tokio::spawn(async move {
-            loop {
+            while !self.shutdown.is_cancelled() {
                 let _ = self.run().await.map_err(|err| {
                     self.context.reset();
+                    self.assembly_start = None;
                     EventLog::error(&self.name, &err);
                 });
             }
+            EventLog::info(&self.name, &"shutdown");
         });
     }
 
+    /// Reopen the stream, increasing the delay between failed attempts
+    async fn reopen(&mut self) {
+        let (mut delay, max_delay) = self.reopen_delay;
+        while !self.shutdown.is_cancelled() {
+            tokio::time::sleep(delay).await;
+            EventLog::info(&self.name, &"reopening port");
+            match (self.open)() {
+                Ok(stream) => {
+                    self.stream = stream;
+                    self.context.reset();
+                    self.assembly_start = None;
+                    EventLog::info(&self.name, &"port reopened");
+                    return;
+                }
+                Err(err) => {
+                    EventLog::warning(&self.name, &err);
+                    delay = (delay * 2).min(max_delay);
+                }
+            }
+        }
+    }
+
     fn reset(&mut self, reason: &str) {
         if !self.context.input.is_empty() {
             EventLog::warning(&self.name, &reason);
         }
         self.context.reset();
+        self.assembly_start = None;
+        self.echo.clear();
+    }
+
+    /// Drop the received bytes that repeat the last response. Any mismatch
+    /// means the echo is not coming and the input is parsed as is
+    fn strip_echo(&mut self) {
+        let input = &self.context.input;
+        let len = input.len().min(self.echo.len());
+        if input[..len] == self.echo[..len] {
+            self.context.input.advance(len);
+            self.echo.drain(..len);
+        } else {
+            self.echo.clear();
+        }
+    }
+
+    /// Drop the partial frame and everything the OS has buffered, so the
+    /// next received byte is taken as the start of a frame
+    fn resync(&mut self) -> Result<(), Error> {
+        self.reset("reset by resync request");
+        self.stream.clear_input()?;
+        EventLog::info(&self.name, &"receiver resynced");
+        Ok(())
+    }
+
+    fn check_assembly_time(&mut self) -> Result<(), Error> {
+        let Some(timeout) = self.assembly_timeout else {
+            return Ok(());
+        };
+
+        let start = *self.assembly_start.get_or_insert_with(Instant::now);
+        if start.elapsed() > timeout {
+            self.context.timeout();
+            self.reset("reset by assembly timeout");
+            Err(Error::new(ErrorKind::InvalidData, "frame assembly timeout"))
+        } else {
+            Ok(())
+        }
     }
 
     async fn run(&mut self) -> Result<(), Error> {
         // read request with timeout
         let read = tokio::time::timeout(
-            std::time::Duration::from_millis(INACTIVE_TIMEOUT),
+            self.inactive_timeout,
             self.stream.read_buf(&mut self.context.input),
         );
 
@@ -77,17 +249,35 @@ impl RtuSlaveChannel {
                 match input {
                     //read:timeout
                     Err(_) => {
+                        // idle line. Nothing to drop, and the echo of the
+                        // last response may still be on its way
+                        if self.context.input.is_empty() {
+                            self.assembly_start = None;
+                            return Ok(());
+                        }
+                        self.context.timeout();
                         self.reset("reset by timeout");
                         Ok(())
                     },
 
+                    //port is closed
+                    Ok(Ok(0)) => {
+                        EventLog::error(&self.name, &"port closed");
+                        self.reopen().await;
+                        Ok(())
+                    },
                     //read next chunk
-                    Ok(Ok(_nbytes)) => {
+                    Ok(Ok(nbytes)) => {
+                        Stats::add(&self.context.stats.bytes_in, nbytes);
                         self.on_input().await
                     },
-                    //read failed
+                    //read failed. The port handle is dead (e.g. device was
+                    //unplugged), so get a new one instead of spinning on it
                     Ok(Err(e)) => {
-                        Err(e)
+                        EventLog::error(&self.name, &e);
+                        self.context.last_error.set_io(&e);
+                        self.reopen().await;
+                        Ok(())
                     },
                 }
             },
@@ -95,41 +285,522 @@ impl RtuSlaveChannel {
             response = self.response_rx.recv() => {
                 self.on_response(response).await
             }
+            _ = self.resync.notified() => {
+                self.resync()
+            }
+            _ = self.shutdown.cancelled() => Ok(())
         }
     }
 
     async fn on_input(&mut self) -> Result<(), Error> {
-        EventLog::input(&self.name, &self.context.input);
-        let Some(request) = self.context.decode()? else { return Ok(()) };
-        self.on_request(request).await;
-        Ok(())
+        if !self.echo.is_empty() {
+            self.strip_echo();
+            if self.context.input.is_empty() {
+                return Ok(());
+            }
+        }
+        self.check_assembly_time()?;
+        let res = self.context.decode_logged(&self.name);
+        if !matches!(res, Ok(None)) {
+            // frame is done (or dropped), the next one has its own time
+            self.assembly_start = None;
+        }
+
+        let Some(request) = res? else {
+            return Ok(());
+        };
+        self.on_request(request).await
     }
 
-    async fn on_request(&mut self, frame: RequestFrame) {
+    async fn on_request(&mut self, frame: RequestFrame) -> Result<(), Error> {
+        if !is_served(&self.slaves, frame.slave) {
+            return Ok(());
+        }
+
+        if let Some(pdu) = rejected_request_exception(&frame.pdu) {
+            return self
+                .send_reply(ResponseFrame::from_parts(0, frame.slave, pdu))
+                .await;
+        }
+
+        if self.silent_unknown_function {
+            if let RequestPdu::Raw { function, .. } = frame.pdu {
+                if !is_known_function(function) {
+                    EventLog::warning(
+                        &self.name,
+                        &format!("unknown function 0x{:X}, no reply", function),
+                    );
+                    return Ok(());
+                }
+            }
+        }
+
+        if !self.limiter.allow() {
+            EventLog::warning(&self.name, &"rate limit exceeded");
+            let pdu = Limiter::busy(&frame.pdu);
+            return self
+                .send_reply(ResponseFrame::from_parts(0, frame.slave, pdu))
+                .await;
+        }
+
         let uuid = Uuid::new_v4();
         let request = Request {
             uuid,
             slave: frame.slave,
             pdu: frame.pdu,
+            deadline: None,
             response_tx: Some(self.response_tx.clone()),
         };
 
         EventLog::request(&self.name, &request);
-        let _ = self.request_tx.send(request);
+        match self.request_tx.send(request) {
+            Ok(()) => {
+                if let Some(order) = self.order.as_mut() {
+                    for frame in order.on_request((), uuid) {
+                        self.send_frame(frame).await?;
+                    }
+                }
+            }
+            Err(SendError::Full(request)) => {
+                EventLog::warning(&self.name, &"request queue is full");
+                let pdu = Limiter::busy(&request.pdu);
+                return self
+                    .send_reply(ResponseFrame::from_parts(0, request.slave, pdu))
+                    .await;
+            }
+            Err(SendError::Closed) => {}
+        }
+        Ok(())
     }
 
     async fn on_response(&mut self, response: Option<Response>) -> Result<(), Error> {
-        if let Some(response) = response {
+        let Some(response) = response else {
+            return Ok(());
+        };
+        let (uuid, last) = (response.uuid, response.is_last());
+        let frames = if response.is_dropped() {
+            // later answers don't wait for it anymore
+            self.order
+                .as_mut()
+                .map_or(Vec::new(), |order| order.on_drop((), uuid))
+        } else {
             EventLog::response(&self.name, &response);
-            self.context
-                .encode(ResponseFrame::from_parts(0, response.slave, response.pdu))?;
-            self.on_output().await?;
+            let frame = ResponseFrame::from_parts(0, response.slave, response.pdu);
+            match self.order.as_mut() {
+                Some(order) => order.on_response((), uuid, frame, last),
+                None => vec![frame],
+            }
+        };
+        for frame in frames {
+            self.send_frame(frame).await?;
         }
         Ok(())
     }
 
+    // broadcasts are processed but never answered
+    async fn send_reply(&mut self, frame: ResponseFrame) -> Result<(), Error> {
+        if frame.slave == 0 {
+            return Ok(());
+        }
+        self.context.encode(frame)?;
+        self.on_output().await
+    }
+
+    async fn send_frame(&mut self, frame: ResponseFrame) -> Result<(), Error> {
+        if frame.slave == 0 {
+            return Ok(());
+        }
+        let slave = frame.slave;
+        self.context.encode(frame)?;
+        self.inject_fault(slave);
+        self.on_output().await
+    }
+
+    fn inject_fault(&mut self, slave: u8) {
+        let Some(fault) = &self.fault else {
+            return;
+        };
+        if fault.apply(slave, &mut self.context.output) {
+            EventLog::warning(&self.name, &fault.fault);
+        }
+    }
+
     async fn on_output(&mut self) -> Result<(), Error> {
-        EventLog::output(&self.name, &self.context.output);
+        self.context.log_output(&self.name);
+        if self.echo_suppression {
+            self.echo = self.context.output.to_vec();
+        }
         self.stream.write_all(&self.context.output).await
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::codec::error::Error as MbError;
+    use crate::codec::rtuext::calc_crc_be;
+    use bytes::BytesMut;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll};
+    use tokio::io::ReadBuf;
+
+    struct MockStream {
+        broken: bool,
+    }
+
+    impl ClearInput for MockStream {
+        fn clear_input(&self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    impl ClearInput for tokio::io::DuplexStream {
+        fn clear_input(&self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    impl AsyncRead for MockStream {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            _buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            if self.broken {
+                Poll::Ready(Err(Error::other("device disconnected")))
+            } else {
+                Poll::Pending
+            }
+        }
+    }
+
+    impl AsyncWrite for MockStream {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<Result<usize, Error>> {
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn assembly_timeout() {
+        let (stream, mut line) = tokio::io::duplex(512);
+        let open = Box::new(|| Err(Error::other("not used")));
+        let (mut channel, _handler) = RtuSlaveChannel::with_stream("mock".to_owned(), stream, open);
+        channel.assembly_timeout = Some(Duration::from_millis(50));
+
+        // fc16 header announces 246 bytes of data, then the line trickles bytes
+        // faster than the silence timeout
+        tokio::spawn(async move {
+            let _ = line
+                .write_all(&[0x11, 0x10, 0x00, 0x01, 0x00, 0x7B, 0xF6])
+                .await;
+            loop {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                if line.write_all(&[0x00]).await.is_err() {
+                    return;
+                }
+            }
+        });
+
+        let start = Instant::now();
+        let err = loop {
+            let res = tokio::time::timeout(Duration::from_secs(5), channel.run()).await;
+            if let Err(err) = res.unwrap() {
+                break err;
+            }
+        };
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert!(channel.context.input.is_empty());
+        assert!(channel.assembly_start.is_none());
+    }
+
+    #[tokio::test]
+    async fn resync_drops_partial_frame() {
+        let (stream, mut line) = tokio::io::duplex(512);
+        let open = Box::new(|| Err(Error::other("not used")));
+        let (mut channel, mut handler) =
+            RtuSlaveChannel::with_stream("mock".to_owned(), stream, open);
+
+        // beginning of a fc1 request
+        line.write_all(&[0x11, 0x01, 0x00]).await.unwrap();
+        channel.run().await.unwrap();
+        assert_eq!(channel.context.input.len(), 3);
+
+        assert!(handler.resync());
+        channel.run().await.unwrap();
+        assert!(channel.context.input.is_empty());
+
+        line.write_all(&[0x11, 0x01, 0x00, 0x13, 0x00, 0x25, 0x0E, 0x84])
+            .await
+            .unwrap();
+        channel.run().await.unwrap();
+        let request = handler.request_rx.try_recv().unwrap();
+        assert_eq!(request.slave, 0x11);
+        assert_eq!(request.pdu, RequestPdu::read_coils(0x13, 0x25));
+    }
+
+    #[tokio::test]
+    async fn echo_suppression() {
+        let (stream, mut line) = tokio::io::duplex(512);
+        let open = Box::new(|| Err(Error::other("not used")));
+        let (mut channel, mut handler) =
+            RtuSlaveChannel::with_stream("mock".to_owned(), stream, open);
+        channel.echo_suppression = true;
+
+        let request = [0x11, 0x01, 0x00, 0x13, 0x00, 0x25, 0x0E, 0x84];
+        line.write_all(&request).await.unwrap();
+        channel.run().await.unwrap();
+        let request = handler.request_rx.try_recv().unwrap();
+        let pdu = ResponsePdu::write_single_coil(0x13, true);
+        Response::make(request, pdu).send().unwrap();
+        channel.run().await.unwrap();
+
+        let mut response = [0u8; 8];
+        line.read_exact(&mut response).await.unwrap();
+
+        // the line stays idle before the echo comes
+        channel.inactive_timeout = Duration::from_millis(10);
+        channel.run().await.unwrap();
+
+        // the line repeats the response in two chunks. It is dropped as echo,
+        // otherwise it would be a valid fc5 request
+        line.write_all(&response[..3]).await.unwrap();
+        channel.run().await.unwrap();
+        line.write_all(&response[3..]).await.unwrap();
+        channel.run().await.unwrap();
+        assert!(channel.context.input.is_empty());
+        assert!(handler.request_rx.try_recv().is_err());
+
+        // the next request is parsed as usual
+        line.write_all(&[0x11, 0x01, 0x00, 0x13, 0x00, 0x25, 0x0E, 0x84])
+            .await
+            .unwrap();
+        channel.run().await.unwrap();
+        let request = handler.request_rx.try_recv().unwrap();
+        assert_eq!(request.pdu, RequestPdu::read_coils(0x13, 0x25));
+    }
+
+    #[tokio::test]
+    async fn zero_count_writes() {
+        let (stream, mut line) = tokio::io::duplex(512);
+        let open = Box::new(|| Err(Error::other("not used")));
+        let (mut channel, mut handler) =
+            RtuSlaveChannel::with_stream("mock".to_owned(), stream, open);
+        channel.context.codec = SlaveCodec::new_rtu().with_zero_count_writes(true);
+
+        for func in [0xF, 0x10] {
+            let mut request = BytesMut::from(&[0x11, func, 0x00, 0x13, 0x00, 0x00, 0x00][..]);
+            request.extend_from_slice(&calc_crc_be(&request).to_be_bytes());
+            line.write_all(&request).await.unwrap();
+            channel.run().await.unwrap();
+
+            let mut response = [0u8; 5];
+            line.read_exact(&mut response).await.unwrap();
+            assert_eq!(response[..3], [0x11, func | 0x80, 0x03]);
+            assert!(handler.request_rx.try_recv().is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn strict_mei() {
+        let (stream, mut line) = tokio::io::duplex(512);
+        let open = Box::new(|| Err(Error::other("not used")));
+        let (mut channel, mut handler) =
+            RtuSlaveChannel::with_stream("mock".to_owned(), stream, open);
+        channel.context.codec = SlaveCodec::new_rtu().with_strict_mei(true);
+
+        let mut request = BytesMut::from(&[0x11, 0x2B, 0x0D, 0x01, 0x20][..]);
+        request.extend_from_slice(&calc_crc_be(&request).to_be_bytes());
+        line.write_all(&request).await.unwrap();
+        channel.run().await.unwrap();
+
+        let mut response = [0u8; 5];
+        line.read_exact(&mut response).await.unwrap();
+        assert_eq!(response[..3], [0x11, 0xAB, 0x03]);
+        assert!(handler.request_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn last_error() {
+        let (stream, mut line) = tokio::io::duplex(512);
+        let open = Box::new(|| Err(Error::other("not used")));
+        let (mut channel, handler) = RtuSlaveChannel::with_stream("mock".to_owned(), stream, open);
+        assert!(handler.last_error().is_none());
+
+        let start = std::time::Instant::now();
+        line.write_all(&[0x11, 0x01, 0x00, 0x13, 0x00, 0x25, 0x0E, 0x85])
+            .await
+            .unwrap();
+        assert!(channel.run().await.is_err());
+        let (err, time) = handler.last_error().unwrap();
+        assert_eq!(err, MbError::InvalidCrc);
+        assert!(time >= start && time.elapsed() < Duration::from_secs(1));
+    }
+
+    fn rtu_request(pdu: &[u8]) -> BytesMut {
+        let mut request = BytesMut::from(&[0x11][..]);
+        request.extend_from_slice(pdu);
+        request.extend_from_slice(&calc_crc_be(&request).to_be_bytes());
+        request
+    }
+
+    #[tokio::test]
+    async fn silent_unknown_function() {
+        for silent in [false, true] {
+            let (stream, mut line) = tokio::io::duplex(512);
+            let open = Box::new(|| Err(Error::other("not used")));
+            let (mut channel, mut handler) =
+                RtuSlaveChannel::with_stream("mock".to_owned(), stream, open);
+            channel.silent_unknown_function = silent;
+            tokio::spawn(async move {
+                while let Some(request) = handler.request_rx.recv().await {
+                    let pdu = ResponsePdu::exception(0x41, ExceptionCode::IllegalFunction);
+                    let _ = Response::make(request, pdu).send();
+                }
+            });
+
+            line.write_all(&rtu_request(&[0x41, 0x01, 0x02]))
+                .await
+                .unwrap();
+            channel.run().await.unwrap();
+            assert!(channel.context.input.is_empty());
+            // the answer of the handler, if any
+            let _ = tokio::time::timeout(Duration::from_millis(50), channel.run()).await;
+
+            let mut response = [0u8; 5];
+            let read = tokio::time::timeout(Duration::from_millis(50), line.read(&mut response));
+            match read.await {
+                Ok(size) => {
+                    assert!(!silent);
+                    assert_eq!(size.unwrap(), 5);
+                    assert_eq!(response[..3], [0x11, 0xC1, 0x01]);
+                }
+                Err(_) => assert!(silent),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn slaves_filter() {
+        let (stream, mut line) = tokio::io::duplex(512);
+        let open = Box::new(|| Err(Error::other("not used")));
+        let (mut channel, mut handler) =
+            RtuSlaveChannel::with_stream("mock".to_owned(), stream, open);
+        channel.slaves = Some(vec![0x10]);
+
+        // slave 0x11 is not served
+        line.write_all(&rtu_request(&[0x03, 0x00, 0x00, 0x00, 0x01]))
+            .await
+            .unwrap();
+        channel.run().await.unwrap();
+        assert!(channel.context.input.is_empty());
+        assert!(handler.request_rx.try_recv().is_err());
+        let mut response = [0u8; 8];
+        let read = tokio::time::timeout(Duration::from_millis(50), line.read(&mut response));
+        assert!(read.await.is_err());
+
+        // broadcast
+        let mut request = BytesMut::from(&[0x00, 0x06, 0x00, 0x01, 0x00, 0x02][..]);
+        request.extend_from_slice(&calc_crc_be(&request).to_be_bytes());
+        line.write_all(&request).await.unwrap();
+        channel.run().await.unwrap();
+        assert_eq!(handler.request_rx.try_recv().unwrap().slave, 0);
+    }
+
+    #[tokio::test]
+    async fn broadcast() {
+        let (stream, mut line) = tokio::io::duplex(512);
+        let open = Box::new(|| Err(Error::other("not used")));
+        let (mut channel, mut handler) =
+            RtuSlaveChannel::with_stream("mock".to_owned(), stream, open);
+
+        let mut request =
+            BytesMut::from(&[0x00, 0x10, 0x00, 0x01, 0x00, 0x01, 0x02, 0x12, 0x34][..]);
+        request.extend_from_slice(&calc_crc_be(&request).to_be_bytes());
+        line.write_all(&request).await.unwrap();
+        channel.run().await.unwrap();
+
+        let request = handler.request_rx.try_recv().unwrap();
+        assert!(request.is_broadcast());
+        let pdu = ResponsePdu::write_multiple_registers(0x1, 0x1);
+        Response::make(request, pdu).send().unwrap();
+        channel.run().await.unwrap();
+
+        let mut response = [0u8; 8];
+        let read = tokio::time::timeout(Duration::from_millis(50), line.read(&mut response));
+        assert!(read.await.is_err());
+        assert_eq!(handler.stats().bytes_out.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn response_order() {
+        let (stream, mut line) = tokio::io::duplex(512);
+        let open = Box::new(|| Err(Error::other("not used")));
+        let (mut channel, mut handler) =
+            RtuSlaveChannel::with_stream("mock".to_owned(), stream, open);
+        channel.order = Some(ResponseOrder::new(4));
+
+        let mut requests = Vec::new();
+        for address in [0x1, 0x2] {
+            let request = rtu_request(&[0x3, 0x0, address, 0x0, 0x1]);
+            line.write_all(&request).await.unwrap();
+            channel.run().await.unwrap();
+            requests.push(handler.request_rx.try_recv().unwrap());
+        }
+
+        // the handler completes them in reverse order
+        let second = requests.pop().unwrap();
+        let first = requests.pop().unwrap();
+        let pdu = ResponsePdu::read_holding_registers([0x2u16].as_slice());
+        Response::make(second, pdu).send().unwrap();
+        channel.run().await.unwrap();
+        let pdu = ResponsePdu::read_holding_registers([0x1u16].as_slice());
+        Response::make(first, pdu).send().unwrap();
+        channel.run().await.unwrap();
+
+        let mut output = [0u8; 14];
+        line.read_exact(&mut output).await.unwrap();
+        assert_eq!(output[..5], [0x11, 0x3, 0x2, 0x0, 0x1]);
+        assert_eq!(output[7..12], [0x11, 0x3, 0x2, 0x0, 0x2]);
+    }
+
+    #[tokio::test]
+    async fn reopen_on_read_error() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counter = attempts.clone();
+        let open = Box::new(move || {
+            // fail twice, like a port that is not back yet
+            if counter.fetch_add(1, Ordering::SeqCst) < 2 {
+                Err(Error::other("no such device"))
+            } else {
+                Ok(MockStream { broken: false })
+            }
+        });
+
+        let (mut channel, _handler) =
+            RtuSlaveChannel::with_stream("mock".to_owned(), MockStream { broken: true }, open);
+        channel.reopen_delay = (Duration::from_millis(10), Duration::from_millis(20));
+
+        let start = std::time::Instant::now();
+        let res = tokio::time::timeout(Duration::from_secs(5), channel.run()).await;
+        assert!(res.unwrap().is_ok());
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        assert!(!channel.stream.broken);
+        // 10ms + 20ms + 20ms of backoff
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+}
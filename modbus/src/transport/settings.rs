@@ -1,10 +1,18 @@
+use crate::codec::rtuext::FaultInjection;
+use crate::transport::event::EventCallback;
+use crate::transport::limit::RateLimit;
+use crate::transport::queue::QueuePolicy;
+use std::net::SocketAddr;
 use std::str::FromStr;
+use std::time::Duration;
 
 #[derive(Clone)]
 pub enum TransportAddress {
     Tcp(String),
     Udp(String),
     Serial(String),
+    /// serial port with Modbus ASCII framing
+    Ascii(String),
 }
 
 impl TransportAddress {
@@ -13,6 +21,31 @@ impl TransportAddress {
             TransportAddress::Tcp(address) => address,
             TransportAddress::Udp(address) => address,
             TransportAddress::Serial(address) => address,
+            TransportAddress::Ascii(address) => address,
+        }
+    }
+}
+
+/// Raw bytes logging mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BytesLog {
+    /// offset annotated hex + ASCII dump of every input/output buffer (trace level)
+    Always,
+    /// log input buffer only if it can't be decoded (warn level)
+    OnError,
+    /// log one line per frame with a summary and raw bytes (debug level).
+    /// Bad input is logged as in OnError
+    Compact,
+}
+
+impl FromStr for BytesLog {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "always" => Ok(BytesLog::Always),
+            "error" => Ok(BytesLog::OnError),
+            "compact" => Ok(BytesLog::Compact),
+            _ => Err(()),
         }
     }
 }
@@ -20,12 +53,93 @@ impl TransportAddress {
 #[derive(Clone)]
 pub struct Settings {
     pub address: TransportAddress,
+    pub bytes_log: BytesLog,
+    /// answer with SlaveDeviceBusy if a request isn't processed in time. Off by default
+    pub processing_timeout: Option<Duration>,
+    /// time the handler has for a request. Sets Request::deadline. Later
+    /// responses are dropped and the server sends nothing. Off by default
+    pub request_deadline: Option<Duration>,
+    /// don't answer faster than this. Emulates slow devices. Off by default
+    pub min_response_time: Option<Duration>,
+    /// damage responses of the RTU slave. For testing masters only
+    pub rtu_fault: Option<FaultInjection>,
+    /// RTU: max. time to assemble one frame. Bounds the time a flood of junk
+    /// could hold the input buffer. Off by default
+    pub rtu_assembly_timeout: Option<Duration>,
+    /// RTU: silence that ends a partial frame. 250ms by default
+    pub rtu_timeout: Option<Duration>,
+    /// RTU: end a partial frame after 3.5 characters of silence at the port
+    /// speed, as the spec says. Frames split by slow adapters (USB-serial
+    /// latency) are lost. Used if rtu_timeout isn't set. Off by default
+    pub rtu_silent_interval: bool,
+    /// RTU: drop the local echo of the last response (RS-485 adapters that
+    /// receive what they transmit). Off by default
+    pub rtu_echo_suppression: bool,
+    /// RTU: answer in the order of requests, holding up to this many
+    /// unanswered ones. Off by default
+    pub rtu_response_depth: Option<usize>,
+    /// answer fc1-fc4 with the last good response if the handler is slower
+    /// than this. Off by default
+    pub stale_cache: Option<Duration>,
+    /// answer fc15/fc16 with zero count with IllegalDataValue instead of
+    /// dropping the input. The handler doesn't see them. Off by default
+    pub zero_count_exception: bool,
+    /// answer 0x2b requests with MEI type 0xD (CANopen) with IllegalDataValue.
+    /// Only Read Device Identification reaches the handler. Off by default
+    pub strict_mei: bool,
+    /// RTU: don't answer requests with unknown function codes, so devices
+    /// that own them on the same bus aren't disturbed. By default they go to
+    /// the handler and get IllegalFunction
+    pub rtu_silent_unknown_function: bool,
+    /// serve only these slave ids and stay silent for others. Broadcasts (0)
+    /// are always served. All ids by default
+    pub slaves: Option<Vec<u8>>,
+    /// answer with SlaveDeviceBusy if the transport gets requests faster. Off by default
+    pub rate_limit: Option<RateLimit>,
+    /// TCP: close a connection without input for this time. None keeps idle
+    /// connections open. 30s by default
+    pub tcp_idle_timeout: Option<Duration>,
+    /// UDP: size of the receive buffer. 512 bytes by default
+    pub udp_buffer: Option<usize>,
+    /// UDP: what to do with a request when too many are pending. Reject
+    /// answers it with SlaveDeviceBusy. DropOldest by default
+    pub udp_queue_policy: QueuePolicy,
+    /// UDP: answer each source in the order of its requests. Off by default
+    pub ordered_responses: bool,
+    /// called on connects/disconnects of TCP clients, decode errors and
+    /// timeouts. Off by default
+    pub on_event: Option<EventCallback>,
+    /// max. number of requests waiting for the handler. More are answered
+    /// with SlaveDeviceBusy. Unbounded by default
+    pub queue_depth: Option<usize>,
 }
 
 impl Default for Settings {
     fn default() -> Settings {
         Settings {
             address: TransportAddress::Tcp("0.0.0.0:502".to_owned()),
+            bytes_log: BytesLog::Always,
+            processing_timeout: None,
+            request_deadline: None,
+            min_response_time: None,
+            rtu_fault: None,
+            rtu_assembly_timeout: None,
+            rtu_timeout: None,
+            rtu_silent_interval: false,
+            rtu_echo_suppression: false,
+            rtu_response_depth: None,
+            stale_cache: None,
+            zero_count_exception: false,
+            strict_mei: false,
+            rtu_silent_unknown_function: false,
+            slaves: None,
+            rate_limit: None,
+            tcp_idle_timeout: Some(Duration::from_secs(30)),
+            udp_buffer: None,
+            udp_queue_policy: QueuePolicy::DropOldest,
+            ordered_responses: false,
+            on_event: None,
+            queue_depth: None,
         }
     }
 }
@@ -33,29 +147,47 @@ impl Default for Settings {
 impl FromStr for TransportAddress {
     type Err = ();
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        s.split(':').next().map_or(Err(()), |tp| {
-            let begin = tp.len() + 1;
-            if begin >= s.len() {
-                return Err(());
-            }
+        let (tp, remain) = s.split_once(':').ok_or(())?;
+        if remain.is_empty() {
+            return Err(());
+        }
 
-            let remain = &s[begin..];
-            match tp {
-                "tcp" => Ok(TransportAddress::Tcp(remain.to_owned())),
-                "udp" => Ok(TransportAddress::Udp(remain.to_owned())),
-                "serial" => Ok(TransportAddress::Serial(remain.to_owned())),
-                _ => Err(()),
-            }
-        })
+        match tp {
+            "tcp" => net_address(remain).map(TransportAddress::Tcp),
+            "udp" => net_address(remain).map(TransportAddress::Udp),
+            "serial" => Ok(TransportAddress::Serial(remain.to_owned())),
+            "ascii" => Ok(TransportAddress::Ascii(remain.to_owned())),
+            _ => Err(()),
+        }
     }
 }
 
-#[cfg(test)]
+/// IPv4/IPv6 socket address or host:port. Hosts are resolved at bind time
+fn net_address(address: &str) -> Result<String, ()> {
+    if SocketAddr::from_str(address).is_ok() {
+        return Ok(address.to_owned());
+    }
+    let (host, port) = address.rsplit_once(':').ok_or(())?;
+    // IPv6 literals must be in brackets
+    if host.is_empty() || host.contains(':') || u16::from_str(port).is_err() {
+        return Err(());
+    }
+    Ok(address.to_owned())
+}
 
+#[cfg(test)]
 mod test {
 
     use super::*;
 
+    #[test]
+    fn bytes_log() {
+        assert_eq!(BytesLog::from_str("always"), Ok(BytesLog::Always));
+        assert_eq!(BytesLog::from_str("error"), Ok(BytesLog::OnError));
+        assert_eq!(BytesLog::from_str("compact"), Ok(BytesLog::Compact));
+        assert!(BytesLog::from_str("dump").is_err());
+    }
+
     #[test]
     fn transport_address() {
         let address = TransportAddress::from_str("");
@@ -80,6 +212,28 @@ mod test {
             _ => unreachable!(),
         };
 
+        let address = TransportAddress::from_str("tcp:[::1]:502").unwrap();
+        match address {
+            TransportAddress::Tcp(ip) => {
+                assert_eq!(ip, "[::1]:502");
+            }
+            _ => unreachable!(),
+        };
+
+        let address = TransportAddress::from_str("tcp:localhost:502").unwrap();
+        match address {
+            TransportAddress::Tcp(host) => {
+                assert_eq!(host, "localhost:502");
+            }
+            _ => unreachable!(),
+        };
+
+        assert!(TransportAddress::from_str("tcp:").is_err());
+        assert!(TransportAddress::from_str("tcp:localhost").is_err());
+        assert!(TransportAddress::from_str("tcp::502").is_err());
+        assert!(TransportAddress::from_str("tcp:::1:502").is_err());
+        assert!(TransportAddress::from_str("udp:127.0.0.1:70000").is_err());
+
         let address = TransportAddress::from_str("serial:/dev/tty0").unwrap();
         match address {
             TransportAddress::Serial(name) => {
@@ -87,5 +241,13 @@ mod test {
             }
             _ => unreachable!(),
         };
+
+        let address = TransportAddress::from_str("ascii:/dev/tty0").unwrap();
+        match address {
+            TransportAddress::Ascii(name) => {
+                assert_eq!(name, "/dev/tty0");
+            }
+            _ => unreachable!(),
+        };
     }
 }
@@ -0,0 +1,23 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counters of a transport. Shared between the transport tasks and its Handler
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub frames_decoded: AtomicU64,
+    pub crc_errors: AtomicU64,
+    /// inactive connections and dropped partial frames
+    pub timeouts: AtomicU64,
+    pub exceptions_sent: AtomicU64,
+    pub bytes_in: AtomicU64,
+    pub bytes_out: AtomicU64,
+}
+
+impl Stats {
+    pub(crate) fn add(counter: &AtomicU64, value: usize) {
+        counter.fetch_add(value as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn inc(counter: &AtomicU64) {
+        Stats::add(counter, 1);
+    }
+}
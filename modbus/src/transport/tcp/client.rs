@@ -0,0 +1,143 @@
+use crate::codec::master::MasterCodec;
+use crate::frame::prelude::*;
+use crate::transport::error::ClientError;
+use bytes::BytesMut;
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio_util::codec::{Decoder, Encoder};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// Modbus TCP master. One request at a time
+pub struct TcpClient {
+    stream: TcpStream,
+    codec: MasterCodec,
+    input: BytesMut,
+    next_id: u16,
+    timeout: Duration,
+}
+
+impl TcpClient {
+    pub async fn connect(addr: impl ToSocketAddrs) -> io::Result<TcpClient> {
+        let stream = TcpStream::connect(addr).await?;
+        Ok(TcpClient {
+            stream,
+            codec: MasterCodec::new_tcp(),
+            input: BytesMut::with_capacity(512),
+            next_id: 1,
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// time to wait for the response. 1s by default
+    pub fn with_timeout(mut self, timeout: Duration) -> TcpClient {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Send the request and wait for the response with the same transaction id.
+    /// Exception responses are returned as ClientError::Exception
+    pub async fn request(
+        &mut self,
+        slave: u8,
+        pdu: RequestPdu,
+    ) -> Result<ResponsePdu, ClientError> {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+
+        let mut output = BytesMut::new();
+        self.codec
+            .encode(RequestFrame::from_parts(id, slave, pdu), &mut output)?;
+        self.stream.write_all(&output).await?;
+
+        let pdu = tokio::time::timeout(self.timeout, self.wait_for(id))
+            .await
+            .map_err(|_| ClientError::Timeout)??;
+        match pdu {
+            ResponsePdu::Exception { code, .. } => Err(ClientError::Exception(code)),
+            pdu => Ok(pdu),
+        }
+    }
+
+    async fn wait_for(&mut self, id: u16) -> Result<ResponsePdu, ClientError> {
+        loop {
+            while let Some(frame) = self.codec.decode(&mut self.input)? {
+                // late responses to timed out requests are skipped
+                if frame.id == id {
+                    return Ok(frame.pdu);
+                }
+            }
+            if self.stream.read_buf(&mut self.input).await? == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transport::{prelude::*, tcp::server::TcpServer};
+
+    #[tokio::test]
+    async fn request_to_server() {
+        let settings = Settings {
+            address: TransportAddress::Tcp("127.0.0.1:15231".to_owned()),
+            ..Default::default()
+        };
+        let mut handler = TcpServer::build(settings).await.unwrap();
+        tokio::spawn(async move {
+            while let Some(request) = handler.request_rx.recv().await {
+                let pdu = match request.pdu {
+                    RequestPdu::ReadHoldingRegisters { .. } => {
+                        ResponsePdu::read_holding_registers([0x1234u16, 0x5678].as_slice())
+                    }
+                    RequestPdu::ReadInputRegisters { .. } => {
+                        ResponsePdu::exception(0x4, ExceptionCode::IllegalDataAddress)
+                    }
+                    // no answer
+                    _ => continue,
+                };
+                let _ = Response::make(request, pdu).send();
+            }
+        });
+
+        let mut client = TcpClient::connect("127.0.0.1:15231")
+            .await
+            .unwrap()
+            .with_timeout(Duration::from_millis(100));
+
+        let pdu = client
+            .request(0x11, RequestPdu::read_holding_registers(0, 2))
+            .await
+            .unwrap();
+        assert_eq!(
+            pdu,
+            ResponsePdu::read_holding_registers([0x1234u16, 0x5678].as_slice())
+        );
+
+        let err = client
+            .request(0x11, RequestPdu::read_input_registers(0, 2))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ClientError::Exception(ExceptionCode::IllegalDataAddress)
+        ));
+
+        let err = client
+            .request(0x11, RequestPdu::write_single_register(0, 1))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ClientError::Timeout));
+
+        // still usable after the timeout
+        let pdu = client
+            .request(0x11, RequestPdu::read_holding_registers(0, 2))
+            .await
+            .unwrap();
+        assert!(matches!(pdu, ResponsePdu::ReadHoldingRegisters { .. }));
+    }
+}
@@ -1,16 +1,32 @@
 use crate::codec::slave::SlaveCodec;
 use crate::frame::prelude::*;
-use crate::transport::{event::EventLog, prelude::*};
-use std::io::{Error, ErrorKind};
+use crate::transport::{
+    channel::{check_queue_depth, request_channel, RequestTx, SendError},
+    error::TransportError,
+    event::{EventLog, LastError},
+    is_served,
+    limit::Limiter,
+    prelude::*,
+    queue::FixedQueue,
+    rejected_request_exception,
+};
+use std::io::Error;
 use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 use uuid::{self, Uuid};
 
-// TODO: Close client if no reading for N ms. It better to make configurable
-const INACTIVE_TIMEOUT: u64 = 30000;
+// pipelined requests of a client waiting for responses
+const MAX_REQUESTS_NUM: usize = 16;
 
+/// Addresses of connected clients shared between the server and its handler
+pub(crate) type ClientList = Arc<Mutex<Vec<SocketAddr>>>;
+
+#[derive(Clone, Copy)]
 struct MsgInfo {
     uuid: Uuid,
     mbid: u16,
@@ -18,56 +34,81 @@ struct MsgInfo {
 
 pub struct TcpServer {
     listener: TcpListener,
-    request_tx: mpsc::UnboundedSender<Request>,
+    request_tx: RequestTx,
+    settings: Settings,
+    codec: SlaveCodec,
+    clients: ClientList,
+    limiter: Limiter,
+    last_error: LastError,
+    stats: Arc<Stats>,
+    shutdown: CancellationToken,
 }
 
 struct Client {
     stream: TcpStream,
-    request_tx: mpsc::UnboundedSender<Request>,
+    request_tx: RequestTx,
     response_tx: mpsc::UnboundedSender<Response>,
     response_rx: mpsc::UnboundedReceiver<Response>,
     address: String,
+    peer: SocketAddr,
+    clients: ClientList,
+    limiter: Limiter,
+    slaves: Option<Vec<u8>>,
+    idle_timeout: Option<Duration>,
+    // why the connection is closed. Logged on drop
+    close_reason: &'static str,
     context: IoContext,
-    wait_for: Option<MsgInfo>,
+    queue: FixedQueue<MsgInfo>,
+    shutdown: CancellationToken,
 }
 
 impl Client {
     fn spawn(mut self) {
         EventLog::info(&self.address, &"connected");
+        self.context.notify(TransportEvent::Connected(self.peer));
+        self.clients.lock().unwrap().push(self.peer);
         tokio::spawn(async move { while self.run().await.is_ok() {} });
     }
 
     async fn run(&mut self) -> Result<(), Error> {
-        let read = tokio::time::timeout(
-            std::time::Duration::from_millis(INACTIVE_TIMEOUT),
-            self.stream.read_buf(&mut self.context.input),
-        );
+        let idle_timeout = self.idle_timeout;
+        let input = self.stream.read_buf(&mut self.context.input);
+        let read = async move {
+            match idle_timeout {
+                Some(timeout) => tokio::time::timeout(timeout, input).await,
+                None => Ok(input.await),
+            }
+        };
 
         tokio::select! {
             result = read => {
                 match result {
                     Err(e) => {
                         // timeout => close
-                        EventLog::warning(&self.address, &"inactive timeout");
+                        self.close_reason = "inactive timeout";
+                        self.context.timeout();
                         Err(Error::from(e))
                     }
                     Ok(Err(e)) => {
                         // read error => close
+                        self.close_reason = "read error";
                         EventLog::error(&self.address, &e);
+                        self.context.last_error.set_io(&e);
                         Err(e)
                     },
 
                     Ok(Ok(0)) => {
                         // close socket
-                        Err(Error::new(ErrorKind::Other, "close"))
+                        self.close_reason = "closed by peer";
+                        Err(Error::other("close"))
                     },
-                    Ok(Ok(_nbytes)) =>
+                    Ok(Ok(nbytes)) =>
                     {
+                        Stats::add(&self.context.stats.bytes_in, nbytes);
                         // got data. Try to process
-                        self.on_input().await.map_err(|e|
+                        self.on_input().await.inspect_err(|e|
                             {
-                                EventLog::error(&self.address,&e);
-                                e
+                                EventLog::error(&self.address,e);
                             })
                     },
 
@@ -76,17 +117,41 @@ impl Client {
             response = self.response_rx.recv() => {
                 self.on_response(response).await
             }
+            // in-flight requests are answered first
+            _ = self.shutdown.cancelled(), if self.queue.len() == 0 => {
+                self.close_reason = "shutdown";
+                Err(Error::other("shutdown"))
+            }
         }
     }
 
     async fn on_input(&mut self) -> Result<(), Error> {
-        EventLog::input(&self.address, &self.context.input);
-        let Some(request) = self.context.decode()? else { return Ok(()) };
-        self.on_request(request).await;
+        // a client may pipeline several requests into one segment
+        while let Some(request) = self.context.decode_logged(&self.address)? {
+            self.on_request(request).await?;
+        }
         Ok(())
     }
 
-    async fn on_request(&mut self, frame: RequestFrame) {
+    async fn on_request(&mut self, frame: RequestFrame) -> Result<(), Error> {
+        if !is_served(&self.slaves, frame.slave) {
+            return Ok(());
+        }
+
+        if let Some(pdu) = rejected_request_exception(&frame.pdu) {
+            return self
+                .on_output(ResponseFrame::from_parts(frame.id, frame.slave, pdu))
+                .await;
+        }
+
+        if !self.limiter.allow() {
+            EventLog::warning(&self.address, &"rate limit exceeded");
+            let pdu = Limiter::busy(&frame.pdu);
+            return self
+                .on_output(ResponseFrame::from_parts(frame.id, frame.slave, pdu))
+                .await;
+        }
+
         // make ids
         let uuid = Uuid::new_v4();
         let mbid = frame.id;
@@ -96,60 +161,107 @@ impl Client {
             uuid,
             slave: frame.slave,
             pdu: frame.pdu,
+            deadline: None,
             response_tx: Some(self.response_tx.clone()),
         };
 
         EventLog::request(&self.address, &request);
 
         // try to send to processor
-        if self.request_tx.send(request).is_ok() {
-            // save info about the request
-            self.wait_for = Some(MsgInfo { uuid, mbid });
-        } else {
-            EventLog::warning(&self.address, &"can't process input request.TX overflow?");
+        match self.request_tx.send(request) {
+            Ok(()) => {
+                // save info about the request
+                self.queue.push_replace(MsgInfo { uuid, mbid });
+            }
+            Err(SendError::Full(request)) => {
+                EventLog::warning(&self.address, &"request queue is full");
+                let pdu = Limiter::busy(&request.pdu);
+                return self
+                    .on_output(ResponseFrame::from_parts(mbid, request.slave, pdu))
+                    .await;
+            }
+            Err(SendError::Closed) => {
+                EventLog::warning(&self.address, &"can't process input request.TX overflow?")
+            }
         }
+        Ok(())
     }
 
     async fn on_response(&mut self, response: Option<Response>) -> Result<(), Error> {
-        let Some(response) = response else { return Ok(()); };
-        let resp_match = self
-            .wait_for
-            .as_ref()
-            .map_or(false, |info| info.uuid == response.uuid);
-
-        if resp_match {
-            let info = self.wait_for.take().unwrap();
-            let frame = ResponseFrame::from_parts(info.mbid, response.slave, response.pdu);
-            self.on_output(frame).await?;
-            self.context.reset();
+        let Some(response) = response else {
+            return Ok(());
+        };
+        let info = if response.is_last() {
+            self.queue.take_if(|info| info.uuid == response.uuid)
         } else {
+            self.queue
+                .find_if(|info| info.uuid == response.uuid)
+                .copied()
+        };
+
+        if response.is_dropped() {
+            return Ok(());
+        }
+        let Some(info) = info else {
             EventLog::warning(&self.address, &"unknown response uuid");
+            return Ok(());
         };
-        Ok(())
+        let frame = ResponseFrame::from_parts(info.mbid, response.slave, response.pdu);
+        self.on_output(frame).await
     }
 
     async fn on_output(&mut self, frame: ResponseFrame) -> Result<(), Error> {
         self.context.encode(frame)?;
-        EventLog::output(&self.address, &self.context.output);
-        self.stream.write_all(&self.context.output[..]).await
+        self.context.log_output(&self.address);
+        let res = self.stream.write_all(&self.context.output[..]).await;
+        self.context.output.clear();
+        res
     }
 }
 
 impl Drop for Client {
     fn drop(&mut self) {
-        EventLog::info(&self.address, &"close");
+        EventLog::info(&self.address, &format!("close: {}", self.close_reason));
+        self.context.notify(TransportEvent::Disconnected(self.peer));
+        let mut clients = self.clients.lock().unwrap();
+        if let Some(pos) = clients.iter().position(|peer| *peer == self.peer) {
+            clients.swap_remove(pos);
+        }
     }
 }
 
 impl TcpServer {
-    pub async fn build(settings: Settings) -> Result<Handler, Error> {
-        let listener = TcpListener::bind(settings.address.get()).await?;
-        let (tx, rx) = mpsc::unbounded_channel();
+    pub async fn build(settings: Settings) -> Result<Handler, TransportError> {
+        TcpServer::build_with_codec(settings, SlaveCodec::new_tcp()).await
+    }
+
+    /// build with a pre-configured TCP codec. Each connection gets a copy
+    pub async fn build_with_codec(
+        settings: Settings,
+        codec: SlaveCodec,
+    ) -> Result<Handler, TransportError> {
+        check_queue_depth(settings.queue_depth)?;
+        let listener = TcpListener::bind(settings.address.get())
+            .await
+            .map_err(TransportError::Bind)?;
+        let limiter = Limiter::new(settings.rate_limit);
+        let (tx, rx) = request_channel(settings.queue_depth);
         let server = TcpServer {
             listener,
             request_tx: tx,
+            settings,
+            codec,
+            clients: ClientList::default(),
+            limiter,
+            last_error: LastError::default(),
+            stats: Arc::default(),
+            shutdown: CancellationToken::new(),
         };
-        let handler = Handler { request_rx: rx };
+        let handler = Handler::new(rx)
+            .with_clients(server.clients.clone())
+            .with_last_error(server.last_error.clone())
+            .with_stats(server.stats.clone())
+            .with_shutdown(server.shutdown.clone());
         server.spawn();
         Ok(handler)
     }
@@ -158,28 +270,239 @@ impl TcpServer {
         tokio::spawn(async move {
             loop {
                 tokio::select! {
+                    _ = self.shutdown.cancelled() => break,
                     Ok((stream,address)) = self.listener.accept() => {
                         self.spawn_client(stream, address);
                     }
                 }
             }
+            EventLog::info(&self.settings.address.get(), &"shutdown");
         });
     }
 
     fn spawn_client(&mut self, stream: TcpStream, address: SocketAddr) {
         let (tx, rx) = mpsc::unbounded_channel();
+        let peer = address;
         let address = address.to_string();
-        let codec = SlaveCodec::new_tcp();
-        let context = IoContext::new(codec);
+        let mut codec = self.codec.clone();
+        if self.settings.zero_count_exception {
+            codec = codec.with_zero_count_writes(true);
+        }
+        if self.settings.strict_mei {
+            codec = codec.with_strict_mei(true);
+        }
+        let mut context = IoContext::new(codec);
+        context.bytes_log = self.settings.bytes_log;
+        context.last_error = self.last_error.clone();
+        context.stats = self.stats.clone();
+        context.on_event = self.settings.on_event.clone();
         let client = Client {
             stream,
             request_tx: self.request_tx.clone(),
             response_tx: tx,
             response_rx: rx,
             address,
+            peer,
+            clients: self.clients.clone(),
+            limiter: self.limiter.clone(),
+            slaves: self.settings.slaves.clone(),
+            idle_timeout: self.settings.tcp_idle_timeout,
+            close_reason: "close",
             context,
-            wait_for: None,
+            queue: FixedQueue::new(MAX_REQUESTS_NUM),
+            shutdown: self.shutdown.clone(),
         };
         client.spawn();
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::Ordering;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    async fn wait_clients(handler: &Handler, count: usize) -> Vec<SocketAddr> {
+        for _ in 0..100 {
+            let clients = handler.connected_clients();
+            if clients.len() == count {
+                return clients;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        handler.connected_clients()
+    }
+
+    #[tokio::test]
+    async fn connected_clients() {
+        let settings = Settings {
+            address: TransportAddress::Tcp("127.0.0.1:15219".to_owned()),
+            ..Default::default()
+        };
+        let handler = TcpServer::build(settings).await.unwrap();
+        assert!(handler.connected_clients().is_empty());
+
+        let first = TcpStream::connect("127.0.0.1:15219").await.unwrap();
+        let second = TcpStream::connect("127.0.0.1:15219").await.unwrap();
+        let clients = wait_clients(&handler, 2).await;
+        assert_eq!(clients.len(), 2);
+        assert!(clients.contains(&first.local_addr().unwrap()));
+        assert!(clients.contains(&second.local_addr().unwrap()));
+
+        let remain = second.local_addr().unwrap();
+        drop(first);
+        let clients = wait_clients(&handler, 1).await;
+        assert_eq!(clients, vec![remain]);
+    }
+
+    #[tokio::test]
+    async fn connection_events() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let log = events.clone();
+        let settings = Settings {
+            address: TransportAddress::Tcp("127.0.0.1:15243".to_owned()),
+            on_event: Some(Arc::new(move |event| log.lock().unwrap().push(event))),
+            ..Default::default()
+        };
+        let handler = TcpServer::build(settings).await.unwrap();
+
+        let first = TcpStream::connect("127.0.0.1:15243").await.unwrap();
+        let second = TcpStream::connect("127.0.0.1:15243").await.unwrap();
+        let peers = [first.local_addr().unwrap(), second.local_addr().unwrap()];
+        wait_clients(&handler, 2).await;
+        drop(first);
+        drop(second);
+        wait_clients(&handler, 0).await;
+
+        let events = events.lock().unwrap();
+        for peer in peers {
+            assert!(events.contains(&TransportEvent::Connected(peer)));
+            assert!(events.contains(&TransportEvent::Disconnected(peer)));
+        }
+        assert_eq!(events.len(), 4);
+    }
+
+    async fn exchange(stream: &mut TcpStream, id: u8) -> Vec<u8> {
+        let request = [0x0, id, 0x0, 0x0, 0x0, 0x6, 0x1, 0x3, 0x0, 0x0, 0x0, 0x1];
+        stream.write_all(&request).await.unwrap();
+        let mut buffer = [0u8; 256];
+        let size = stream.read(&mut buffer).await.unwrap();
+        buffer[7..size].to_vec()
+    }
+
+    #[tokio::test]
+    async fn rate_limit_across_clients() {
+        let settings = Settings {
+            address: TransportAddress::Tcp("127.0.0.1:15221".to_owned()),
+            rate_limit: Some(RateLimit {
+                rate: 0.001,
+                burst: 2,
+            }),
+            ..Default::default()
+        };
+        let mut handler = TcpServer::build(settings).await.unwrap();
+        tokio::spawn(async move {
+            while let Some(request) = handler.request_rx.recv().await {
+                let pdu = ResponsePdu::read_holding_registers([0x1234u16].as_slice());
+                let _ = Response::make(request, pdu).send();
+            }
+        });
+
+        let mut first = TcpStream::connect("127.0.0.1:15221").await.unwrap();
+        let mut second = TcpStream::connect("127.0.0.1:15221").await.unwrap();
+        let ok = vec![0x3, 0x2, 0x12, 0x34];
+        let busy = vec![0x83, 0x6];
+        assert_eq!(exchange(&mut first, 1).await, ok);
+        assert_eq!(exchange(&mut second, 2).await, ok);
+        assert_eq!(exchange(&mut first, 3).await, busy);
+        assert_eq!(exchange(&mut second, 4).await, busy);
+    }
+
+    #[tokio::test]
+    async fn pipelined_requests() {
+        let settings = Settings {
+            address: TransportAddress::Tcp("127.0.0.1:15239".to_owned()),
+            ..Default::default()
+        };
+        let mut handler = TcpServer::build(settings).await.unwrap();
+        // answer the second request first
+        tokio::spawn(async move {
+            let first = handler.request_rx.recv().await.unwrap();
+            let second = handler.request_rx.recv().await.unwrap();
+            for (request, value) in [(second, 0x2u16), (first, 0x1)] {
+                let pdu = ResponsePdu::read_holding_registers([value].as_slice());
+                Response::make(request, pdu).send().unwrap();
+            }
+        });
+
+        let mut client = TcpStream::connect("127.0.0.1:15239").await.unwrap();
+        let request = [
+            0x0, 0x1, 0x0, 0x0, 0x0, 0x6, 0x1, 0x3, 0x0, 0x0, 0x0, 0x1, //
+            0x0, 0x2, 0x0, 0x0, 0x0, 0x6, 0x1, 0x3, 0x0, 0x0, 0x0, 0x1,
+        ];
+        client.write_all(&request).await.unwrap();
+
+        let mut buffer = [0u8; 22];
+        client.read_exact(&mut buffer).await.unwrap();
+        assert_eq!(
+            buffer,
+            [
+                0x0, 0x2, 0x0, 0x0, 0x0, 0x5, 0x1, 0x3, 0x2, 0x0, 0x2, //
+                0x0, 0x1, 0x0, 0x0, 0x0, 0x5, 0x1, 0x3, 0x2, 0x0, 0x1,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn idle_timeout() {
+        let settings = Settings {
+            address: TransportAddress::Tcp("127.0.0.1:15241".to_owned()),
+            tcp_idle_timeout: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let handler = TcpServer::build(settings).await.unwrap();
+        let mut client = TcpStream::connect("127.0.0.1:15241").await.unwrap();
+
+        let mut buffer = [0u8; 16];
+        let read = tokio::time::timeout(Duration::from_secs(1), client.read(&mut buffer));
+        assert_eq!(read.await.unwrap().unwrap(), 0);
+        assert_eq!(handler.stats().timeouts.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn bind_hostname() {
+        let settings = Settings {
+            address: "tcp:localhost:15235".parse().unwrap(),
+            ..Default::default()
+        };
+        let _handler = TcpServer::build(settings).await.unwrap();
+        assert!(TcpStream::connect("localhost:15235").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn shutdown() {
+        let settings = Settings {
+            address: TransportAddress::Tcp("127.0.0.1:15233".to_owned()),
+            ..Default::default()
+        };
+        let handler = TcpServer::build(settings).await.unwrap();
+        let mut client = TcpStream::connect("127.0.0.1:15233").await.unwrap();
+        assert_eq!(wait_clients(&handler, 1).await.len(), 1);
+        assert!(TcpListener::bind("127.0.0.1:15233").await.is_err());
+
+        handler.shutdown();
+        let mut released = false;
+        for _ in 0..100 {
+            if TcpListener::bind("127.0.0.1:15233").await.is_ok() {
+                released = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(released);
+
+        // idle client is closed
+        let mut buffer = [0u8; 16];
+        assert_eq!(client.read(&mut buffer).await.unwrap(), 0);
+    }
+}
@@ -1,2 +1 @@
-mod queue;
 pub mod server;
@@ -1,15 +1,30 @@
+use crate::codec::mbap::MBAP_HEADER_LEN;
 use crate::codec::slave::SlaveCodec;
 use crate::frame::prelude::*;
-use crate::transport::{event::EventLog, prelude::*, udp::queue::FixedQueue};
+use crate::transport::{
+    channel::{check_queue_depth, request_channel, RequestTx, SendError},
+    error::TransportError,
+    event::EventLog,
+    is_served,
+    limit::Limiter,
+    order::ResponseOrder,
+    prelude::*,
+    queue::FixedQueue,
+    rejected_request_exception,
+};
 use std::io::Error;
 use std::net::SocketAddr;
 use tokio::net::UdpSocket;
 use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 use uuid::{self, Uuid};
 
-const MAX_BUFFER_SIZE: usize = 512;
+const DEFAULT_BUFFER_SIZE: usize = 512;
+// MBAP header and function code with at least one byte of data
+const MIN_BUFFER_SIZE: usize = MBAP_HEADER_LEN + 2;
 const MAX_REQUESTS_NUM: usize = 256;
 
+#[derive(Clone, Copy)]
 struct MsgInfo {
     uuid: Uuid,
     mbid: u16,
@@ -19,19 +34,60 @@ struct MsgInfo {
 pub struct UdpServer {
     socket: UdpSocket,
     context: IoContext,
-    request_tx: mpsc::UnboundedSender<Request>,
+    request_tx: RequestTx,
     response_tx: mpsc::UnboundedSender<Response>,
     response_rx: mpsc::UnboundedReceiver<Response>,
+    buffer_size: usize,
     queue: FixedQueue<MsgInfo>,
+    queue_policy: QueuePolicy,
+    limiter: Limiter,
+    slaves: Option<Vec<u8>>,
+    order: Option<ResponseOrder>,
+    shutdown: CancellationToken,
 }
 
 impl UdpServer {
-    pub async fn build(settings: Settings) -> Result<Handler, Error> {
+    pub async fn build(settings: Settings) -> Result<Handler, TransportError> {
+        UdpServer::build_with_codec(settings, SlaveCodec::new_udp()).await
+    }
+
+    /// build with a pre-configured UDP codec
+    pub async fn build_with_codec(
+        settings: Settings,
+        codec: SlaveCodec,
+    ) -> Result<Handler, TransportError> {
+        let (server, handler) = UdpServer::bind(settings, codec).await?;
+        server.spawn();
+        Ok(handler)
+    }
+
+    async fn bind(
+        settings: Settings,
+        mut codec: SlaveCodec,
+    ) -> Result<(UdpServer, Handler), TransportError> {
+        check_queue_depth(settings.queue_depth)?;
+        let buffer_size = settings.udp_buffer.unwrap_or(DEFAULT_BUFFER_SIZE);
+        if buffer_size < MIN_BUFFER_SIZE {
+            return Err(TransportError::InvalidSettings(format!(
+                "UDP buffer {} is less than {}",
+                buffer_size, MIN_BUFFER_SIZE
+            )));
+        }
         let address = settings.address.get();
-        let codec = SlaveCodec::new_udp();
-        let context = IoContext::new(codec);
-        let socket = UdpSocket::bind(address).await?;
-        let (tx, rx) = mpsc::unbounded_channel();
+        if settings.zero_count_exception {
+            codec = codec.with_zero_count_writes(true);
+        }
+        if settings.strict_mei {
+            codec = codec.with_strict_mei(true);
+        }
+        let mut context = IoContext::new(codec);
+        context.bytes_log = settings.bytes_log;
+        context.on_event = settings.on_event.clone();
+        context.resize_input(buffer_size);
+        let socket = UdpSocket::bind(address)
+            .await
+            .map_err(TransportError::Bind)?;
+        let (tx, rx) = request_channel(settings.queue_depth);
         let (response_tx, response_rx) = mpsc::unbounded_channel();
         let server = UdpServer {
             socket,
@@ -39,28 +95,39 @@ impl UdpServer {
             request_tx: tx,
             response_tx,
             response_rx,
+            buffer_size,
             queue: FixedQueue::new(MAX_REQUESTS_NUM),
+            queue_policy: settings.udp_queue_policy,
+            limiter: Limiter::new(settings.rate_limit),
+            slaves: settings.slaves,
+            order: settings
+                .ordered_responses
+                .then(|| ResponseOrder::new(MAX_REQUESTS_NUM)),
+            shutdown: CancellationToken::new(),
         };
 
-        let handler = Handler { request_rx: rx };
-        server.spawn();
-        Ok(handler)
+        let handler = Handler::new(rx)
+            .with_last_error(server.context.last_error.clone())
+            .with_stats(server.context.stats.clone())
+            .with_shutdown(server.shutdown.clone());
+        Ok((server, handler))
     }
 
     pub fn spawn(mut self) {
         tokio::spawn(async move {
-            loop {
+            while !self.shutdown.is_cancelled() {
                 let _ = self.run().await;
             }
+            EventLog::info(&"UDP server", &"shutdown");
         });
     }
 
     async fn run(&mut self) -> Result<(), Error> {
-        self.context.resize_input(MAX_BUFFER_SIZE);
+        self.context.resize_input(self.buffer_size);
 
         let read = self
             .socket
-            .recv_from(&mut self.context.input[..MAX_BUFFER_SIZE]);
+            .recv_from(&mut self.context.input[..self.buffer_size]);
 
         tokio::select! {
             result = read => {
@@ -71,14 +138,15 @@ impl UdpServer {
                     },
                     Ok((size, address)) => {
                         self.context.resize_input(size);
-                        self.on_input(address).await.map_err(|err|
+                        Stats::add(&self.context.stats.bytes_in, size);
+                        self.on_input(address).await.inspect_err(|err|
                             {
-                                EventLog::error(&address,&err);
-                                err
+                                EventLog::error(&address,err);
                             })
                     }
                     Err(err) => {
                         EventLog::error(&"UDP server",&err);
+                        self.context.last_error.set_io(&err);
                         Err(err)
                     }
                 }
@@ -87,17 +155,48 @@ impl UdpServer {
             response = self.response_rx.recv() => {
                 self.on_response(response).await
             }
+            _ = self.shutdown.cancelled() => Ok(())
         }
     }
 
     async fn on_input(&mut self, address: SocketAddr) -> Result<(), Error> {
-        EventLog::input(&address, &self.context.input);
-        let Some(request) = self.context.decode()? else { return Ok(()) };
-        self.on_request(address, request).await;
-        Ok(())
+        let Some(request) = self.context.decode_logged(&address)? else {
+            return Ok(());
+        };
+        self.on_request(address, request).await
     }
 
-    async fn on_request(&mut self, address: SocketAddr, request: RequestFrame) {
+    async fn on_request(
+        &mut self,
+        address: SocketAddr,
+        request: RequestFrame,
+    ) -> Result<(), Error> {
+        if !is_served(&self.slaves, request.slave) {
+            return Ok(());
+        }
+
+        if let Some(pdu) = rejected_request_exception(&request.pdu) {
+            let frame = ResponseFrame::from_parts(request.id, request.slave, pdu);
+            return self.on_output(address, frame).await.map(|_| ());
+        }
+
+        if !self.limiter.allow() {
+            EventLog::warning(&address, &"rate limit exceeded");
+            let pdu = Limiter::busy(&request.pdu);
+            let frame = ResponseFrame::from_parts(request.id, request.slave, pdu);
+            return self.on_output(address, frame).await.map(|_| ());
+        }
+
+        if self.queue_policy == QueuePolicy::Reject && self.queue.count_free() == 0 {
+            EventLog::warning(&address, &"request queue is full");
+            let pdu = ResponsePdu::exception(
+                request.pdu.func().unwrap_or(0),
+                ExceptionCode::SlaveDeviceBusy,
+            );
+            let frame = ResponseFrame::from_parts(request.id, request.slave, pdu);
+            return self.on_output(address, frame).await.map(|_| ());
+        }
+
         let uuid = Uuid::new_v4();
         let info = MsgInfo {
             uuid,
@@ -109,27 +208,82 @@ impl UdpServer {
             uuid,
             slave: request.slave,
             pdu: request.pdu,
+            deadline: None,
             response_tx: Some(self.response_tx.clone()),
         };
 
         EventLog::request(&address, &request);
 
-        if self.request_tx.send(request).is_ok() {
-            self.queue.push_replace(info);
-        } else {
-            EventLog::warning(&address, &"can't process input request.TX overflow?");
+        match self.request_tx.send(request) {
+            Ok(()) => {
+                // the answer of the evicted request won't be sent
+                if let Some(evicted) = self.queue.push_replace(info) {
+                    self.release(evicted.address, evicted.uuid).await?;
+                }
+                if let Some(order) = self.order.as_mut() {
+                    for frame in order.on_request(address, uuid) {
+                        self.on_output(address, frame).await?;
+                    }
+                }
+            }
+            Err(SendError::Full(request)) => {
+                EventLog::warning(&address, &"request queue is full");
+                let pdu = Limiter::busy(&request.pdu);
+                let frame = ResponseFrame::from_parts(info.mbid, request.slave, pdu);
+                self.on_output(address, frame).await?;
+            }
+            Err(SendError::Closed) => {
+                EventLog::warning(&address, &"can't process input request.TX overflow?")
+            }
         }
+        Ok(())
     }
 
     async fn on_response(&mut self, response: Option<Response>) -> Result<(), Error> {
-        let Some(response) = response else { return Ok(()) };
-        let Some(info) = self.queue.take_if(|rec| rec.uuid == response.uuid) else {
+        let Some(response) = response else {
+            return Ok(());
+        };
+        let info = if response.is_last() {
+            self.queue.take_if(|rec| rec.uuid == response.uuid)
+        } else {
+            self.queue.find_if(|rec| rec.uuid == response.uuid).copied()
+        };
+
+        if response.is_dropped() {
+            return match info {
+                Some(info) => self.release(info.address, response.uuid).await,
+                None => Ok(()),
+            };
+        }
+
+        let Some(info) = info else {
             EventLog::warning(&response.uuid, &"uuid is missing/expired");
-            return Ok(()) };
+            return Ok(());
+        };
 
         EventLog::response(&info.address, &response);
+        let last = response.is_last();
         let frame = ResponseFrame::from_parts(info.mbid, response.slave, response.pdu);
-        self.on_output(info.address, frame).await.map(|_| ())
+        let frames = match self.order.as_mut() {
+            Some(order) => order.on_response(info.address, response.uuid, frame, last),
+            None => vec![frame],
+        };
+
+        for frame in frames {
+            self.on_output(info.address, frame).await?;
+        }
+        Ok(())
+    }
+
+    // send answers of the source that waited for the unanswered request
+    async fn release(&mut self, address: SocketAddr, uuid: Uuid) -> Result<(), Error> {
+        let Some(order) = self.order.as_mut() else {
+            return Ok(());
+        };
+        for frame in order.on_drop(address, uuid) {
+            self.on_output(address, frame).await?;
+        }
+        Ok(())
     }
 
     async fn on_output(
@@ -138,7 +292,205 @@ impl UdpServer {
         frame: ResponseFrame,
     ) -> Result<usize, Error> {
         self.context.encode(frame)?;
-        EventLog::output(&address, &self.context.output);
+        self.context.log_output(&address);
         self.socket.send_to(&self.context.output, address).await
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn buffer_size() {
+        let settings = Settings {
+            address: TransportAddress::Udp("127.0.0.1:0".to_owned()),
+            udp_buffer: Some(1024),
+            ..Default::default()
+        };
+        let (server, _) = UdpServer::bind(settings, SlaveCodec::new_udp())
+            .await
+            .unwrap();
+        assert_eq!(server.buffer_size, 1024);
+        assert!(server.context.input.capacity() >= 1024);
+
+        let settings = Settings {
+            address: TransportAddress::Udp("127.0.0.1:0".to_owned()),
+            udp_buffer: Some(MBAP_HEADER_LEN + 1),
+            ..Default::default()
+        };
+        let res = UdpServer::build(settings).await;
+        assert!(matches!(res, Err(TransportError::InvalidSettings(_))));
+
+        let settings = Settings {
+            address: TransportAddress::Udp("127.0.0.1:0".to_owned()),
+            queue_depth: Some(0),
+            ..Default::default()
+        };
+        let res = UdpServer::build(settings).await;
+        assert!(matches!(res, Err(TransportError::InvalidSettings(_))));
+    }
+
+    // two requests to a server that holds one
+    async fn fill_queue(policy: QueuePolicy) -> (UdpServer, Handler, UdpSocket) {
+        let settings = Settings {
+            address: TransportAddress::Udp("127.0.0.1:0".to_owned()),
+            udp_queue_policy: policy,
+            ..Default::default()
+        };
+        let (mut server, handler) = UdpServer::bind(settings, SlaveCodec::new_udp())
+            .await
+            .unwrap();
+        server.queue = FixedQueue::new(1);
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = client.local_addr().unwrap();
+        for id in [1, 2] {
+            let pdu = RequestPdu::read_holding_registers(0x0, id);
+            let frame = RequestFrame::from_parts(id, 0x1, pdu);
+            server.on_request(address, frame).await.unwrap();
+        }
+        (server, handler, client)
+    }
+
+    #[tokio::test]
+    async fn queue_drop_oldest() {
+        let (mut server, mut handler, _) = fill_queue(QueuePolicy::DropOldest).await;
+        let first = handler.request_rx.recv().await.unwrap();
+        let second = handler.request_rx.recv().await.unwrap();
+        assert_eq!(server.queue.len(), 1);
+        assert!(server
+            .queue
+            .find_if(|info| info.uuid == first.uuid)
+            .is_none());
+        assert!(server
+            .queue
+            .take_if(|info| info.uuid == second.uuid)
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn queue_reject() {
+        let (mut server, mut handler, client) = fill_queue(QueuePolicy::Reject).await;
+        let first = handler.request_rx.recv().await.unwrap();
+        assert!(handler.request_rx.try_recv().is_err());
+        assert!(server
+            .queue
+            .take_if(|info| info.uuid == first.uuid)
+            .is_some());
+
+        let mut buffer = [0u8; 256];
+        let size = client.recv(&mut buffer).await.unwrap();
+        assert_eq!(
+            &buffer[..size],
+            &[0x0, 0x2, 0x0, 0x0, 0x0, 0x3, 0x1, 0x83, 0x6]
+        );
+    }
+
+    #[tokio::test]
+    async fn queue_depth() {
+        let settings = Settings {
+            address: TransportAddress::Udp("127.0.0.1:0".to_owned()),
+            queue_depth: Some(2),
+            ..Default::default()
+        };
+        let (mut server, mut handler) = UdpServer::bind(settings, SlaveCodec::new_udp())
+            .await
+            .unwrap();
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = client.local_addr().unwrap();
+        for id in [1, 2, 3] {
+            let pdu = RequestPdu::read_holding_registers(0x0, id);
+            let frame = RequestFrame::from_parts(id, 0x1, pdu);
+            server.on_request(address, frame).await.unwrap();
+        }
+
+        // the third one is answered instead of being buffered
+        let mut buffer = [0u8; 256];
+        let size = client.recv(&mut buffer).await.unwrap();
+        assert_eq!(
+            &buffer[..size],
+            &[0x0, 0x3, 0x0, 0x0, 0x0, 0x3, 0x1, 0x83, 0x6]
+        );
+        assert!(handler.request_rx.try_recv().is_ok());
+        assert!(handler.request_rx.try_recv().is_ok());
+        assert!(handler.request_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn ordered_responses() {
+        let settings = Settings {
+            address: TransportAddress::Udp("127.0.0.1:15228".to_owned()),
+            ordered_responses: true,
+            ..Default::default()
+        };
+        let mut handler = UdpServer::build(settings).await.unwrap();
+
+        // answer the second request first
+        tokio::spawn(async move {
+            let first = handler.request_rx.recv().await.unwrap();
+            let second = handler.request_rx.recv().await.unwrap();
+            let pdu = ResponsePdu::write_single_register(0x2, 0x2);
+            Response::make(second, pdu).send().unwrap();
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            let pdu = ResponsePdu::write_single_register(0x1, 0x1);
+            Response::make(first, pdu).send().unwrap();
+        });
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        socket.connect("127.0.0.1:15228").await.unwrap();
+        for id in [1u8, 2] {
+            let request = [0x0, id, 0x0, 0x0, 0x0, 0x6, 0x1, 0x6, 0x0, id, 0x0, id];
+            socket.send(&request).await.unwrap();
+        }
+
+        for id in [1u8, 2] {
+            let mut buffer = [0u8; 256];
+            let size = socket.recv(&mut buffer).await.unwrap();
+            assert_eq!(
+                &buffer[..size],
+                &[0x0, id, 0x0, 0x0, 0x0, 0x6, 0x1, 0x6, 0x0, id, 0x0, id]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn ordered_responses_dropped() {
+        let settings = Settings {
+            address: TransportAddress::Udp("127.0.0.1:15229".to_owned()),
+            ordered_responses: true,
+            ..Default::default()
+        };
+        let mut handler = UdpServer::build(settings).await.unwrap();
+
+        // the first request is never answered
+        tokio::spawn(async move {
+            let first = handler.request_rx.recv().await.unwrap();
+            let second = handler.request_rx.recv().await.unwrap();
+            let pdu = ResponsePdu::write_single_register(0x2, 0x2);
+            Response::make(second, pdu).send().unwrap();
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            drop(first);
+            handler
+        });
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        socket.connect("127.0.0.1:15229").await.unwrap();
+        for id in [1u8, 2] {
+            let request = [0x0, id, 0x0, 0x0, 0x0, 0x6, 0x1, 0x6, 0x0, id, 0x0, id];
+            socket.send(&request).await.unwrap();
+        }
+
+        let mut buffer = [0u8; 256];
+        let size = tokio::time::timeout(Duration::from_secs(1), socket.recv(&mut buffer))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            &buffer[..size],
+            &[0x0, 0x2, 0x0, 0x0, 0x0, 0x6, 0x1, 0x6, 0x0, 0x2, 0x0, 0x2]
+        );
+    }
+}
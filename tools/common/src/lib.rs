@@ -0,0 +1,57 @@
+//! Helpers shared by the slave tools
+use modbus::frame::prelude::*;
+use modbus::transport::prelude::*;
+use std::env;
+use std::str::FromStr;
+
+/// Answer to a request the tool doesn't support: IllegalFunction with the
+/// function code of the request
+pub fn default_exception(pdu: &RequestPdu) -> ResponsePdu {
+    ResponsePdu::exception(pdu.func().unwrap_or(0), ExceptionCode::IllegalFunction)
+}
+
+// --bytes-log=<always|error|compact>
+fn parse_bytes_log(arg: &str) -> Option<BytesLog> {
+    BytesLog::from_str(arg.strip_prefix("--bytes-log=")?).ok()
+}
+
+/// Raw bytes logging mode from the command line. Always by default
+pub fn bytes_log() -> BytesLog {
+    env::args()
+        .skip(1)
+        .find_map(|arg| parse_bytes_log(&arg))
+        .unwrap_or(BytesLog::Always)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use modbus::codec::pduext::decode_response_pdu;
+    use modbus::data::prelude::*;
+
+    #[test]
+    fn raw_request() {
+        let pdu = RequestPdu::raw(0x41, Data::raw(&[0x1, 0x2]));
+        let response = default_exception(&pdu);
+        assert_eq!(
+            response,
+            ResponsePdu::exception(0x41, ExceptionCode::IllegalFunction)
+        );
+        // 0x80|func on the wire
+        assert_eq!(decode_response_pdu(&[0xC1, 0x01]), Ok(Some(response)));
+    }
+
+    #[test]
+    fn bytes_log_arg() {
+        assert_eq!(
+            parse_bytes_log("--bytes-log=error"),
+            Some(BytesLog::OnError)
+        );
+        assert_eq!(
+            parse_bytes_log("--bytes-log=compact"),
+            Some(BytesLog::Compact)
+        );
+        assert_eq!(parse_bytes_log("--bytes-log=x"), None);
+        assert_eq!(parse_bytes_log("--truncate=1"), None);
+    }
+}
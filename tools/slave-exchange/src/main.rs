@@ -1,193 +1,32 @@
+mod memory;
+
+use common::bytes_log;
 use env_logger::Builder;
-use modbus::data::prelude::*;
-use modbus::frame::prelude::*;
+use memory::{Memory, ReadPolicy, Router, SpaceLimit, WriteOrder};
 use modbus::transport::builder;
 use modbus::transport::prelude::*;
 
-use log::{info, warn, LevelFilter};
+use log::{debug, error, info, warn, LevelFilter};
 use tokio::signal;
 
 use std::env;
+use std::ops::Range;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 
-#[derive(PartialEq, Eq, Hash)]
-struct Address {
-    slave: u8,
-    func: u8,
-    address: u16,
-}
-
-struct Memory {
-    values: std::collections::HashMap<Address, u16>,
-}
-
-impl Memory {
-    fn read_coils(&self, slave: u8, func: u8, address: u16, output: &mut [bool]) -> usize {
-        let count = output.len();
-        for (i, v) in output.iter_mut().enumerate().take(count) {
-            let address = Address {
-                slave,
-                func,
-                address: address + i as u16,
-            };
-
-            *v = self
-                .values
-                .get(&address)
-                .map(|value| *value != 0)
-                .unwrap_or(false);
-        }
-        count
-    }
-
-    fn read_registers(&self, slave: u8, func: u8, address: u16, output: &mut [u16]) -> usize {
-        let count = output.len();
-        for (i, v) in output.iter_mut().enumerate().take(count) {
-            let address = Address {
-                slave,
-                func,
-                address: address + i as u16,
-            };
-
-            *v = *self.values.get(&address).unwrap_or(&0);
-        }
-        count
-    }
-
-    fn write_coils(&mut self, slave: u8, func: u8, address: u16, input: &[bool]) -> usize {
-        let count = input.len();
-        for (i, v) in input.iter().enumerate().take(count) {
-            let address = Address {
-                slave,
-                func,
-                address: address + i as u16,
-            };
-            self.values.insert(address, *v as u16);
-        }
-        count
-    }
-
-    fn write_registers(&mut self, slave: u8, func: u8, address: u16, input: &[u16]) -> usize {
-        let count = input.len();
-        for (i, v) in input.iter().enumerate().take(count) {
-            let address = Address {
-                slave,
-                func,
-                address: address + i as u16,
-            };
-            self.values.insert(address, *v);
-        }
-        count
-    }
-
-    pub fn process(&mut self, request: Request) -> Response {
-        let slave = request.slave;
-        let func = request.pdu.func().unwrap();
-        let mut coils = [false; MAX_NCOILS];
-        let mut regs = [0u16; MAX_NREGS];
-        let pdu = match &request.pdu {
-            RequestPdu::ReadCoils { nobjs, address } => {
-                let res = self.read_coils(slave, func, *address, &mut coils[..*nobjs as usize]);
-                ResponsePdu::ReadCoils {
-                    nobjs: *nobjs,
-                    data: Data::coils(&coils[..res]),
-                }
-            }
-            RequestPdu::ReadDiscreteInputs { nobjs, address } => {
-                let res = self.read_coils(slave, func, *address, &mut coils[..*nobjs as usize]);
-                ResponsePdu::ReadDiscreteInputs {
-                    nobjs: *nobjs,
-                    data: Data::coils(&coils[..res]),
-                }
-            }
-
-            RequestPdu::ReadHoldingRegisters { nobjs, address } => {
-                let res = self.read_registers(slave, func, *address, &mut regs[..*nobjs as usize]);
-                ResponsePdu::ReadHoldingRegisters {
-                    nobjs: *nobjs,
-                    data: Data::registers(&regs[..res]),
-                }
-            }
-
-            RequestPdu::ReadInputRegisters { nobjs, address } => {
-                let res = self.read_registers(slave, func, *address, &mut regs[..*nobjs as usize]);
-                ResponsePdu::ReadInputRegisters {
-                    nobjs: *nobjs,
-                    data: Data::registers(&regs[..res]),
-                }
-            }
-
-            RequestPdu::WriteSingleCoil { address, value } => {
-                self.write_coils(slave, 0x1, *address, &[*value]);
-                ResponsePdu::WriteSingleCoil {
-                    address: *address,
-                    value: *value,
-                }
-            }
-
-            RequestPdu::WriteSingleRegister { address, value } => {
-                self.write_registers(slave, 0x3, *address, &[*value]);
-                ResponsePdu::WriteSingleRegister {
-                    address: *address,
-                    value: *value,
-                }
-            }
-
-            RequestPdu::WriteMultipleCoils {
-                address,
-                nobjs,
-                data,
-            } => {
-                let count = *nobjs as usize;
-                for (i, value) in coils.iter_mut().take(count).enumerate() {
-                    *value = data.get_bit(i).unwrap();
-                }
-                self.write_coils(slave, 0x1, *address, &coils[..count]);
-                ResponsePdu::WriteMultipleCoils {
-                    address: *address,
-                    nobjs: *nobjs,
-                }
-            }
-
-            RequestPdu::WriteMultipleRegisters {
-                address,
-                nobjs,
-                data,
-            } => {
-                let count = *nobjs as usize;
-                for (i, value) in regs.iter_mut().take(count).enumerate() {
-                    *value = data.get_u16(i).unwrap();
-                }
-                self.write_registers(slave, 0x3, *address, &regs[..count]);
-                ResponsePdu::WriteMultipleRegisters {
-                    address: *address,
-                    nobjs: *nobjs,
-                }
-            }
-
-            _ => ResponsePdu::Exception {
-                function: func,
-                code: ExceptionCode::IllegalFunction,
-            },
-        };
-
-        Response::make(request, pdu)
-    }
-
-    pub fn new() -> Memory {
-        Memory {
-            values: std::collections::HashMap::new(),
-        }
-    }
-}
-
 fn usage() {
     println!(
-        r#"slave-exchange [addresses]
+        r#"slave-exchange [--unset=policy] [--coils=limit] [--ranges=ranges] [--slaves=ids] [--respond-first] [--preset=file] [--bytes-log=mode] [addresses]
 
 Parameters:
     addresses - One or more addresses on which application should work
+    --unset - answer for addresses that were never written. Values [zero,exception,<number>]. zero by default
+    --coils - size of coils/discrete inputs space and what to do on reads past its end. Format <size>[:clamp|:exception]. Unlimited by default
+    --ranges - valid addresses of coils, holding and input registers. Format <start>-<end>,<start>-<end>,<start>-<end>, end is exclusive. Requests outside get IllegalDataAddress. All addresses are valid by default
+    --slaves - comma separated slave ids, each with its own memory. Other ids get GatewayTargetDeviceFailedToRespond. All ids share one memory by default
+    --respond-first - answer write requests before applying them
+    --preset - CSV file with initial values. Rows are slave,func,address,value where func is 1-4
+    --bytes-log - how raw bytes are logged. Values [always,error,compact]. always (hex dump at trace level) by default
 
 Env. variables:
     RUST_LOG - changes output verbosity. Values [error,warn,info,debug,trace]. info by default
@@ -200,6 +39,8 @@ Examples:
     slave-exchange tcp:0.0.0.0:8888 - run app on port 8888. TCP mode.
 
     slave-exchange tcp:0.0.0.0:1502 udp:0.0.0.0:1502 serial:/dev/ttyUSB0:9600-8-N-1 - run app on TCP/UDP ports #1502 and serial port /dev/ttyUSB0
+
+    slave-exchange ascii:/dev/ttyUSB0:9600-7-E-1 - run app on serial port /dev/ttyUSB0. ASCII mode.
     "#
     );
 }
@@ -207,7 +48,11 @@ Examples:
 fn read_args() -> Vec<Settings> {
     env::args().skip(1).fold(Vec::new(), |mut acc, rec| {
         if let Ok(address) = TransportAddress::from_str(&rec) {
-            let settings = Settings { address };
+            let settings = Settings {
+                address,
+                bytes_log: bytes_log(),
+                ..Default::default()
+            };
             acc.push(settings);
         }
         acc
@@ -230,8 +75,98 @@ fn init_logger() {
     builder.init();
 }
 
-fn init_memory() -> Arc<Mutex<Memory>> {
-    Arc::new(Mutex::new(Memory::new()))
+fn read_policy() -> ReadPolicy {
+    env::args()
+        .skip(1)
+        .find_map(|arg| {
+            arg.strip_prefix("--unset=")
+                .and_then(|policy| ReadPolicy::from_str(policy).ok())
+        })
+        .unwrap_or(ReadPolicy::ReturnZero)
+}
+
+fn coils_limit() -> Option<SpaceLimit> {
+    env::args().skip(1).find_map(|arg| {
+        arg.strip_prefix("--coils=")
+            .and_then(|limit| SpaceLimit::from_str(limit).ok())
+    })
+}
+
+// <start>-<end>, end is exclusive
+fn parse_range(s: &str) -> Option<Range<u16>> {
+    let (start, end) = s.split_once('-')?;
+    Some(u16::from_str(start).ok()?..u16::from_str(end).ok()?)
+}
+
+fn ranges() -> Option<(Range<u16>, Range<u16>, Range<u16>)> {
+    env::args().skip(1).find_map(|arg| {
+        let ranges: Vec<_> = arg
+            .strip_prefix("--ranges=")?
+            .split(',')
+            .map(parse_range)
+            .collect::<Option<_>>()?;
+        match ranges.as_slice() {
+            [coils, holding, input] => Some((coils.clone(), holding.clone(), input.clone())),
+            _ => None,
+        }
+    })
+}
+
+fn slaves() -> Vec<u8> {
+    env::args()
+        .skip(1)
+        .find_map(|arg| {
+            arg.strip_prefix("--slaves=").map(|ids| {
+                ids.split(',')
+                    .filter_map(|id| u8::from_str(id).ok())
+                    .collect()
+            })
+        })
+        .unwrap_or_default()
+}
+
+fn write_order() -> WriteOrder {
+    if env::args().skip(1).any(|arg| arg == "--respond-first") {
+        WriteOrder::RespondThenApply
+    } else {
+        WriteOrder::ApplyThenRespond
+    }
+}
+
+fn preset() -> Option<String> {
+    env::args()
+        .skip(1)
+        .find_map(|arg| arg.strip_prefix("--preset=").map(str::to_owned))
+}
+
+fn init_memory() -> Memory {
+    let mut memory = Memory::with_policy(read_policy()).with_write_order(write_order());
+    if let Some(limit) = coils_limit() {
+        memory = memory.with_coils_limit(limit);
+    }
+    if let Some((coils, holding, input)) = ranges() {
+        memory = memory.with_ranges(coils, holding, input);
+    }
+    if let Some(path) = preset() {
+        match memory.load_csv(&path) {
+            Ok(count) => info!("loaded {} values from {}", count, path),
+            Err(err) => error!("can't load {}: {}", path, err),
+        }
+    }
+    memory.on_write(|event| debug!("{:?}", event));
+    memory
+}
+
+fn init_router() -> Arc<Mutex<Router>> {
+    let slaves = slaves();
+    let router = if slaves.is_empty() {
+        Router::default().with_fallback(init_memory())
+    } else {
+        slaves.into_iter().fold(Router::default(), |router, slave| {
+            router.with_slave(slave, init_memory())
+        })
+    };
+    Arc::new(Mutex::new(router))
 }
 
 #[tokio::main]
@@ -243,14 +178,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     if settings.is_empty() {
         usage();
     } else {
-        let memory = init_memory();
+        let router = init_router();
         for record in settings {
-            let local = memory.clone();
+            let local = router.clone();
+            let address = record.address.get().to_owned();
             builder::build_slave(record, move |request| {
                 let mut locked = local.lock().unwrap();
-                let _ = locked.process(request).send().map_err(|e| warn!("{:?}", e));
+                let _ = locked.serve(request).map_err(|e| warn!("{:?}", e));
             })
-            .await?;
+            .await
+            .inspect_err(|err| error!("can't start {}: {}", address, err))?;
         }
         wait_ctrl_c().await;
     }
@@ -0,0 +1,1042 @@
+use common::default_exception;
+use modbus::data::prelude::*;
+use modbus::frame::prelude::*;
+use modbus::transport::prelude::*;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::ops::Range;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(PartialEq, Eq, Hash)]
+struct Address {
+    slave: u8,
+    func: u8,
+    address: u16,
+}
+
+/// What to answer when reading an address that was never written
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReadPolicy {
+    ReturnZero,
+    ReturnDefault(u16),
+    Exception(ExceptionCode),
+}
+
+impl FromStr for ReadPolicy {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zero" => Ok(ReadPolicy::ReturnZero),
+            "exception" => Ok(ReadPolicy::Exception(ExceptionCode::IllegalDataAddress)),
+            value => u16::from_str(value)
+                .map(ReadPolicy::ReturnDefault)
+                .map_err(|_| ()),
+        }
+    }
+}
+
+/// What to do with reads that go past the end of the configured space
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverrunPolicy {
+    /// return fewer objects
+    Clamp,
+    /// answer with IllegalDataAddress
+    Exception,
+}
+
+/// Size of the object space and how to handle reads beyond it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpaceLimit {
+    pub size: u16,
+    pub overrun: OverrunPolicy,
+}
+
+impl SpaceLimit {
+    /// Number of objects that could be read from the address
+    fn count(&self, address: u16, nobjs: u16) -> Result<u16, ExceptionCode> {
+        let available = self.size.saturating_sub(address);
+        match self.overrun {
+            _ if available == 0 => Err(ExceptionCode::IllegalDataAddress),
+            OverrunPolicy::Clamp => Ok(nobjs.min(available)),
+            OverrunPolicy::Exception if nobjs <= available => Ok(nobjs),
+            OverrunPolicy::Exception => Err(ExceptionCode::IllegalDataAddress),
+        }
+    }
+}
+
+impl FromStr for SpaceLimit {
+    type Err = ();
+    /// <size>[:clamp|:exception]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (size, overrun) = s.split_once(':').unwrap_or((s, "clamp"));
+        let overrun = match overrun {
+            "clamp" => OverrunPolicy::Clamp,
+            "exception" => OverrunPolicy::Exception,
+            _ => return Err(()),
+        };
+        let size = u16::from_str(size).map_err(|_| ())?;
+        Ok(SpaceLimit { size, overrun })
+    }
+}
+
+/// Addresses that exist in each object space. Coils also cover discrete
+/// inputs
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Ranges {
+    coils: Range<u16>,
+    holding: Range<u16>,
+    input: Range<u16>,
+}
+
+impl Ranges {
+    /// IllegalDataAddress if the request touches an address outside of its
+    /// space
+    fn check(&self, pdu: &RequestPdu) -> Result<(), ExceptionCode> {
+        let (range, address, nobjs) = match pdu {
+            RequestPdu::ReadCoils { address, nobjs }
+            | RequestPdu::ReadDiscreteInputs { address, nobjs }
+            | RequestPdu::WriteMultipleCoils { address, nobjs, .. } => {
+                (&self.coils, *address, *nobjs)
+            }
+            RequestPdu::WriteSingleCoil { address, .. } => (&self.coils, *address, 1),
+            RequestPdu::ReadHoldingRegisters { address, nobjs }
+            | RequestPdu::WriteMultipleRegisters { address, nobjs, .. } => {
+                (&self.holding, *address, *nobjs)
+            }
+            RequestPdu::WriteSingleRegister { address, .. }
+            | RequestPdu::MaskWriteRegister { address, .. } => (&self.holding, *address, 1),
+            RequestPdu::ReadInputRegisters { address, nobjs } => (&self.input, *address, *nobjs),
+            _ => return Ok(()),
+        };
+        let end = address as u32 + nobjs as u32;
+        if address >= range.start && end <= range.end as u32 {
+            Ok(())
+        } else {
+            Err(ExceptionCode::IllegalDataAddress)
+        }
+    }
+}
+
+/// When to answer write requests
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WriteOrder {
+    /// a read after the response always sees the written values
+    ApplyThenRespond,
+    /// answer first for lower latency. The write hook runs after the answer
+    /// is sent, so a slow one (e.g. forwarding writes to a device) doesn't
+    /// delay it. A read that races with the write may see old values
+    RespondThenApply,
+}
+
+/// Kind of objects affected by a write
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Space {
+    Coils,
+    HoldingRegisters,
+}
+
+impl Space {
+    fn func(&self) -> u8 {
+        match self {
+            Space::Coils => 0x1,
+            Space::HoldingRegisters => 0x3,
+        }
+    }
+}
+
+/// Description of an applied write. Coils are reported as 0/1
+#[derive(Debug, PartialEq, Eq)]
+pub struct WriteEvent<'a> {
+    pub slave: u8,
+    pub space: Space,
+    pub address: u16,
+    pub values: &'a [u16],
+}
+
+/// Values of a write request, detached from the request itself
+struct Write {
+    slave: u8,
+    space: Space,
+    address: u16,
+    values: Vec<u16>,
+}
+
+impl Write {
+    /// Values to write and the echo response. None if it's not a write request
+    fn parse(request: &Request) -> Option<(Write, ResponsePdu)> {
+        let (space, address, values, echo) = match &request.pdu {
+            RequestPdu::WriteSingleCoil { address, value } => (
+                Space::Coils,
+                *address,
+                vec![*value as u16],
+                ResponsePdu::WriteSingleCoil {
+                    address: *address,
+                    value: *value,
+                },
+            ),
+
+            RequestPdu::WriteSingleRegister { address, value } => (
+                Space::HoldingRegisters,
+                *address,
+                vec![*value],
+                ResponsePdu::WriteSingleRegister {
+                    address: *address,
+                    value: *value,
+                },
+            ),
+
+            RequestPdu::WriteMultipleCoils {
+                address,
+                nobjs,
+                data,
+            } => (
+                Space::Coils,
+                *address,
+                data.coils_iter(*nobjs).map(u16::from).collect(),
+                ResponsePdu::WriteMultipleCoils {
+                    address: *address,
+                    nobjs: *nobjs,
+                },
+            ),
+
+            RequestPdu::WriteMultipleRegisters {
+                address,
+                nobjs,
+                data,
+            } => (
+                Space::HoldingRegisters,
+                *address,
+                data.registers_iter().collect(),
+                ResponsePdu::WriteMultipleRegisters {
+                    address: *address,
+                    nobjs: *nobjs,
+                },
+            ),
+
+            _ => return None,
+        };
+
+        let write = Write {
+            slave: request.slave,
+            space,
+            address,
+            values,
+        };
+        Some((write, echo))
+    }
+}
+
+/// Result of Mask Write Register (fc22) applied to the current value
+fn mask_register(value: u16, and_mask: u16, or_mask: u16) -> u16 {
+    (value & and_mask) | (or_mask & !and_mask)
+}
+
+type WriteHook = Box<dyn FnMut(&WriteEvent) + Send>;
+
+pub struct Memory {
+    values: std::collections::HashMap<Address, u16>,
+    read_policy: ReadPolicy,
+    coils_limit: Option<SpaceLimit>,
+    ranges: Option<Ranges>,
+    write_order: WriteOrder,
+    on_write: Option<WriteHook>,
+}
+
+impl Memory {
+    fn read_value(&self, address: &Address) -> Result<u16, ExceptionCode> {
+        match (self.values.get(address), self.read_policy) {
+            (Some(value), _) => Ok(*value),
+            (None, ReadPolicy::ReturnZero) => Ok(0),
+            (None, ReadPolicy::ReturnDefault(value)) => Ok(value),
+            (None, ReadPolicy::Exception(code)) => Err(code),
+        }
+    }
+
+    fn check_range(&self, pdu: &RequestPdu) -> Result<(), ExceptionCode> {
+        self.ranges
+            .as_ref()
+            .map_or(Ok(()), |ranges| ranges.check(pdu))
+    }
+
+    fn coils_count(&self, address: u16, nobjs: u16) -> Result<u16, ExceptionCode> {
+        self.coils_limit
+            .map_or(Ok(nobjs), |limit| limit.count(address, nobjs))
+    }
+
+    fn read_coils(
+        &self,
+        slave: u8,
+        func: u8,
+        address: u16,
+        output: &mut [bool],
+    ) -> Result<usize, ExceptionCode> {
+        let count = output.len();
+        for (i, v) in output.iter_mut().enumerate().take(count) {
+            let address = Address {
+                slave,
+                func,
+                address: address + i as u16,
+            };
+
+            *v = self.read_value(&address)? != 0;
+        }
+        Ok(count)
+    }
+
+    fn read_registers(
+        &self,
+        slave: u8,
+        func: u8,
+        address: u16,
+        output: &mut [u16],
+    ) -> Result<usize, ExceptionCode> {
+        let count = output.len();
+        for (i, v) in output.iter_mut().enumerate().take(count) {
+            let address = Address {
+                slave,
+                func,
+                address: address + i as u16,
+            };
+
+            *v = self.read_value(&address)?;
+        }
+        Ok(count)
+    }
+
+    fn apply(&mut self, write: Write) -> usize {
+        let count = write.values.len();
+        for (i, v) in write.values.iter().enumerate() {
+            let address = Address {
+                slave: write.slave,
+                func: write.space.func(),
+                address: write.address + i as u16,
+            };
+            self.values.insert(address, *v);
+        }
+
+        if let Some(hook) = self.on_write.as_mut() {
+            hook(&WriteEvent {
+                slave: write.slave,
+                space: write.space,
+                address: write.address,
+                values: &write.values,
+            });
+        }
+        count
+    }
+
+    fn mask_write(
+        &mut self,
+        slave: u8,
+        address: u16,
+        and_mask: u16,
+        or_mask: u16,
+    ) -> Result<ResponsePdu, ExceptionCode> {
+        let space = Space::HoldingRegisters;
+        let value = self.read_value(&Address {
+            slave,
+            func: space.func(),
+            address,
+        })?;
+        self.apply(Write {
+            slave,
+            space,
+            address,
+            values: vec![mask_register(value, and_mask, or_mask)],
+        });
+        Ok(ResponsePdu::mask_write_register(address, and_mask, or_mask))
+    }
+
+    /// Set a callback that is called after every applied write (fc5/fc6/fc15/fc16/fc22)
+    pub fn on_write(&mut self, hook: impl FnMut(&WriteEvent) + Send + 'static) {
+        self.on_write = Some(Box::new(hook));
+    }
+
+    /// Process the request and send the response respecting the write order
+    pub fn serve(&mut self, request: Request) -> std::io::Result<()> {
+        match self.write_order {
+            WriteOrder::RespondThenApply => match Write::parse(&request) {
+                Some((write, echo)) if self.check_range(&request.pdu).is_ok() => {
+                    let res = Response::make(request, echo).send();
+                    self.apply(write);
+                    res
+                }
+                _ => self.process(request).send(),
+            },
+            WriteOrder::ApplyThenRespond => self.process(request).send(),
+        }
+    }
+
+    pub fn process(&mut self, request: Request) -> Response {
+        let func = request.pdu.func().unwrap();
+        let pdu = self
+            .make_pdu(&request)
+            .unwrap_or_else(|code| ResponsePdu::exception(func, code));
+        Response::make(request, pdu)
+    }
+
+    fn make_pdu(&mut self, request: &Request) -> Result<ResponsePdu, ExceptionCode> {
+        self.check_range(&request.pdu)?;
+
+        if let Some((write, echo)) = Write::parse(request) {
+            self.apply(write);
+            return Ok(echo);
+        }
+
+        if let RequestPdu::MaskWriteRegister {
+            address,
+            and_mask,
+            or_mask,
+        } = request.pdu
+        {
+            return self.mask_write(request.slave, address, and_mask, or_mask);
+        }
+
+        let slave = request.slave;
+        let func = request.pdu.func().unwrap();
+        let mut coils = [false; MAX_NCOILS];
+        let mut regs = [0u16; MAX_NREGS];
+        let pdu = match &request.pdu {
+            RequestPdu::ReadCoils { nobjs, address } => {
+                let nobjs = self.coils_count(*address, *nobjs)?;
+                let res = self.read_coils(slave, func, *address, &mut coils[..nobjs as usize])?;
+                ResponsePdu::ReadCoils {
+                    nobjs,
+                    data: Data::coils(&coils[..res]),
+                }
+            }
+            RequestPdu::ReadDiscreteInputs { nobjs, address } => {
+                let nobjs = self.coils_count(*address, *nobjs)?;
+                let res = self.read_coils(slave, func, *address, &mut coils[..nobjs as usize])?;
+                ResponsePdu::ReadDiscreteInputs {
+                    nobjs,
+                    data: Data::coils(&coils[..res]),
+                }
+            }
+
+            RequestPdu::ReadHoldingRegisters { nobjs, address } => {
+                let res =
+                    self.read_registers(slave, func, *address, &mut regs[..*nobjs as usize])?;
+                ResponsePdu::ReadHoldingRegisters {
+                    nobjs: *nobjs,
+                    data: Data::registers(&regs[..res]),
+                }
+            }
+
+            RequestPdu::ReadInputRegisters { nobjs, address } => {
+                let res =
+                    self.read_registers(slave, func, *address, &mut regs[..*nobjs as usize])?;
+                ResponsePdu::ReadInputRegisters {
+                    nobjs: *nobjs,
+                    data: Data::registers(&regs[..res]),
+                }
+            }
+
+            // Return Query Data (loopback)
+            RequestPdu::Diagnostic {
+                sub_function: 0x0,
+                data,
+            } => ResponsePdu::diagnostic(0x0, *data),
+
+            _ => default_exception(&request.pdu),
+        };
+
+        Ok(pdu)
+    }
+
+    pub fn with_policy(read_policy: ReadPolicy) -> Memory {
+        Memory {
+            values: std::collections::HashMap::new(),
+            read_policy,
+            coils_limit: None,
+            ranges: None,
+            write_order: WriteOrder::ApplyThenRespond,
+            on_write: None,
+        }
+    }
+
+    /// Pre-populate values from `slave,func,address,value` rows. func is
+    /// 1-4. Invalid rows are logged and skipped. Returns number of loaded rows
+    pub fn load_csv(&mut self, path: impl AsRef<Path>) -> std::io::Result<usize> {
+        let file = File::open(path)?;
+        Ok(self.load_rows(BufReader::new(file)))
+    }
+
+    fn load_rows(&mut self, input: impl BufRead) -> usize {
+        let mut loaded = 0;
+        for (idx, line) in input.lines().map_while(Result::ok).enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match parse_row(line) {
+                Some((address, value)) => {
+                    self.values.insert(address, value);
+                    loaded += 1;
+                }
+                None => log::warn!("preset line {}: invalid row '{}'", idx + 1, line),
+            }
+        }
+        loaded
+    }
+
+    pub fn with_write_order(mut self, write_order: WriteOrder) -> Memory {
+        self.write_order = write_order;
+        self
+    }
+
+    /// Limit the coils/discrete inputs space. Unlimited by default
+    pub fn with_coils_limit(mut self, limit: SpaceLimit) -> Memory {
+        self.coils_limit = Some(limit);
+        self
+    }
+
+    /// Answer IllegalDataAddress to reads and writes outside of the ranges.
+    /// All addresses are valid by default
+    pub fn with_ranges(
+        mut self,
+        coils: Range<u16>,
+        holding: Range<u16>,
+        input: Range<u16>,
+    ) -> Memory {
+        self.ranges = Some(Ranges {
+            coils,
+            holding,
+            input,
+        });
+        self
+    }
+}
+
+// decimal or 0x prefixed hex
+fn parse_number(s: &str) -> Option<u16> {
+    let s = s.trim();
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => u16::from_str(s).ok(),
+    }
+}
+
+fn parse_row(line: &str) -> Option<(Address, u16)> {
+    let fields: Vec<&str> = line.split(',').collect();
+    let [slave, func, address, value] = fields.as_slice() else {
+        return None;
+    };
+    let func = parse_number(func).filter(|func| (0x1..=0x4).contains(func))? as u8;
+    let value = parse_number(value)?;
+    // coils are stored as 0/1
+    let value = if func <= 0x2 {
+        (value != 0) as u16
+    } else {
+        value
+    };
+    let address = Address {
+        slave: u8::try_from(parse_number(slave)?).ok()?,
+        func,
+        address: parse_number(address)?,
+    };
+    Some((address, value))
+}
+
+/// Dispatches requests to per-slave memory maps
+#[derive(Default)]
+pub struct Router {
+    maps: std::collections::HashMap<u8, Memory>,
+    fallback: Option<Memory>,
+}
+
+impl Router {
+    /// Serve the slave id with its own memory map
+    pub fn with_slave(mut self, slave: u8, memory: Memory) -> Router {
+        self.maps.insert(slave, memory);
+        self
+    }
+
+    /// Serve all unconfigured slave ids with the memory map. Without it,
+    /// such requests get GatewayTargetDeviceFailedToRespond
+    pub fn with_fallback(mut self, memory: Memory) -> Router {
+        self.fallback = Some(memory);
+        self
+    }
+
+    fn memory(&mut self, slave: u8) -> Option<&mut Memory> {
+        match self.maps.get_mut(&slave) {
+            Some(memory) => Some(memory),
+            None => self.fallback.as_mut(),
+        }
+    }
+
+    pub fn serve(&mut self, request: Request) -> std::io::Result<()> {
+        if request.is_broadcast() && !self.maps.is_empty() {
+            return self.broadcast(request);
+        }
+
+        if let Some(memory) = self.memory(request.slave) {
+            memory.serve(request)
+        } else {
+            let func = request.pdu.func().unwrap();
+            let code = ExceptionCode::GatewayTargetDeciveFailedToRespond;
+            Response::make(request, ResponsePdu::exception(func, code)).send()
+        }
+    }
+
+    // every memory map acts on broadcasts. Serial transports drop the answer
+    fn broadcast(&mut self, mut request: Request) -> std::io::Result<()> {
+        let func = request.pdu.func().unwrap();
+        let mut result = Err(ExceptionCode::GatewayTargetDeciveFailedToRespond);
+        // memory maps are keyed by slave id, so act as each unit in turn
+        for (slave, memory) in self.maps.iter_mut() {
+            request.slave = *slave;
+            result = memory.make_pdu(&request);
+        }
+        request.slave = 0;
+        if let Some(memory) = self.fallback.as_mut() {
+            result = memory.make_pdu(&request);
+        }
+        let pdu = result.unwrap_or_else(|code| ResponsePdu::exception(func, code));
+        Response::make(request, pdu).send()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use uuid::Uuid;
+
+    fn request(slave: u8, pdu: RequestPdu) -> Request {
+        Request {
+            uuid: Uuid::new_v4(),
+            slave,
+            pdu,
+            deadline: None,
+            response_tx: None,
+        }
+    }
+
+    fn read_unset(policy: ReadPolicy) -> (ResponsePdu, ResponsePdu) {
+        let mut memory = Memory::with_policy(policy);
+        let coils = memory.process(request(1, RequestPdu::read_coils(0x10, 2)));
+        let regs = memory.process(request(1, RequestPdu::read_holding_registers(0x10, 2)));
+        (coils.pdu, regs.pdu)
+    }
+
+    #[test]
+    fn load_preset() {
+        let csv = "# slave,func,address,value\n\
+                   1,3,0x10,0x1234\n\
+                   1,1,0x10,5\n\
+                   1,4,0x10,not-a-number\n\
+                   1,6,0x10,1\n\
+                   1,3\n\
+                   \n\
+                   2,4,0x0,42\n";
+        let mut memory = Memory::with_policy(ReadPolicy::ReturnZero);
+        assert_eq!(memory.load_rows(csv.as_bytes()), 3);
+
+        let regs = memory.process(request(1, RequestPdu::read_holding_registers(0x10, 1)));
+        assert_eq!(
+            regs.pdu,
+            ResponsePdu::read_holding_registers([0x1234u16].as_slice())
+        );
+        let coils = memory.process(request(1, RequestPdu::read_coils(0x10, 2)));
+        assert_eq!(coils.pdu, ResponsePdu::read_coils([true, false].as_slice()));
+        let inputs = memory.process(request(1, RequestPdu::read_input_registers(0x10, 1)));
+        assert_eq!(
+            inputs.pdu,
+            ResponsePdu::read_input_registers([0u16].as_slice())
+        );
+        let inputs = memory.process(request(2, RequestPdu::read_input_registers(0x0, 1)));
+        assert_eq!(
+            inputs.pdu,
+            ResponsePdu::read_input_registers([42u16].as_slice())
+        );
+    }
+
+    #[test]
+    fn mask_write_register() {
+        // example from the specification
+        assert_eq!(mask_register(0x12, 0xF2, 0x25), 0x17);
+
+        let mut memory = Memory::with_policy(ReadPolicy::ReturnZero);
+        memory.process(request(1, RequestPdu::write_single_register(0x4, 0x12)));
+        let response = memory.process(request(1, RequestPdu::mask_write_register(0x4, 0xF2, 0x25)));
+        assert_eq!(
+            response.pdu,
+            ResponsePdu::mask_write_register(0x4, 0xF2, 0x25)
+        );
+        let response = memory.process(request(1, RequestPdu::read_holding_registers(0x4, 1)));
+        assert_eq!(
+            response.pdu,
+            ResponsePdu::read_holding_registers([0x17u16].as_slice())
+        );
+    }
+
+    #[test]
+    fn diagnostic_loopback() {
+        let mut memory = Memory::with_policy(ReadPolicy::ReturnZero);
+        let response = memory.process(request(1, RequestPdu::diagnostic(0x0, 0xA537)));
+        assert_eq!(response.pdu, ResponsePdu::diagnostic(0x0, 0xA537));
+
+        let response = memory.process(request(1, RequestPdu::diagnostic(0xA, 0x0)));
+        assert_eq!(
+            response.pdu,
+            ResponsePdu::exception(0x8, ExceptionCode::IllegalFunction)
+        );
+    }
+
+    #[test]
+    fn read_unset_zero() {
+        let (coils, regs) = read_unset(ReadPolicy::ReturnZero);
+        assert_eq!(coils, ResponsePdu::read_coils([false, false].as_slice()));
+        assert_eq!(
+            regs,
+            ResponsePdu::read_holding_registers([0u16, 0].as_slice())
+        );
+    }
+
+    #[test]
+    fn read_unset_default() {
+        let (coils, regs) = read_unset(ReadPolicy::ReturnDefault(0xAA));
+        assert_eq!(coils, ResponsePdu::read_coils([true, true].as_slice()));
+        assert_eq!(
+            regs,
+            ResponsePdu::read_holding_registers([0xAAu16, 0xAA].as_slice())
+        );
+    }
+
+    #[test]
+    fn read_unset_exception() {
+        let code = ExceptionCode::IllegalDataAddress;
+        let (coils, regs) = read_unset(ReadPolicy::Exception(code));
+        assert_eq!(coils, ResponsePdu::exception(0x1, code));
+        assert_eq!(regs, ResponsePdu::exception(0x3, code));
+    }
+
+    #[test]
+    fn on_write_register() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut memory = Memory::with_policy(ReadPolicy::ReturnZero);
+        let local = events.clone();
+        memory.on_write(move |event| {
+            local.lock().unwrap().push((
+                event.slave,
+                event.space,
+                event.address,
+                event.values.to_vec(),
+            ))
+        });
+
+        memory.process(request(1, RequestPdu::write_single_register(0x10, 0x1234)));
+        memory.process(request(
+            2,
+            RequestPdu::write_multiple_registers(0x20, [0x1u16, 0x2].as_slice()),
+        ));
+        memory.process(request(
+            3,
+            RequestPdu::write_multiple_coils(0x30, [true, false, true].as_slice()),
+        ));
+        memory.process(request(1, RequestPdu::read_holding_registers(0x10, 1)));
+
+        let events = events.lock().unwrap();
+        assert_eq!(
+            *events,
+            vec![
+                (1, Space::HoldingRegisters, 0x10, vec![0x1234]),
+                (2, Space::HoldingRegisters, 0x20, vec![0x1, 0x2]),
+                (3, Space::Coils, 0x30, vec![1, 0, 1]),
+            ]
+        );
+    }
+
+    fn read_limited(
+        overrun: OverrunPolicy,
+        address: u16,
+        nobjs: u16,
+    ) -> (ResponsePdu, ResponsePdu) {
+        let limit = SpaceLimit { size: 10, overrun };
+        let mut memory = Memory::with_policy(ReadPolicy::ReturnDefault(1)).with_coils_limit(limit);
+        let coils = memory.process(request(1, RequestPdu::read_coils(address, nobjs)));
+        let inputs = memory.process(request(1, RequestPdu::read_discrete_inputs(address, nobjs)));
+        (coils.pdu, inputs.pdu)
+    }
+
+    #[test]
+    fn read_coils_clamp() {
+        let (coils, inputs) = read_limited(OverrunPolicy::Clamp, 4, 8);
+        assert_eq!(coils, ResponsePdu::read_coils([true; 6].as_slice()));
+        assert_eq!(
+            inputs,
+            ResponsePdu::read_discrete_inputs([true; 6].as_slice())
+        );
+
+        let (coils, _) = read_limited(OverrunPolicy::Clamp, 0, 10);
+        assert_eq!(coils, ResponsePdu::read_coils([true; 10].as_slice()));
+    }
+
+    #[test]
+    fn read_coils_overrun_exception() {
+        let code = ExceptionCode::IllegalDataAddress;
+        let (coils, inputs) = read_limited(OverrunPolicy::Exception, 4, 8);
+        assert_eq!(coils, ResponsePdu::exception(0x1, code));
+        assert_eq!(inputs, ResponsePdu::exception(0x2, code));
+
+        let (coils, _) = read_limited(OverrunPolicy::Exception, 4, 6);
+        assert_eq!(coils, ResponsePdu::read_coils([true; 6].as_slice()));
+    }
+
+    fn ranged() -> Memory {
+        Memory::with_policy(ReadPolicy::ReturnZero).with_ranges(0..10, 100..110, 200..210)
+    }
+
+    #[test]
+    fn ranges_in_range() {
+        let mut memory = ranged();
+        let pdu = memory
+            .process(request(1, RequestPdu::write_single_register(100, 7)))
+            .pdu;
+        assert_eq!(pdu, ResponsePdu::write_single_register(100, 7));
+        let pdu = memory
+            .process(request(1, RequestPdu::read_holding_registers(100, 10)))
+            .pdu;
+        let mut regs = [0u16; 10];
+        regs[0] = 7;
+        assert_eq!(pdu, ResponsePdu::read_holding_registers(regs.as_slice()));
+        let pdu = memory
+            .process(request(1, RequestPdu::read_coils(0, 10)))
+            .pdu;
+        assert_eq!(pdu, ResponsePdu::read_coils([false; 10].as_slice()));
+        let pdu = memory
+            .process(request(1, RequestPdu::read_input_registers(209, 1)))
+            .pdu;
+        assert_eq!(pdu, ResponsePdu::read_input_registers([0u16].as_slice()));
+    }
+
+    #[test]
+    fn ranges_out_of_range() {
+        let code = ExceptionCode::IllegalDataAddress;
+        let mut memory = ranged();
+        let requests = [
+            (RequestPdu::write_single_register(99, 7), 0x6),
+            (
+                RequestPdu::write_multiple_registers(105, [1u16; 6].as_slice()),
+                0x10,
+            ),
+            (RequestPdu::read_holding_registers(100, 11), 0x3),
+            (RequestPdu::write_single_coil(10, true), 0x5),
+            (RequestPdu::read_input_registers(0xFFFF, 1), 0x4),
+        ];
+        for (pdu, func) in requests {
+            let response = memory.process(request(1, pdu)).pdu;
+            assert_eq!(response, ResponsePdu::exception(func, code));
+        }
+        // rejected writes are not applied
+        let pdu = memory
+            .process(request(1, RequestPdu::read_holding_registers(105, 5)))
+            .pdu;
+        assert_eq!(
+            pdu,
+            ResponsePdu::read_holding_registers([0u16; 5].as_slice())
+        );
+    }
+
+    #[test]
+    fn read_coils_out_of_space() {
+        let code = ExceptionCode::IllegalDataAddress;
+        let (coils, _) = read_limited(OverrunPolicy::Clamp, 10, 1);
+        assert_eq!(coils, ResponsePdu::exception(0x1, code));
+        let (coils, _) = read_limited(OverrunPolicy::Exception, 10, 1);
+        assert_eq!(coils, ResponsePdu::exception(0x1, code));
+    }
+
+    #[test]
+    fn space_limit_from_str() {
+        let limit = |size, overrun| Ok(SpaceLimit { size, overrun });
+        assert_eq!(SpaceLimit::from_str("16"), limit(16, OverrunPolicy::Clamp));
+        assert_eq!(
+            SpaceLimit::from_str("16:exception"),
+            limit(16, OverrunPolicy::Exception)
+        );
+        assert!(SpaceLimit::from_str("16:unknown").is_err());
+        assert!(SpaceLimit::from_str("x").is_err());
+    }
+
+    fn route(router: &mut Router, slave: u8, pdu: RequestPdu) -> ResponsePdu {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let request = Request {
+            uuid: Uuid::new_v4(),
+            slave,
+            pdu,
+            deadline: None,
+            response_tx: Some(tx),
+        };
+        router.serve(request).unwrap();
+        rx.try_recv().unwrap().pdu
+    }
+
+    #[test]
+    fn router_per_slave() {
+        let mut router = Router::default()
+            .with_slave(1, Memory::with_policy(ReadPolicy::ReturnDefault(1)))
+            .with_slave(2, Memory::with_policy(ReadPolicy::ReturnDefault(2)));
+
+        route(
+            &mut router,
+            1,
+            RequestPdu::write_single_register(0x10, 0x1111),
+        );
+        route(
+            &mut router,
+            2,
+            RequestPdu::write_single_register(0x10, 0x2222),
+        );
+
+        let read = |router: &mut Router, slave| {
+            route(router, slave, RequestPdu::read_holding_registers(0x10, 2))
+        };
+        assert_eq!(
+            read(&mut router, 1),
+            ResponsePdu::read_holding_registers([0x1111u16, 0x1].as_slice())
+        );
+        assert_eq!(
+            read(&mut router, 2),
+            ResponsePdu::read_holding_registers([0x2222u16, 0x2].as_slice())
+        );
+        assert_eq!(
+            read(&mut router, 3),
+            ResponsePdu::exception(0x3, ExceptionCode::GatewayTargetDeciveFailedToRespond)
+        );
+    }
+
+    #[test]
+    fn router_fallback() {
+        let mut router = Router::default()
+            .with_slave(1, Memory::with_policy(ReadPolicy::ReturnDefault(1)))
+            .with_fallback(Memory::with_policy(ReadPolicy::ReturnDefault(7)));
+        let response = route(&mut router, 3, RequestPdu::read_holding_registers(0x10, 1));
+        assert_eq!(
+            response,
+            ResponsePdu::read_holding_registers([0x7u16].as_slice())
+        );
+    }
+
+    #[test]
+    fn router_broadcast() {
+        let mut router = Router::default()
+            .with_slave(1, Memory::with_policy(ReadPolicy::ReturnZero))
+            .with_slave(2, Memory::with_policy(ReadPolicy::ReturnZero));
+        let pdu = RequestPdu::write_multiple_registers(0x10, [0x1234u16].as_slice());
+        route(&mut router, 0, pdu);
+
+        for slave in [1, 2] {
+            assert_eq!(
+                route(
+                    &mut router,
+                    slave,
+                    RequestPdu::read_holding_registers(0x10, 1)
+                ),
+                ResponsePdu::read_holding_registers([0x1234u16].as_slice())
+            );
+        }
+    }
+
+    fn serve_write_read(order: WriteOrder) -> (ResponsePdu, ResponsePdu) {
+        let mut memory = Memory::with_policy(ReadPolicy::ReturnZero).with_write_order(order);
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let request = |pdu| Request {
+            uuid: Uuid::new_v4(),
+            slave: 1,
+            pdu,
+            deadline: None,
+            response_tx: Some(tx.clone()),
+        };
+
+        memory
+            .serve(request(RequestPdu::write_single_register(0x10, 0x1234)))
+            .unwrap();
+        memory
+            .serve(request(RequestPdu::read_holding_registers(0x10, 1)))
+            .unwrap();
+        let write = rx.try_recv().unwrap();
+        let read = rx.try_recv().unwrap();
+        (write.pdu, read.pdu)
+    }
+
+    #[test]
+    fn serve_apply_then_respond() {
+        let (write, read) = serve_write_read(WriteOrder::ApplyThenRespond);
+        assert_eq!(write, ResponsePdu::write_single_register(0x10, 0x1234));
+        assert_eq!(
+            read,
+            ResponsePdu::read_holding_registers([0x1234u16].as_slice())
+        );
+    }
+
+    #[test]
+    fn serve_respond_then_apply() {
+        let (write, read) = serve_write_read(WriteOrder::RespondThenApply);
+        assert_eq!(write, ResponsePdu::write_single_register(0x10, 0x1234));
+        assert_eq!(
+            read,
+            ResponsePdu::read_holding_registers([0x1234u16].as_slice())
+        );
+    }
+
+    // was the answer sent when the write hook ran
+    fn answered_before_hook(order: WriteOrder) -> bool {
+        let mut memory = Memory::with_policy(ReadPolicy::ReturnZero).with_write_order(order);
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let rx = Arc::new(Mutex::new(rx));
+        let answered = Arc::new(Mutex::new(None));
+        {
+            let (rx, answered) = (rx.clone(), answered.clone());
+            memory.on_write(move |_| {
+                *answered.lock().unwrap() = Some(rx.lock().unwrap().try_recv().is_ok());
+            });
+        }
+
+        let request = Request {
+            uuid: Uuid::new_v4(),
+            slave: 1,
+            pdu: RequestPdu::write_single_register(0x10, 0x1234),
+            deadline: None,
+            response_tx: Some(tx),
+        };
+        memory.serve(request).unwrap();
+        let answered = answered.lock().unwrap().unwrap();
+        answered
+    }
+
+    #[test]
+    fn serve_write_order_hook() {
+        assert!(!answered_before_hook(WriteOrder::ApplyThenRespond));
+        assert!(answered_before_hook(WriteOrder::RespondThenApply));
+    }
+
+    #[test]
+    fn read_policy_from_str() {
+        assert_eq!(ReadPolicy::from_str("zero"), Ok(ReadPolicy::ReturnZero));
+        assert_eq!(
+            ReadPolicy::from_str("17"),
+            Ok(ReadPolicy::ReturnDefault(17))
+        );
+        assert_eq!(
+            ReadPolicy::from_str("exception"),
+            Ok(ReadPolicy::Exception(ExceptionCode::IllegalDataAddress))
+        );
+        assert!(ReadPolicy::from_str("unknown").is_err());
+    }
+
+    #[test]
+    fn read_written_exception() {
+        let code = ExceptionCode::IllegalDataAddress;
+        let mut memory = Memory::with_policy(ReadPolicy::Exception(code));
+        memory.process(request(1, RequestPdu::write_single_register(0x10, 0x1234)));
+        let response = memory.process(request(1, RequestPdu::read_holding_registers(0x10, 1)));
+        assert_eq!(
+            response.pdu,
+            ResponsePdu::read_holding_registers([0x1234u16].as_slice())
+        );
+    }
+}
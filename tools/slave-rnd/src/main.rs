@@ -1,15 +1,17 @@
-use modbus::data::prelude::*;
+use common::{bytes_log, default_exception};
+use modbus::data::{prelude::*, MAX_FIFO_COUNT};
 use modbus::frame::exception::Code;
 use modbus::frame::prelude::*;
 use modbus::transport::builder;
 use modbus::transport::prelude::*;
 
 use env_logger::Builder;
-use log::{info, warn, LevelFilter};
+use log::{error, info, warn, LevelFilter};
 use tokio::signal;
 
 use rand::Rng;
 use std::env;
+use std::num::NonZeroUsize;
 use std::str::FromStr;
 
 fn fill_registers(registers: &mut [u16]) {
@@ -24,30 +26,70 @@ fn fill_coils(coils: &mut [bool]) {
     }
 }
 
-fn make_answer(request: Request) -> Response {
+// vendor name, product code and revision
+const DEVICE_ID_OBJECTS: [&str; 3] = ["modbus-tools", "slave-rnd", env!("CARGO_PKG_VERSION")];
+
+fn device_id(read_code: u8, object_id: u8) -> ResponsePdu {
+    let objects = DEVICE_ID_OBJECTS.iter().enumerate();
+    match read_code {
+        // basic, regular and extended stream access. Only basic objects are
+        // available. Unknown object id restarts the stream
+        1..=3 => {
+            let first = if (object_id as usize) < DEVICE_ID_OBJECTS.len() {
+                object_id as usize
+            } else {
+                0
+            };
+            objects
+                .skip(first)
+                .fold(DeviceId::new(read_code), |id, (idx, value)| {
+                    id.with_object(idx as u8, value.as_bytes())
+                })
+                .with_conformity(0x81)
+                .build()
+        }
+        // individual access
+        4 => match DEVICE_ID_OBJECTS.get(object_id as usize) {
+            Some(value) => DeviceId::new(read_code)
+                .with_conformity(0x81)
+                .with_object(object_id, value.as_bytes())
+                .build(),
+            None => ResponsePdu::exception(0x2b, Code::IllegalDataAddress),
+        },
+        _ => ResponsePdu::exception(0x2b, Code::IllegalDataValue),
+    }
+}
+
+// number of objects to return. Truncated answers simulate short responses
+fn count(nobjs: u16, truncate: Option<usize>) -> usize {
+    let nobjs = nobjs as usize;
+    truncate.map_or(nobjs, |max| nobjs.min(max))
+}
+
+fn make_answer(request: Request, truncate: Option<usize>) -> Response {
     let mut registers = [0u16; MAX_NREGS];
     let mut coils = [false; MAX_NCOILS];
     let pdu = match &request.pdu {
         RequestPdu::ReadCoils { nobjs, .. } => {
-            let nobjs = *nobjs as usize;
+            let nobjs = count(*nobjs, truncate);
             fill_coils(&mut coils[0..nobjs]);
             ResponsePdu::read_coils(&coils[0..nobjs])
         }
 
         RequestPdu::ReadDiscreteInputs { nobjs, .. } => {
-            let nobjs = *nobjs as usize;
+            let nobjs = count(*nobjs, truncate);
             fill_coils(&mut coils[0..nobjs]);
             ResponsePdu::read_discrete_inputs(&coils[0..nobjs])
         }
 
         RequestPdu::ReadHoldingRegisters { nobjs, .. } => {
-            let nobjs = *nobjs as usize;
+            let nobjs = count(*nobjs, truncate);
             fill_registers(&mut registers[0..nobjs]);
             ResponsePdu::read_holding_registers(&registers[0..nobjs])
         }
 
         RequestPdu::ReadInputRegisters { nobjs, .. } => {
-            let nobjs = *nobjs as usize;
+            let nobjs = count(*nobjs, truncate);
             fill_registers(&mut registers[0..nobjs]);
             ResponsePdu::read_input_registers(&registers[0..nobjs])
         }
@@ -60,6 +102,18 @@ fn make_answer(request: Request) -> Response {
             ResponsePdu::write_single_register(*address, *value)
         }
 
+        RequestPdu::ReadExceptionStatus => {
+            ResponsePdu::read_exception_status(rand::thread_rng().gen())
+        }
+
+        // Return Query Data (loopback)
+        RequestPdu::Diagnostic {
+            sub_function: 0x0,
+            data,
+        } => ResponsePdu::diagnostic(0x0, *data),
+
+        RequestPdu::Diagnostic { .. } => default_exception(&request.pdu),
+
         RequestPdu::WriteMultipleCoils { address, nobjs, .. } => {
             ResponsePdu::write_multiple_coils(*address, *nobjs)
         }
@@ -68,35 +122,52 @@ fn make_answer(request: Request) -> Response {
             ResponsePdu::write_multiple_registers(*address, *nobjs)
         }
 
-        RequestPdu::EncapsulatedInterfaceTransport { mei_type, data, .. } => {
-            match (mei_type, data.get_u8(0)) {
-                (0xE, Some(0) | Some(1) | Some(2)) => {
-                    ResponsePdu::encapsulated_interface_transport(
-                        *mei_type,
-                        "modbus-imit".as_bytes(),
-                    )
-                }
-                _ => ResponsePdu::exception(0x2b, Code::IllegalDataValue),
-            }
+        RequestPdu::MaskWriteRegister {
+            address,
+            and_mask,
+            or_mask,
+        } => ResponsePdu::mask_write_register(*address, *and_mask, *or_mask),
+
+        RequestPdu::ReadWriteMultipleRegisters { read_nobjs, .. } => {
+            let nobjs = count(*read_nobjs, truncate);
+            fill_registers(&mut registers[0..nobjs]);
+            ResponsePdu::read_write_multiple_registers(&registers[0..nobjs])
+        }
+
+        RequestPdu::ReadFifoQueue { .. } => {
+            let count = rand::thread_rng().gen_range(0..=MAX_FIFO_COUNT);
+            fill_registers(&mut registers[0..count]);
+            ResponsePdu::read_fifo_queue(&registers[0..count])
         }
 
-        RequestPdu::Raw { function, .. } => {
-            ResponsePdu::exception(*function, Code::IllegalFunction)
+        RequestPdu::ReportSlaveId => {
+            // slave id, run indicator (ON) and the name
+            let mut data = vec![request.slave, 0xFF];
+            data.extend_from_slice("modbus-imit".as_bytes());
+            ResponsePdu::report_slave_id(&data)
+        }
+
+        RequestPdu::EncapsulatedInterfaceTransport { .. } => {
+            match request.pdu.device_id_request() {
+                Some((read_code, object_id)) => device_id(read_code, object_id),
+                None => ResponsePdu::exception(0x2b, Code::IllegalDataValue),
+            }
         }
+
+        RequestPdu::Raw { .. } => default_exception(&request.pdu),
     };
 
     Response::make(request, pdu)
 }
 
-fn read_args() -> Option<Settings> {
-    let arg: String = env::args().skip(1).take(1).collect();
-
-    if arg == "--help" || arg == "-h" {
-        println!(
-            r#"slave-rnd [address]
+fn usage() {
+    println!(
+        r#"slave-rnd [address] [--truncate=<n>] [--bytes-log=mode]
 
 Parameters:
     address - optional parameter for binding server socket. 0.0.0.0:502 by default
+    --truncate - return at most n (n > 0) objects in read responses to simulate short answers
+    --bytes-log - how raw bytes are logged. Values [always,error,compact]. always (hex dump at trace level) by default
 
 Env. variables:
     RUST_LOG - changes output verbosity. Values [error,warn,info,debug,trace]. info by default
@@ -111,16 +182,60 @@ Examples:
     slave-rnd udp:0.0.0.0:8888 - run app on port 8888. UDP mode.
 
     slave-rnd serial:/dev/ttyUSB0:19200-8-E-1 - run app on serial port. RTU mode.
+
+    slave-rnd ascii:/dev/ttyUSB0:9600-7-E-1 - run app on serial port. ASCII mode.
+
+    slave-rnd tcp:0.0.0.0:8888 --truncate=2 - answer reads with 2 objects at most
+
+    RUST_LOG=debug slave-rnd --bytes-log=compact - log one line per frame
     "#
-        );
-        None
-    } else {
-        let mut settings = Settings::default();
-        if !arg.is_empty() {
-            settings.address = TransportAddress::from_str(&arg).unwrap();
+    );
+}
+
+fn read_args() -> Option<(Settings, Option<usize>)> {
+    let arg: String = env::args()
+        .skip(1)
+        .find(|arg| !arg.starts_with("--truncate=") && !arg.starts_with("--bytes-log="))
+        .unwrap_or_default();
+
+    if arg == "--help" || arg == "-h" {
+        usage();
+        return None;
+    }
+
+    let truncate = match truncate() {
+        Ok(truncate) => truncate,
+        Err(err) => {
+            eprintln!("{}\n", err);
+            usage();
+            return None;
         }
-        Some(settings)
+    };
+
+    let mut settings = Settings {
+        bytes_log: bytes_log(),
+        ..Default::default()
+    };
+    if !arg.is_empty() {
+        settings.address = TransportAddress::from_str(&arg).unwrap();
     }
+    Some((settings, truncate))
+}
+
+// None if it's another argument. Responses can't be empty, so 0 is invalid
+fn parse_truncate(arg: &str) -> Option<Result<usize, String>> {
+    let value = arg.strip_prefix("--truncate=")?;
+    let max = NonZeroUsize::from_str(value)
+        .map(NonZeroUsize::get)
+        .map_err(|_| format!("invalid --truncate value: {}", value));
+    Some(max)
+}
+
+fn truncate() -> Result<Option<usize>, String> {
+    env::args()
+        .skip(1)
+        .find_map(|arg| parse_truncate(&arg))
+        .transpose()
 }
 
 async fn wait_ctrl_c() {
@@ -141,13 +256,64 @@ fn init_logger() {
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    if let Some(settings) = read_args() {
+    if let Some((settings, truncate)) = read_args() {
         init_logger();
-        builder::build_slave(settings, |request| {
-            let _ = make_answer(request).send().map_err(|e| warn!("{:?}", e));
+        let address = settings.address.get().to_owned();
+        builder::build_slave(settings, move |request| {
+            let _ = make_answer(request, truncate)
+                .send()
+                .map_err(|e| warn!("{:?}", e));
         })
-        .await?;
+        .await
+        .inspect_err(|err| error!("can't start {}: {}", address, err))?;
         wait_ctrl_c().await;
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use uuid::Uuid;
+
+    fn request(pdu: RequestPdu) -> Request {
+        Request {
+            uuid: Uuid::new_v4(),
+            slave: 1,
+            pdu,
+            deadline: None,
+            response_tx: None,
+        }
+    }
+
+    #[test]
+    fn truncated_reads() {
+        let answer = make_answer(request(RequestPdu::read_discrete_inputs(0x0, 10)), Some(3));
+        assert!(matches!(
+            answer.pdu,
+            ResponsePdu::ReadDiscreteInputs { nobjs: 3, .. }
+        ));
+        let answer = make_answer(
+            request(RequestPdu::read_holding_registers(0x0, 10)),
+            Some(3),
+        );
+        assert_eq!(answer.pdu.len(), 1 + 1 + 3 * 2);
+
+        // less than the limit is returned as is
+        let answer = make_answer(request(RequestPdu::read_coils(0x0, 2)), Some(3));
+        assert!(matches!(
+            answer.pdu,
+            ResponsePdu::ReadCoils { nobjs: 2, .. }
+        ));
+        let answer = make_answer(request(RequestPdu::read_input_registers(0x0, 10)), None);
+        assert_eq!(answer.pdu.len(), 1 + 1 + 10 * 2);
+    }
+
+    #[test]
+    fn truncate_arg() {
+        assert_eq!(parse_truncate("--truncate=3"), Some(Ok(3)));
+        assert!(matches!(parse_truncate("--truncate=0"), Some(Err(_))));
+        assert!(matches!(parse_truncate("--truncate=x"), Some(Err(_))));
+        assert_eq!(parse_truncate("tcp:0.0.0.0:502"), None);
+    }
+}